@@ -0,0 +1,40 @@
+use image::{ImageFormat, Rgb, RgbImage};
+use std::io::Cursor;
+use svdimagecompress::SvdBackend;
+use svdimagecompress::bench;
+
+fn main() {
+    // A smooth gradient with some texture, standing in for a typical photo
+    let img = RgbImage::from_fn(1024, 768, |x, y| {
+        let t = ((x as f32 / 37.0).sin() * (y as f32 / 23.0).cos() * 40.0) as i32;
+        Rgb([(x / 4) as u8, (y / 3) as u8, (128 + t).clamp(0, 255) as u8])
+    });
+
+    let mut png = Cursor::new(Vec::new());
+    img.write_to(&mut png, ImageFormat::Png).unwrap();
+
+    let report = bench::run(png.get_ref(), &[5, 20, 50, 100], &[SvdBackend::Faer]).unwrap();
+
+    println!(
+        "decode: {:?}, to planes: {:?}, from planes: {:?}",
+        report.decode, report.to_planes, report.from_planes
+    );
+    for t in report.timings {
+        println!(
+            "{:?} rank {:>3}: svd {:?}, reconstruct {:?}, encode {:?} ({} bytes)",
+            t.backend, t.rank, t.svd, t.reconstruct, t.encode, t.encoded_bytes
+        );
+    }
+
+    for rank in [20, 100] {
+        let c = bench::compare_baselines(png.get_ref(), rank).unwrap();
+        let jpeg = match &c.jpeg {
+            Some(jpeg) => format!("{} bytes ({:?})", jpeg.bytes, jpeg.encoding),
+            None => "unreachable".to_string(),
+        };
+        println!(
+            "rank {:>3} at {:.1} dB: factors {} bytes, png {} bytes, jpeg {}, lossless webp {} bytes",
+            c.rank, c.psnr, c.factor_bytes, c.png_bytes, jpeg, c.webp_lossless.bytes
+        );
+    }
+}
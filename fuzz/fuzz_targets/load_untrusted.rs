@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::time::Duration;
+use svdimagecompress::{UntrustedLimits, load_untrusted};
+
+// Run with `cargo fuzz run load_untrusted` from the repository root. Any panic that escapes
+// `load_untrusted`, rather than coming back as an error, is a bug.
+fuzz_target!(|data: &[u8]| {
+    let limits = UntrustedLimits {
+        max_width: 4096,
+        max_height: 4096,
+        max_alloc: 64 << 20,
+        timeout: Duration::from_secs(5),
+        ..UntrustedLimits::default()
+    };
+    let _ = load_untrusted(data, &limits);
+});
@@ -0,0 +1,283 @@
+use crate::imagewrapper::Planes;
+use faer_core::Mat;
+use image::codecs::webp::WebPEncoder;
+use image::{ExtendedColorType, ImageError};
+use std::io::{self, Write};
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum AnimationError {
+    Io(io::Error),
+    Png(png::EncodingError),
+    Image(ImageError),
+    NoFrames,
+    // Every frame must have the size of the first
+    FrameSize((usize, usize), (usize, usize)),
+    Unsupported(String),
+}
+
+impl std::fmt::Display for AnimationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AnimationError::Io(err) => write!(f, "I/O error: {}", err),
+            AnimationError::Png(err) => write!(f, "PNG encoding error: {}", err),
+            AnimationError::Image(err) => write!(f, "Image error: {}", err),
+            AnimationError::NoFrames => write!(f, "An animation needs at least one frame."),
+            AnimationError::FrameSize((w1, h1), (w2, h2)) => {
+                write!(f, "Frames must be {}x{}, got {}x{}.", w1, h1, w2, h2)
+            }
+            AnimationError::Unsupported(msg) => write!(f, "Unsupported input: {}.", msg),
+        }
+    }
+}
+
+impl From<io::Error> for AnimationError {
+    fn from(err: io::Error) -> Self {
+        AnimationError::Io(err)
+    }
+}
+
+impl From<png::EncodingError> for AnimationError {
+    fn from(err: png::EncodingError) -> Self {
+        AnimationError::Png(err)
+    }
+}
+
+impl From<ImageError> for AnimationError {
+    fn from(err: ImageError) -> Self {
+        AnimationError::Image(err)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnimationOptions {
+    // How long each frame shows, in order; the last delay carries over to any frames beyond
+    // it, so a single entry sets a constant frame rate
+    pub delays: Vec<Duration>,
+    // Times the animation plays, 0 meaning forever
+    pub loops: u32,
+}
+
+impl AnimationOptions {
+    pub fn new(delay: Duration) -> Self {
+        AnimationOptions {
+            delays: vec![delay],
+            loops: 0,
+        }
+    }
+
+    fn delay(&self, frame: usize) -> Duration {
+        self.delays
+            .get(frame)
+            .or(self.delays.last())
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+impl Default for AnimationOptions {
+    fn default() -> Self {
+        AnimationOptions::new(Duration::from_millis(100))
+    }
+}
+
+// Row-major interleaved 8-bit samples of `planes`, clamped and truncated as on save
+fn interleave(planes: &[Mat<f32>]) -> Vec<u8> {
+    let (m, n) = (planes[0].nrows(), planes[0].ncols());
+    let mut buf = Vec::with_capacity(m * n * planes.len());
+    for i in 0..m {
+        for j in 0..n {
+            buf.extend(
+                planes
+                    .iter()
+                    .map(|mat| mat.read(i, j).clamp(0.0, 255.0) as u8),
+            );
+        }
+    }
+    buf
+}
+
+// Width and height shared by all frames, which neither format allows to be 0
+fn frame_size<W: Planes>(frames: &[W]) -> Result<(usize, usize), AnimationError> {
+    let size = |frame: &W| {
+        frame
+            .planes()
+            .first()
+            .map_or((0, 0), |mat| (mat.ncols(), mat.nrows()))
+    };
+    let first = size(frames.first().ok_or(AnimationError::NoFrames)?);
+    if first.0 == 0 || first.1 == 0 {
+        return Err(AnimationError::Unsupported(format!(
+            "empty {}x{} frames",
+            first.0, first.1
+        )));
+    }
+    match frames.iter().map(size).find(|&other| other != first) {
+        Some(other) => Err(AnimationError::FrameSize(first, other)),
+        None => Ok(first),
+    }
+}
+
+// Writes `frames`, such as a rank sweep or compressed video frames, as an animated PNG. Each
+// frame replaces the previous one entirely. Delays are stored in milliseconds, up to about a
+// minute per frame.
+pub fn save_apng<W: Planes, O: Write>(
+    writer: O,
+    frames: &[W],
+    options: &AnimationOptions,
+) -> Result<(), AnimationError> {
+    let (width, height) = frame_size(frames)?;
+    let color = match frames[0].planes().len() {
+        1 => png::ColorType::Grayscale,
+        2 => png::ColorType::GrayscaleAlpha,
+        3 => png::ColorType::Rgb,
+        4 => png::ColorType::Rgba,
+        channels => {
+            return Err(AnimationError::Unsupported(format!(
+                "{} channels",
+                channels
+            )));
+        }
+    };
+
+    let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
+    encoder.set_color(color);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(frames.len() as u32, options.loops)?;
+    let mut writer = encoder.write_header()?;
+    for (k, frame) in frames.iter().enumerate() {
+        let ms = options.delay(k).as_millis().min(u16::MAX as u128) as u16;
+        writer.set_frame_delay(ms, 1000)?;
+        writer.write_image_data(&interleave(frame.planes()))?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+// The VP8L bitstream of a lossless WebP frame, without its RIFF and chunk headers
+fn vp8l_frame(planes: &[Mat<f32>], width: usize, height: usize) -> Result<Vec<u8>, AnimationError> {
+    let color = match planes.len() {
+        1 => ExtendedColorType::L8,
+        2 => ExtendedColorType::La8,
+        3 => ExtendedColorType::Rgb8,
+        4 => ExtendedColorType::Rgba8,
+        channels => {
+            return Err(AnimationError::Unsupported(format!(
+                "{} channels",
+                channels
+            )));
+        }
+    };
+    let mut file = Vec::new();
+    WebPEncoder::new_lossless(&mut file).encode(
+        &interleave(planes),
+        width as u32,
+        height as u32,
+        color,
+    )?;
+
+    // The encoder writes a simple file: the RIFF header, then a single VP8L chunk
+    match (file.get(12..16), file.get(16..20)) {
+        (Some(b"VP8L"), Some(size)) => {
+            let size = u32::from_le_bytes(size.try_into().unwrap()) as usize;
+            Ok(file[20..20 + size].to_vec())
+        }
+        _ => Err(AnimationError::Unsupported(
+            "unexpected WebP encoder output".to_string(),
+        )),
+    }
+}
+
+fn chunk(out: &mut Vec<u8>, fourcc: &[u8; 4], payload: &[u8]) {
+    out.extend_from_slice(fourcc);
+    out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    out.extend_from_slice(payload);
+    // Chunks are padded to an even length
+    if payload.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+fn u24(out: &mut Vec<u8>, value: usize) {
+    out.extend_from_slice(&(value as u32).to_le_bytes()[..3]);
+}
+
+// Writes `frames` as an animated lossless WebP, assembled from the lossless frames the `image`
+// encoder makes, which only writes still images. Delays are stored in milliseconds, up to
+// about four and a half hours per frame.
+pub fn save_webp_animation<W: Planes, O: Write>(
+    mut writer: O,
+    frames: &[W],
+    options: &AnimationOptions,
+) -> Result<(), AnimationError> {
+    let (width, height) = frame_size(frames)?;
+    if width > 1 << 14 || height > 1 << 14 {
+        return Err(AnimationError::Unsupported(format!(
+            "{}x{} exceeds the WebP limit of 16384x16384",
+            width, height
+        )));
+    }
+    let alpha = frames[0].alpha().is_some();
+
+    let mut body = b"WEBP".to_vec();
+    // Animation flag, plus alpha if the frames have it
+    let mut header = vec![if alpha { 0x12 } else { 0x02 }, 0, 0, 0];
+    u24(&mut header, width - 1);
+    u24(&mut header, height - 1);
+    chunk(&mut body, b"VP8X", &header);
+
+    // Transparent black background, and the loop count
+    let mut anim = vec![0; 4];
+    anim.extend_from_slice(&(options.loops.min(u16::MAX as u32) as u16).to_le_bytes());
+    chunk(&mut body, b"ANIM", &anim);
+
+    for (k, frame) in frames.iter().enumerate() {
+        let mut anmf = Vec::new();
+        // Offsets of zero, then the frame size
+        u24(&mut anmf, 0);
+        u24(&mut anmf, 0);
+        u24(&mut anmf, width - 1);
+        u24(&mut anmf, height - 1);
+        let ms = options.delay(k).as_millis().min((1 << 24) - 1) as usize;
+        u24(&mut anmf, ms);
+        // Don't blend with the previous frame, and don't dispose of it either
+        anmf.push(0x02);
+        chunk(
+            &mut anmf,
+            b"VP8L",
+            &vp8l_frame(frame.planes(), width, height)?,
+        );
+        chunk(&mut body, b"ANMF", &anmf);
+    }
+
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(body.len() as u32).to_le_bytes())?;
+    writer.write_all(&body)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutils::checkerboard;
+
+    #[test]
+    fn rejects_empty_frames() {
+        let options = AnimationOptions::default();
+        let empty = [checkerboard(0, 0, 1)];
+        assert!(matches!(
+            save_webp_animation(Vec::new(), &empty, &options),
+            Err(AnimationError::Unsupported(_))
+        ));
+        assert!(matches!(
+            save_apng(Vec::new(), &empty, &options),
+            Err(AnimationError::Unsupported(_))
+        ));
+
+        let frames = [checkerboard(8, 8, 2), checkerboard(8, 8, 4)];
+        let mut buf = Vec::new();
+        save_webp_animation(&mut buf, &frames, &options).unwrap();
+        assert_eq!(&buf[8..12], b"WEBP");
+    }
+}
@@ -0,0 +1,28 @@
+use crate::compress::product;
+use faer_core::{Mat, MatRef};
+
+// The first `count` DCT-II basis vectors of length `len` as orthonormal columns, from the
+// constant up to the `count - 1`th cosine
+fn cosine_basis(len: usize, count: usize) -> Mat<f32> {
+    Mat::from_fn(len, count, |i, k| {
+        let scale = if k == 0 { 1.0 } else { 2.0 };
+        let angle = std::f64::consts::PI * (i as f64 + 0.5) * k as f64 / len as f64;
+        ((scale / len as f64).sqrt() * angle.cos()) as f32
+    })
+}
+
+// Regresses every column of `mat` onto the `rows` smoothest vertical cosines and every row onto
+// the `cols` smoothest horizontal ones: `B_r (B_r^T A B_c) B_c^T`, a two-sided factorization
+// storing an `rows x cols` core. Unlike a rank, the two budgets limit detail in each direction
+// separately, so stripes and text lines keep their sharp edges across while losing little
+// along. Costs `O(m n (rows + cols))`.
+pub(crate) fn anisotropic_approx(mat: MatRef<f32>, rows: usize, cols: usize) -> Mat<f32> {
+    let left = cosine_basis(mat.nrows(), rows);
+    let right = cosine_basis(mat.ncols(), cols);
+
+    let core = product(product(left.transpose(), mat).as_ref(), right.as_ref());
+    product(
+        product(left.as_ref(), core.as_ref()).as_ref(),
+        right.transpose(),
+    )
+}
@@ -0,0 +1,185 @@
+use crate::font::{GLYPH_HEIGHT, draw_text, text_width};
+use crate::imagewrapper::Planes;
+use crate::metrics::Metrics;
+use crate::pipeline::{Stage, StageError};
+use faer_core::Mat;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+// Text burned into a corner of an image, for demo and comparison outputs: one line for each of
+// the rank, the PSNR, the crate version and the free text that are set
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Annotation {
+    pub rank: Option<usize>,
+    pub psnr: Option<f32>,
+    pub version: bool,
+    pub text: Option<String>,
+    pub corner: Corner,
+    // Size of each font pixel, in image pixels
+    pub scale: usize,
+}
+
+impl Default for Annotation {
+    fn default() -> Self {
+        Annotation {
+            rank: None,
+            psnr: None,
+            version: true,
+            text: None,
+            corner: Corner::default(),
+            scale: 2,
+        }
+    }
+}
+
+impl Annotation {
+    // The rank of `compressed` and its PSNR against `original`
+    pub fn compared<W: Metrics>(original: &W, compressed: &W, rank: usize) -> Self {
+        Annotation {
+            rank: Some(rank),
+            psnr: Some(original.psnr(compressed)),
+            ..Annotation::default()
+        }
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Some(rank) = self.rank {
+            lines.push(format!("RANK {}", rank));
+        }
+        if let Some(psnr) = self.psnr {
+            lines.push(if psnr.is_finite() {
+                format!("PSNR {:.2} DB", psnr)
+            } else {
+                "PSNR EXACT".to_string()
+            });
+        }
+        if self.version {
+            lines.push(format!(
+                "{} {}",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION")
+            ));
+        }
+        lines.extend(
+            self.text
+                .iter()
+                .flat_map(|text| text.lines().map(String::from)),
+        );
+        lines
+    }
+}
+
+pub trait Annotate: Planes {
+    // Draws the lines of `annotation` on a solid box in its corner, scaled down to fit or else
+    // clipped to the image. The text takes the brightest color sample of the image and the box
+    // the darkest, so it stays legible whatever the range of the planes; alpha is made opaque
+    // under the box.
+    fn annotate(&self, annotation: &Annotation) -> Self
+    where
+        Self: Sized,
+    {
+        let lines = annotation.lines();
+        let planes = self.planes();
+        let (m, n) = (planes[0].nrows(), planes[0].ncols());
+        if lines.is_empty() || m == 0 || n == 0 {
+            return self.rebuild(planes.to_vec());
+        }
+
+        let alpha = self.alpha();
+        let (mut lo, mut hi) = (f32::INFINITY, f32::NEG_INFINITY);
+        for (k, mat) in planes.iter().enumerate() {
+            if Some(k) == alpha {
+                continue;
+            }
+            for j in 0..n {
+                for i in 0..m {
+                    lo = lo.min(mat.read(i, j));
+                    hi = hi.max(mat.read(i, j));
+                }
+            }
+        }
+        // Flat images get white on black over 0-255
+        if hi <= lo {
+            (lo, hi) = (0.0, 255.0);
+        }
+
+        // Width and height of the box at `scale`, with room for the margin around it
+        let size = |scale: usize| {
+            let text = lines.iter().map(|text| text_width(text, scale)).max();
+            let width = text.unwrap_or(0) + 4 * scale;
+            let height = lines.len() * (GLYPH_HEIGHT + 2) * scale + 2 * scale;
+            (width, height)
+        };
+        // Small images get smaller text rather than a clipped box, down to a scale of 1
+        let scale = (1..=annotation.scale.max(1))
+            .rev()
+            .find(|&scale| {
+                let (width, height) = size(scale);
+                width + 4 * scale <= n && height + 4 * scale <= m
+            })
+            .unwrap_or(1);
+        let (width, height) = size(scale);
+        let pad = 2 * scale;
+        let line = (GLYPH_HEIGHT + 2) * scale;
+        let margin = 2 * scale;
+        let x0 = match annotation.corner {
+            Corner::TopLeft | Corner::BottomLeft => margin,
+            Corner::TopRight | Corner::BottomRight => n.saturating_sub(width + margin),
+        };
+        let y0 = match annotation.corner {
+            Corner::TopLeft | Corner::TopRight => margin,
+            Corner::BottomLeft | Corner::BottomRight => m.saturating_sub(height + margin),
+        };
+
+        // 0 outside the box, 1 for its background and 2 for text
+        let mut mask = vec![0u8; m * n];
+        for y in y0..(y0 + height).min(m) {
+            for x in x0..(x0 + width).min(n) {
+                mask[y * n + x] = 1;
+            }
+        }
+        for (k, text) in lines.iter().enumerate() {
+            draw_text(text, x0 + pad, y0 + pad + k * line, scale, |x, y| {
+                if x < n && y < m {
+                    mask[y * n + x] = 2;
+                }
+            });
+        }
+
+        let mats = planes
+            .iter()
+            .enumerate()
+            .map(|(k, mat)| {
+                Mat::from_fn(m, n, |i, j| match mask[i * n + j] {
+                    0 => mat.read(i, j),
+                    _ if Some(k) == alpha => 255.0,
+                    1 => lo,
+                    _ => hi,
+                })
+            })
+            .collect();
+        self.rebuild(mats)
+    }
+}
+
+impl<W: Planes> Annotate for W {}
+
+impl<W: Planes> Stage<W> for Annotation {
+    fn name(&self) -> String {
+        "annotate".to_string()
+    }
+
+    fn apply(&self, wrapper: W) -> Result<W, StageError> {
+        Ok(wrapper.annotate(self))
+    }
+}
@@ -0,0 +1,147 @@
+use crate::compress::{SvdApproxError, is_accurate_parts};
+use crate::instrument::span;
+use faer_core::dyn_stack::{PodStack, StackReq};
+use faer_core::mul::matmul;
+use faer_core::reborrow::ReborrowMut;
+use faer_core::{Mat, MatMut, Parallelism, temp_mat_req, temp_mat_uninit};
+use faer_svd::{ComputeVectors, SvdParams, compute_svd, compute_svd_req};
+
+// Layout of the arena for an `m x n` plane at `rank`: U, S and V stay live throughout, while
+// the SVD workspace is reused for the scaled U once the decomposition is done
+fn plane_req(m: usize, n: usize, rank: usize) -> Result<StackReq, SvdApproxError> {
+    let k = m.min(n);
+    let failed = |_| SvdApproxError::ComputeReqFailed;
+    let svd = compute_svd_req::<f32>(
+        m,
+        n,
+        ComputeVectors::Thin,
+        ComputeVectors::Thin,
+        Parallelism::None,
+        SvdParams::default(),
+    )
+    .map_err(failed)?;
+
+    StackReq::try_all_of([
+        temp_mat_req::<f32>(m, k).map_err(failed)?,
+        temp_mat_req::<f32>(k, 1).map_err(failed)?,
+        temp_mat_req::<f32>(n, k).map_err(failed)?,
+        StackReq::try_any_of([svd, temp_mat_req::<f32>(m, rank).map_err(failed)?])
+            .map_err(failed)?,
+    ])
+    .map_err(failed)
+}
+
+// Bytes of arena `Compressible::compress_in_arena` needs for planes of `width x height` at
+// `rank`. Planes are compressed one after another, so this doesn't grow with the channel count.
+pub fn arena_size(width: usize, height: usize, rank: usize) -> Result<usize, SvdApproxError> {
+    let k = width.min(height);
+    if rank > k {
+        return Err(SvdApproxError::InvalidRank(k, rank));
+    }
+    Ok(plane_req(height, width, rank)?.size_bytes())
+}
+
+// Compresses `mat` in place with every temporary matrix carved out of `arena`. Only the faer
+// backend runs here, since the Jacobi fallback needs heap memory of its own, so an SVD that
+// fails the accuracy check is an error and `mat` is left untouched.
+fn compress_plane(
+    mut mat: MatMut<f32>,
+    rank: usize,
+    bad: bool,
+    arena: &mut [u8],
+) -> Result<(), SvdApproxError> {
+    let (m, n) = (mat.nrows(), mat.ncols());
+    let k = m.min(n);
+    if rank == 0 {
+        let mut sum = 0.0;
+        for j in 0..n {
+            for i in 0..m {
+                sum += mat.read(i, j) as f64;
+            }
+        }
+        mat.fill((sum / (m * n).max(1) as f64) as f32);
+        return Ok(());
+    }
+    if rank == k {
+        return Ok(());
+    }
+
+    let required = plane_req(m, n, rank)?.size_bytes();
+    if arena.len() < required {
+        return Err(SvdApproxError::ArenaTooSmall(required, arena.len()));
+    }
+
+    let stack = PodStack::new(arena);
+    let (mut u, stack) = temp_mat_uninit::<f32>(m, k, stack);
+    let (mut s, stack) = temp_mat_uninit::<f32>(k, 1, stack);
+    let (mut v, mut stack) = temp_mat_uninit::<f32>(n, k, stack);
+
+    let svd_span = span("svd");
+    // Singular values come out in descending order
+    compute_svd(
+        mat.as_ref(),
+        s.as_mut(),
+        Some(u.as_mut()),
+        Some(v.as_mut()),
+        Parallelism::None,
+        stack.rb_mut(),
+        SvdParams::default(),
+    );
+    svd_span.finish(m, n);
+    if !is_accurate_parts(mat.as_ref(), u.as_ref(), s.as_ref(), v.as_ref()) {
+        return Err(SvdApproxError::InaccurateSvd);
+    }
+
+    let reconstruct_span = span("reconstruct");
+    let start = if bad { k - rank } else { 0 };
+    let (mut us, _) = temp_mat_uninit::<f32>(m, rank, stack);
+    for j in 0..rank {
+        let sj = s.read(start + j, 0);
+        for i in 0..m {
+            us.write(i, j, u.read(i, start + j) * sj);
+        }
+    }
+    matmul(
+        mat.as_mut(),
+        us.as_ref(),
+        v.as_ref().subcols(start, rank).transpose(),
+        None,
+        1.0,
+        Parallelism::None,
+    );
+    reconstruct_span.finish(m, n);
+
+    Ok(())
+}
+
+pub(crate) fn svdapprox_in_arena(
+    mats: &mut [Mat<f32>],
+    rank: usize,
+    bad: bool,
+    arena: &mut [u8],
+) -> Result<(), SvdApproxError> {
+    if let Some(mat) = mats.first() {
+        let k = mat.nrows().min(mat.ncols());
+        if rank > k {
+            return Err(SvdApproxError::InvalidRank(k, rank));
+        }
+    }
+    mats.iter_mut()
+        .try_for_each(|mat| compress_plane(mat.as_mut(), rank, bad, arena))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Compressible;
+    use crate::testutils::{assert_images_close, low_rank};
+
+    #[test]
+    fn arena_matches_the_heap() {
+        let img = low_rank(50, 40, &[300.0, 100.0, 30.0, 3.0], 4);
+        let mut arena = vec![0; arena_size(50, 40, 3).unwrap()];
+        let mut compressed = img.clone();
+        compressed.compress_in_arena(3, &mut arena).unwrap();
+        assert_images_close(&compressed, &img.compress(3).unwrap(), 1e-2);
+    }
+}
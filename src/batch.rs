@@ -0,0 +1,640 @@
+use crate::compress::{
+    ChannelError, CompressOptions, Compressible, SvdApproxError, SvdBackend, svd,
+};
+use crate::dynwrapper::DynWrapper;
+use crate::geometry::Geometry;
+use crate::imagewrapper::ImageWrapper;
+use crate::instrument::{Timings, timed};
+use crate::metrics::Metrics;
+use faer_core::Mat;
+use image::imageops::{self, FilterType};
+use image::{ImageError, ImageFormat, ImageReader};
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+pub enum BatchError {
+    Io(io::Error),
+    Image(ImageError),
+    Svd(SvdApproxError),
+}
+
+impl std::fmt::Display for BatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BatchError::Io(err) => write!(f, "I/O error: {}", err),
+            BatchError::Image(err) => write!(f, "Image error: {}", err),
+            BatchError::Svd(err) => write!(f, "SVD error: {}", err),
+        }
+    }
+}
+
+impl From<io::Error> for BatchError {
+    fn from(err: io::Error) -> Self {
+        BatchError::Io(err)
+    }
+}
+
+impl From<ImageError> for BatchError {
+    fn from(err: ImageError) -> Self {
+        BatchError::Image(err)
+    }
+}
+
+impl From<SvdApproxError> for BatchError {
+    fn from(err: SvdApproxError) -> Self {
+        BatchError::Svd(err)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BatchJob {
+    pub input: PathBuf,
+    pub output: PathBuf,
+}
+
+impl BatchJob {
+    // Writes `<dir>/<input stem>.<ext>`, keeping the input's extension unless `format` is given
+    pub fn into_dir(
+        input: impl AsRef<Path>,
+        dir: impl AsRef<Path>,
+        format: Option<ImageFormat>,
+    ) -> Self {
+        let input = input.as_ref().to_path_buf();
+        let mut output = dir.as_ref().join(input.file_name().unwrap_or_default());
+        if let Some(ext) = format.and_then(|format| format.extensions_str().first()) {
+            output.set_extension(ext);
+        }
+
+        BatchJob { input, output }
+    }
+}
+
+// Side of the grey thumbnail a fingerprint is taken from, and the rank kept of it
+const FINGERPRINT_DIM: u32 = 32;
+const FINGERPRINT_RANK: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DedupAction {
+    // Write nothing for a duplicate
+    Skip,
+    // Hard-link a duplicate's output to the original's, or compress it anyway if their output
+    // formats differ
+    Link,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dedup {
+    // Largest fingerprint distance, relative to the fingerprints' norms, at which two inputs
+    // count as duplicates; 0.02 catches re-encodes and resizes of the same photo
+    pub threshold: f32,
+    pub action: DedupAction,
+}
+
+impl Dedup {
+    pub fn new(action: DedupAction) -> Self {
+        Dedup {
+            threshold: 0.02,
+            action,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BatchOptions {
+    pub compress: CompressOptions,
+    // Output format; `None` infers it from each output's extension
+    pub format: Option<ImageFormat>,
+    // Number of files in flight at once; 0 uses one worker per core
+    pub jobs: usize,
+    // Append-only record of finished jobs; jobs listed there whose output still exists are
+    // skipped, so an interrupted run can be restarted with the same options
+    pub manifest: Option<PathBuf>,
+    // Writes files whose compression failed for some channels anyway, with those channels
+    // uncompressed (see `Compressible::compress_salvaged`), rather than counting them as failed
+    pub salvage: bool,
+    // Fingerprints every input first and deduplicates near-identical ones against the first
+    // job they resemble
+    pub dedup: Option<Dedup>,
+    // Reports the time each file spent in each stage
+    pub timings: bool,
+}
+
+#[derive(Debug)]
+pub struct FileReport {
+    pub input_bytes: u64,
+    pub output_bytes: u64,
+    pub elapsed: Duration,
+    // Skipped because the manifest already lists the job as done
+    pub resumed: bool,
+    // Channels left uncompressed when salvaging
+    pub failed_channels: Vec<ChannelError>,
+    // Input of the job this one duplicated, when deduplicating skipped or linked it
+    pub duplicate_of: Option<PathBuf>,
+    pub rank: usize,
+    // Mean squared error of the compressed image against the (resized) input; `None` when
+    // nothing was compressed, as for resumed jobs and deduplicated ones
+    pub mse: Option<f32>,
+    // When `BatchOptions::timings` is set and the file was compressed
+    pub timings: Option<Timings>,
+}
+
+#[derive(Debug)]
+pub struct BatchResult {
+    pub job: BatchJob,
+    pub outcome: Result<FileReport, BatchError>,
+}
+
+fn process(job: &BatchJob, options: &BatchOptions) -> Result<FileReport, BatchError> {
+    if !options.timings {
+        return compress_file(job, options);
+    }
+    let (report, timings) = timed(|| compress_file(job, options));
+    Ok(FileReport {
+        timings: Some(timings),
+        ..report?
+    })
+}
+
+fn compress_file(job: &BatchJob, options: &BatchOptions) -> Result<FileReport, BatchError> {
+    let start = Instant::now();
+    let format = match options.format {
+        Some(format) => format,
+        None => ImageFormat::from_path(&job.output)?,
+    };
+
+    let file = File::open(&job.input)?;
+    let input_bytes = file.metadata()?.len();
+    let wrapper = DynWrapper::load(BufReader::new(file))?;
+    let (compressed, failed_channels) = if options.salvage {
+        let salvaged = wrapper.compress_salvaged(&options.compress)?;
+        (salvaged.wrapper, salvaged.failures)
+    } else {
+        (wrapper.compress_with(&options.compress)?, Vec::new())
+    };
+    let mut writer = BufWriter::new(File::create(&job.output)?);
+    compressed.save(&mut writer, format)?;
+    writer.flush()?;
+
+    let reference = options
+        .compress
+        .resize
+        .map(|resize| wrapper.resize(resize.width, resize.height, resize.filter));
+    let mse = reference.as_ref().unwrap_or(&wrapper).mse(&compressed);
+
+    Ok(FileReport {
+        input_bytes,
+        output_bytes: std::fs::metadata(&job.output)?.len(),
+        elapsed: start.elapsed(),
+        resumed: false,
+        failed_channels,
+        duplicate_of: None,
+        rank: options.compress.rank,
+        mse: Some(mse),
+        timings: None,
+    })
+}
+
+// One `<input>\t<output>` line per finished job, written as soon as the job is, so that the
+// manifest never lists an output that wasn't fully written
+struct Manifest {
+    done: HashSet<(PathBuf, PathBuf)>,
+    file: Mutex<File>,
+}
+
+impl Manifest {
+    fn open(path: &Path) -> io::Result<Self> {
+        let done = match std::fs::read_to_string(path) {
+            Ok(text) => text
+                .lines()
+                .filter_map(|line| line.split_once('\t'))
+                .map(|(input, output)| (PathBuf::from(input), PathBuf::from(output)))
+                .collect(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => HashSet::new(),
+            Err(err) => return Err(err),
+        };
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(Manifest {
+            done,
+            file: Mutex::new(file),
+        })
+    }
+
+    fn is_done(&self, job: &BatchJob) -> bool {
+        self.done.contains(&(job.input.clone(), job.output.clone())) && job.output.exists()
+    }
+
+    fn record(&self, job: &BatchJob) -> io::Result<()> {
+        let mut file = self.file.lock().unwrap();
+        writeln!(file, "{}\t{}", job.input.display(), job.output.display())
+    }
+}
+
+fn resume(job: &BatchJob, options: &BatchOptions) -> Result<FileReport, BatchError> {
+    Ok(FileReport {
+        input_bytes: std::fs::metadata(&job.input).map_or(0, |meta| meta.len()),
+        output_bytes: std::fs::metadata(&job.output)?.len(),
+        elapsed: Duration::ZERO,
+        resumed: true,
+        failed_channels: Vec::new(),
+        duplicate_of: None,
+        rank: options.compress.rank,
+        mse: None,
+        timings: None,
+    })
+}
+
+// Rank-`FINGERPRINT_RANK` approximation of a small grey thumbnail: the dominant structure of
+// the image, unaffected by noise, recompression artifacts or the original resolution
+struct Fingerprint {
+    aspect: f32,
+    mat: Mat<f32>,
+    norm: f32,
+}
+
+impl Fingerprint {
+    fn of(path: &Path) -> Result<Self, BatchError> {
+        let img = ImageReader::open(path)?.with_guessed_format()?.decode()?;
+        let aspect = img.width() as f32 / img.height().max(1) as f32;
+        let thumb = imageops::resize(
+            &img.into_luma8(),
+            FINGERPRINT_DIM,
+            FINGERPRINT_DIM,
+            FilterType::Triangle,
+        );
+
+        let dim = FINGERPRINT_DIM as usize;
+        let mat = Mat::from_fn(dim, dim, |i, j| {
+            thumb.get_pixel(j as u32, i as u32)[0] as f32
+        });
+        let mat = svd(mat.as_ref(), SvdBackend::default())?
+            .truncate(FINGERPRINT_RANK, false)?
+            .reconstruct();
+        let norm = mat.norm_l2();
+
+        Ok(Fingerprint { aspect, mat, norm })
+    }
+
+    // Images of different shapes are never duplicates, however similar their thumbnails
+    fn distance(&self, other: &Fingerprint) -> f32 {
+        if (self.aspect - other.aspect).abs() > 0.01 * self.aspect.max(other.aspect) {
+            return f32::INFINITY;
+        }
+        (&self.mat - &other.mat).norm_l2() / self.norm.max(other.norm).max(f32::EPSILON)
+    }
+}
+
+// For each job, the index of the earliest job it duplicates. Only inputs that are not
+// duplicates themselves serve as originals. Since `|a| - |b| <= |a - b|`, only originals whose
+// norms lie within a factor `1 - threshold` of each other can match, so those are kept sorted
+// by norm and just that window is searched.
+fn find_duplicates(jobs: &[BatchJob], threshold: f32) -> Vec<Option<usize>> {
+    let fingerprints: Vec<Option<Fingerprint>> = jobs
+        .par_iter()
+        .map(|job| Fingerprint::of(&job.input).ok())
+        .collect();
+
+    let mut originals: Vec<(f32, usize)> = Vec::new();
+    let scale = (1.0 - threshold).max(f32::EPSILON);
+
+    fingerprints
+        .iter()
+        .enumerate()
+        .map(|(i, fingerprint)| {
+            // Inputs that fail to decode are left for `process` to report
+            let fingerprint = fingerprint.as_ref()?;
+            let norm = fingerprint.norm;
+            let lo = originals.partition_point(|&(n, _)| n < norm * scale);
+            let hi = originals.partition_point(|&(n, _)| n <= norm / scale);
+
+            let original = originals[lo..hi]
+                .iter()
+                .map(|&(_, j)| j)
+                .filter(|&j| {
+                    let other = fingerprints[j].as_ref().unwrap();
+                    fingerprint.distance(other) <= threshold
+                })
+                .min();
+
+            if original.is_none() {
+                let at = originals.partition_point(|&(n, _)| n < norm);
+                originals.insert(at, (norm, i));
+            }
+            original
+        })
+        .collect()
+}
+
+fn output_format(job: &BatchJob, options: &BatchOptions) -> Option<ImageFormat> {
+    options
+        .format
+        .or_else(|| ImageFormat::from_path(&job.output).ok())
+}
+
+// Handles a duplicate of `original`, whose output has already been written
+fn deduplicate(
+    job: &BatchJob,
+    original: &BatchJob,
+    options: &BatchOptions,
+    action: DedupAction,
+) -> Result<FileReport, BatchError> {
+    let start = Instant::now();
+    let input_bytes = std::fs::metadata(&job.input)?.len();
+
+    let output_bytes = match action {
+        DedupAction::Skip => 0,
+        DedupAction::Link => {
+            if output_format(job, options) != output_format(original, options) {
+                return process(job, options);
+            }
+            // The same input listed twice has nothing to link
+            if job.output != original.output {
+                if job.output.exists() {
+                    std::fs::remove_file(&job.output)?;
+                }
+                std::fs::hard_link(&original.output, &job.output)?;
+            }
+            std::fs::metadata(&job.output)?.len()
+        }
+    };
+
+    Ok(FileReport {
+        input_bytes,
+        output_bytes,
+        elapsed: start.elapsed(),
+        resumed: false,
+        failed_channels: Vec::new(),
+        duplicate_of: Some(original.input.clone()),
+        rank: options.compress.rank,
+        mse: None,
+        timings: None,
+    })
+}
+
+// Compresses every job on a dedicated pool of `options.jobs` workers, each of which handles one
+// file at a time. A failing file does not stop the others; results come back in job order.
+// When deduplicating, duplicates are handled once all originals are done, and a duplicate whose
+// original failed is compressed after all.
+pub fn run_batch(
+    jobs: &[BatchJob],
+    options: &BatchOptions,
+) -> Result<Vec<BatchResult>, BatchError> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(options.jobs)
+        .build()
+        .map_err(io::Error::other)?;
+    let manifest = options
+        .manifest
+        .as_deref()
+        .map(Manifest::open)
+        .transpose()?;
+
+    let run = |job: &BatchJob,
+               work: &dyn Fn() -> Result<FileReport, BatchError>|
+     -> Result<FileReport, BatchError> {
+        let Some(manifest) = &manifest else {
+            return work();
+        };
+        if manifest.is_done(job) {
+            return resume(job, options);
+        }
+
+        let report = work()?;
+        // A skipped duplicate has no output, so there is nothing to resume from
+        if !(report.duplicate_of.is_some() && report.output_bytes == 0) {
+            manifest.record(job)?;
+        }
+        Ok(report)
+    };
+
+    Ok(pool.install(|| {
+        let duplicates = match options.dedup {
+            Some(dedup) => find_duplicates(jobs, dedup.threshold),
+            None => vec![None; jobs.len()],
+        };
+
+        let mut outcomes: Vec<Option<Result<FileReport, BatchError>>> = jobs
+            .par_iter()
+            .zip(&duplicates)
+            .with_max_len(1)
+            .map(|(job, duplicate)| {
+                duplicate
+                    .is_none()
+                    .then(|| run(job, &|| process(job, options)))
+            })
+            .collect();
+
+        let deduplicated: Vec<(usize, Result<FileReport, BatchError>)> = jobs
+            .par_iter()
+            .zip(&duplicates)
+            .enumerate()
+            .with_max_len(1)
+            .filter_map(|(i, (job, duplicate))| {
+                let original = (*duplicate)?;
+                let action = options.dedup?.action;
+                let outcome = match &outcomes[original] {
+                    Some(Ok(_)) => run(job, &|| deduplicate(job, &jobs[original], options, action)),
+                    _ => run(job, &|| process(job, options)),
+                };
+                Some((i, outcome))
+            })
+            .collect();
+        for (i, outcome) in deduplicated {
+            outcomes[i] = Some(outcome);
+        }
+
+        jobs.iter()
+            .zip(outcomes)
+            .map(|(job, outcome)| BatchResult {
+                job: job.clone(),
+                outcome: outcome.unwrap(),
+            })
+            .collect()
+    }))
+}
+
+// Percentiles tabulated by `Summary`; the first and last are the minimum and maximum
+pub const PERCENTILES: [f32; 7] = [0.0, 10.0, 25.0, 50.0, 75.0, 90.0, 100.0];
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Summary {
+    pub count: usize,
+    pub mean: f64,
+    // Value at each of `PERCENTILES`, interpolating linearly between the nearest two files
+    pub percentiles: Vec<f64>,
+}
+
+impl Summary {
+    // `None` for no values
+    pub fn of(mut values: Vec<f64>) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_by(f64::total_cmp);
+        let last = values.len() - 1;
+        let percentiles = PERCENTILES
+            .iter()
+            .map(|&p| {
+                let x = p as f64 / 100.0 * last as f64;
+                let (i, t) = (x as usize, x.fract());
+                let next = values[(i + 1).min(last)];
+                values[i] + t * (next - values[i])
+            })
+            .collect();
+
+        Some(Summary {
+            count: values.len(),
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+            percentiles,
+        })
+    }
+
+    fn to_json(&self) -> String {
+        let percentiles: Vec<String> = PERCENTILES
+            .iter()
+            .zip(&self.percentiles)
+            .map(|(p, value)| format!("\"p{}\": {}", p, json_number(*value)))
+            .collect();
+        format!(
+            "{{\"count\": {}, \"mean\": {}, {}}}",
+            self.count,
+            json_number(self.mean),
+            percentiles.join(", ")
+        )
+    }
+}
+
+fn json_number(x: f64) -> String {
+    if x.is_finite() {
+        x.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+// Dataset-level statistics of a batch run. Each summary covers only the files it makes sense
+// for: errors only those actually compressed, times only those not resumed.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BatchStats {
+    pub files: usize,
+    pub failed: usize,
+    pub resumed: usize,
+    pub duplicates: usize,
+    pub input_bytes: u64,
+    pub output_bytes: u64,
+    pub rank: Option<Summary>,
+    pub input_size: Option<Summary>,
+    pub output_size: Option<Summary>,
+    // Output size over input size
+    pub ratio: Option<Summary>,
+    pub mse: Option<Summary>,
+    // Of the files with nonzero error, since identical ones have infinite PSNR
+    pub psnr: Option<Summary>,
+    pub seconds: Option<Summary>,
+}
+
+impl BatchStats {
+    pub fn from_results(results: &[BatchResult]) -> Self {
+        let reports: Vec<&FileReport> = results
+            .iter()
+            .filter_map(|result| result.outcome.as_ref().ok())
+            .collect();
+        let summary = |f: &dyn Fn(&FileReport) -> Option<f64>| {
+            Summary::of(reports.iter().filter_map(|report| f(report)).collect())
+        };
+        // Skipped duplicates wrote nothing, so there is no output to measure
+        let written =
+            |report: &FileReport| report.output_bytes > 0 || report.duplicate_of.is_none();
+
+        BatchStats {
+            files: results.len(),
+            failed: results.len() - reports.len(),
+            resumed: reports.iter().filter(|report| report.resumed).count(),
+            duplicates: reports
+                .iter()
+                .filter(|report| report.duplicate_of.is_some())
+                .count(),
+            input_bytes: reports.iter().map(|report| report.input_bytes).sum(),
+            output_bytes: reports.iter().map(|report| report.output_bytes).sum(),
+            rank: summary(&|report| Some(report.rank as f64)),
+            input_size: summary(&|report| Some(report.input_bytes as f64)),
+            output_size: summary(&|report| written(report).then_some(report.output_bytes as f64)),
+            ratio: summary(&|report| {
+                (written(report) && report.input_bytes > 0)
+                    .then(|| report.output_bytes as f64 / report.input_bytes as f64)
+            }),
+            mse: summary(&|report| report.mse.map(f64::from)),
+            psnr: summary(&|report| {
+                let mse = report.mse.filter(|&mse| mse > 0.0)? as f64;
+                Some(10.0 * (255.0 * 255.0 / mse).log10())
+            }),
+            seconds: summary(&|report| (!report.resumed).then_some(report.elapsed.as_secs_f64())),
+        }
+    }
+
+    fn summaries(&self) -> [(&'static str, &Option<Summary>); 7] {
+        [
+            ("rank", &self.rank),
+            ("input_size", &self.input_size),
+            ("output_size", &self.output_size),
+            ("ratio", &self.ratio),
+            ("mse", &self.mse),
+            ("psnr", &self.psnr),
+            ("seconds", &self.seconds),
+        ]
+    }
+
+    // One row per metric: its name, count, mean and `PERCENTILES`; metrics with no values are
+    // left out
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write!(writer, "metric,count,mean")?;
+        for p in PERCENTILES {
+            write!(writer, ",p{}", p)?;
+        }
+        writeln!(writer)?;
+
+        for (name, summary) in self.summaries() {
+            let Some(summary) = summary else {
+                continue;
+            };
+            write!(writer, "{},{},{}", name, summary.count, summary.mean)?;
+            for value in &summary.percentiles {
+                write!(writer, ",{}", value)?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> String {
+        let summaries: Vec<String> = self
+            .summaries()
+            .iter()
+            .map(|(name, summary)| {
+                let value = summary
+                    .as_ref()
+                    .map_or("null".to_string(), Summary::to_json);
+                format!("\"{}\": {}", name, value)
+            })
+            .collect();
+        format!(
+            "{{\"files\": {}, \"failed\": {}, \"resumed\": {}, \"duplicates\": {}, \
+             \"input_bytes\": {}, \"output_bytes\": {}, {}}}",
+            self.files,
+            self.failed,
+            self.resumed,
+            self.duplicates,
+            self.input_bytes,
+            self.output_bytes,
+            summaries.join(", ")
+        )
+    }
+}
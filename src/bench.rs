@@ -0,0 +1,212 @@
+use crate::compress::{Compressible, SvdApproxError, SvdBackend, SvdFactors, svd};
+use crate::dynwrapper::DynWrapper;
+use crate::encode::{Encoding, SaveWith};
+use crate::imagewrapper::{ImageWrapper, Planes};
+use crate::metrics::Metrics;
+use image::{DynamicImage, ImageError, ImageFormat, guess_format, load_from_memory_with_format};
+use std::io::Cursor;
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+pub enum BenchError {
+    Image(ImageError),
+    Svd(SvdApproxError),
+}
+
+impl std::fmt::Display for BenchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BenchError::Image(err) => write!(f, "Image error: {}", err),
+            BenchError::Svd(err) => write!(f, "SVD error: {}", err),
+        }
+    }
+}
+
+impl From<ImageError> for BenchError {
+    fn from(err: ImageError) -> Self {
+        BenchError::Image(err)
+    }
+}
+
+impl From<SvdApproxError> for BenchError {
+    fn from(err: SvdApproxError) -> Self {
+        BenchError::Svd(err)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BenchTimings {
+    pub backend: SvdBackend,
+    pub rank: usize,
+    // Full decomposition of every channel, shared by all ranks of the same backend
+    pub svd: Duration,
+    pub reconstruct: Duration,
+    pub encode: Duration,
+    pub encoded_bytes: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct BenchReport {
+    pub decode: Duration,
+    // Splitting the decoded pixels into column-major planes, and interleaving them back
+    pub to_planes: Duration,
+    pub from_planes: Duration,
+    pub timings: Vec<BenchTimings>,
+}
+
+// Times each stage of the pipeline separately for every backend/rank pair, re-encoding into the
+// input's own format. Channels are processed sequentially so the timings are comparable.
+pub fn run(
+    image: &[u8],
+    ranks: &[usize],
+    backends: &[SvdBackend],
+) -> Result<BenchReport, BenchError> {
+    let format = guess_format(image)?;
+
+    let start = Instant::now();
+    let img = load_from_memory_with_format(image, format)?;
+    let decode = start.elapsed();
+
+    let start = Instant::now();
+    let wrapper = DynWrapper::from_dynamic(img);
+    let to_planes = start.elapsed();
+
+    let start = Instant::now();
+    wrapper.to_dynamic();
+    let from_planes = start.elapsed();
+
+    let mut timings = Vec::with_capacity(ranks.len() * backends.len());
+
+    for &backend in backends {
+        let start = Instant::now();
+        let factors = wrapper
+            .planes()
+            .iter()
+            .map(|mat| svd(mat.as_ref(), backend))
+            .collect::<Result<Vec<SvdFactors>, _>>()?;
+        let svd = start.elapsed();
+
+        for &rank in ranks {
+            let start = Instant::now();
+            let mats = factors
+                .iter()
+                .map(|f| Ok(f.truncate(rank, false)?.reconstruct()))
+                .collect::<Result<Vec<_>, SvdApproxError>>()?;
+            let reconstruct = start.elapsed();
+
+            let compressed = wrapper.rebuild(mats);
+
+            let start = Instant::now();
+            let mut encoded = Cursor::new(Vec::new());
+            compressed.save(&mut encoded, format)?;
+            let encode = start.elapsed();
+
+            timings.push(BenchTimings {
+                backend,
+                rank,
+                svd,
+                reconstruct,
+                encode,
+                encoded_bytes: encoded.into_inner().len(),
+            });
+        }
+    }
+
+    Ok(BenchReport {
+        decode,
+        to_planes,
+        from_planes,
+        timings,
+    })
+}
+
+#[derive(Clone, Debug)]
+pub struct Baseline {
+    pub encoding: Encoding,
+    pub bytes: usize,
+    // Against the original; infinite for lossless encodings
+    pub psnr: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct BaselineComparison {
+    pub rank: usize,
+    pub psnr: f32,
+    // The rank-`rank` factors as raw f32s, i.e. what storing the SVD itself costs
+    pub factor_bytes: usize,
+    // The reconstruction encoded as PNG
+    pub png_bytes: usize,
+    // Lowest JPEG quality matching `psnr`, or `None` if even quality 100 falls short
+    pub jpeg: Option<Baseline>,
+    // `image` has no lossy WebP encoder, so this is a lossless reference size
+    pub webp_lossless: Baseline,
+}
+
+fn encoded(wrapper: &DynWrapper, encoding: Encoding) -> Result<Vec<u8>, BenchError> {
+    let mut encoded = Vec::new();
+    wrapper.save_with(&mut encoded, encoding)?;
+    Ok(encoded)
+}
+
+// JPEG drops alpha, so it is compared against the original's color channels only
+fn jpeg_baseline(original: &DynWrapper, quality: u8) -> Result<Baseline, BenchError> {
+    let encoding = Encoding::Jpeg { quality };
+    let encoded = encoded(original, encoding)?;
+    let decoded =
+        DynWrapper::from_dynamic(load_from_memory_with_format(&encoded, ImageFormat::Jpeg)?);
+
+    let img = original.to_dynamic();
+    let reference = DynWrapper::from_dynamic(if img.color().has_color() {
+        DynamicImage::ImageRgb8(img.into_rgb8())
+    } else {
+        DynamicImage::ImageLuma8(img.into_luma8())
+    });
+
+    Ok(Baseline {
+        encoding,
+        bytes: encoded.len(),
+        psnr: reference.psnr(&decoded),
+    })
+}
+
+// Compresses `image` at `rank` and sets the result beside standard codecs tuned to the same
+// quality, to show whether the SVD pays for itself on this image. The JPEG quality is found by
+// bisection, assuming PSNR grows with quality, so it costs about seven encodes.
+pub fn compare_baselines(image: &[u8], rank: usize) -> Result<BaselineComparison, BenchError> {
+    let wrapper = DynWrapper::from_dynamic(image::load_from_memory(image)?);
+    let compressed = wrapper.compress(rank)?;
+    let psnr = wrapper.psnr(&compressed);
+
+    let planes = wrapper.planes();
+    let (m, n) = (planes[0].nrows(), planes[0].ncols());
+    let factor_bytes = planes.len() * rank * (m + n + 1) * size_of::<f32>();
+
+    let mut png = Cursor::new(Vec::new());
+    compressed.save(&mut png, ImageFormat::Png)?;
+
+    let (mut lo, mut hi) = (1u8, 100u8);
+    let mut jpeg = None;
+    while lo <= hi {
+        let quality = lo + (hi - lo) / 2;
+        let baseline = jpeg_baseline(&wrapper, quality)?;
+        if baseline.psnr >= psnr {
+            jpeg = Some(baseline);
+            hi = quality - 1;
+        } else {
+            lo = quality + 1;
+        }
+    }
+
+    Ok(BaselineComparison {
+        rank,
+        psnr,
+        factor_bytes,
+        png_bytes: png.into_inner().len(),
+        jpeg,
+        webp_lossless: Baseline {
+            encoding: Encoding::WebPLossless,
+            bytes: encoded(&wrapper, Encoding::WebPLossless)?.len(),
+            psnr: f32::INFINITY,
+        },
+    })
+}
@@ -0,0 +1,433 @@
+use image::ImageFormat;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Cursor, Read, Write};
+use std::process::ExitCode;
+use svdimagecompress::{
+    AlphaRank, Annotate, Annotation, BatchJob, BatchOptions, BatchStats, Binarize, CompressOptions,
+    Compressible, ContactSheetOptions, Dedup, DedupAction, DocumentOptions, DowncastPolicy,
+    DynWrapper, ErrorMap, ErrorMapOptions, ImageWrapper, Metrics, Planes, Preset, clean_document,
+    run_batch, save_contact_sheets, set_diagnostics_handler, set_downcast_policy,
+};
+
+const USAGE: &str = "\
+Usage: svdimagecompress -r <rank> [-f <format>] [--bad] <input> <output>
+       svdimagecompress -p <preset> [-r <rank>] [-f <format>] <input> <output>
+       svdimagecompress -r <rank> [-f <format>] [--bad] [-j <jobs>] -o <dir> <inputs>...
+       svdimagecompress compare [--error-map <file>] <original> <compressed>
+
+Use `-` as the input or output to read from stdin or write to stdout. With `-o`, every input is
+compressed into <dir> under its own file name.
+
+Options:
+    -r, --rank <rank>        Rank of the approximation; 0 gives the mean color
+    -p, --preset <preset>    archive, web, thumbnail or lossless: picks the rank from the image
+                             and the encoder, unless overridden by -r or -f; document cleans up
+                             a scan instead, with -r as the rank of the lighting model
+    --binarize               With `-p document`, output pure black and white
+    -f, --format <format>    Output format by extension, e.g. png (default: from <output>)
+    --bad                    Keep the smallest singular values instead of the largest
+    --premultiply            Compress colors multiplied by alpha, avoiding fringes at
+                             transparent edges (default with lossy presets)
+    --alpha-rank <rank>      Rank of the alpha channel, or `lossless` to keep it exact
+    --annotate               Print the rank, PSNR and version in a corner of the output
+    --strict-depth           Fail on 16-bit or float inputs instead of reducing them to 8 bits
+    -o, --out-dir <dir>      Compress many inputs into <dir>
+    -j, --jobs <jobs>        Files compressed concurrently with `-o` (default: one per core)
+    --manifest <file>        With `-o`, record finished files in <file> and skip them on rerun
+    --salvage                With `-o`, write files with failed channels left uncompressed
+    --dedup <skip|link>      With `-o`, skip near-duplicate inputs or hard-link their outputs
+                             to the first similar input's
+    --stats <file>           With `-o`, write percentiles of the ranks, errors and sizes to
+                             <file>, as JSON if it ends in .json and CSV otherwise
+    --contact-sheets <dir>   With `-o`, write labelled thumbnails of the outputs to <dir> as
+                             sheet_001.png and so on
+
+Commands:
+    compare <original> <compressed>    Print PSNR, SSIM, edge similarity, max error and
+                                       size savings; with --error-map, also save the
+                                       per-pixel error as a viridis heat map";
+
+fn load(path: &str) -> Result<(DynWrapper, u64), String> {
+    let file = File::open(path).map_err(|err| format!("{}: {}", path, err))?;
+    let size = file
+        .metadata()
+        .map_err(|err| format!("{}: {}", path, err))?
+        .len();
+    let wrapper =
+        DynWrapper::load(BufReader::new(file)).map_err(|err| format!("{}: {}", path, err))?;
+    Ok((wrapper, size))
+}
+
+fn compress(args: &[String]) -> Result<(), String> {
+    let mut rank = None;
+    let mut preset = None;
+    let mut format = None;
+    let mut bad = false;
+    let mut out_dir = None;
+    let mut jobs = 0;
+    let mut manifest = None;
+    let mut salvage = false;
+    let mut dedup = None;
+    let mut stats = None;
+    let mut sheets = None;
+    let mut binarize = false;
+    let mut annotate = false;
+    let mut premultiply = false;
+    let mut alpha_rank = AlphaRank::Same;
+    let mut paths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-r" | "--rank" => {
+                let value = args.next().ok_or(USAGE)?;
+                rank = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid rank `{}`", value))?,
+                );
+            }
+            "-p" | "--preset" => {
+                let value = args.next().ok_or(USAGE)?;
+                preset = Some(
+                    Preset::from_name(value)
+                        .ok_or_else(|| format!("unknown preset `{}`", value))?,
+                );
+            }
+            "-f" | "--format" => {
+                let value = args.next().ok_or(USAGE)?;
+                format = Some(
+                    ImageFormat::from_extension(value)
+                        .ok_or_else(|| format!("unknown format `{}`", value))?,
+                );
+            }
+            "--bad" => bad = true,
+            "--premultiply" => premultiply = true,
+            "--strict-depth" => set_downcast_policy(DowncastPolicy::Error),
+            "--alpha-rank" => {
+                let value = args.next().ok_or(USAGE)?;
+                alpha_rank = match value.as_str() {
+                    "lossless" => AlphaRank::Lossless,
+                    _ => AlphaRank::Rank(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid alpha rank `{}`", value))?,
+                    ),
+                };
+            }
+            "--salvage" => salvage = true,
+            "--binarize" => binarize = true,
+            "--annotate" => annotate = true,
+            "--dedup" => {
+                let value = args.next().ok_or(USAGE)?;
+                let action = match value.as_str() {
+                    "skip" => DedupAction::Skip,
+                    "link" => DedupAction::Link,
+                    _ => return Err(format!("unknown dedup action `{}`", value)),
+                };
+                dedup = Some(Dedup::new(action));
+            }
+            "-o" | "--out-dir" => out_dir = Some(args.next().ok_or(USAGE)?.as_str()),
+            "--manifest" => manifest = Some(args.next().ok_or(USAGE)?.into()),
+            "--stats" => stats = Some(args.next().ok_or(USAGE)?.as_str()),
+            "--contact-sheets" => sheets = Some(args.next().ok_or(USAGE)?.as_str()),
+            "-j" | "--jobs" => {
+                let value = args.next().ok_or(USAGE)?;
+                jobs = value
+                    .parse()
+                    .map_err(|_| format!("invalid job count `{}`", value))?;
+            }
+            _ => paths.push(arg.as_str()),
+        }
+    }
+
+    if let Some(dir) = out_dir {
+        // A preset's rank depends on the image, while a batch shares one set of options
+        if preset.is_some() {
+            return Err("`-p` cannot be combined with `-o`".to_string());
+        }
+        let Some(rank) = rank else {
+            return Err(USAGE.to_string());
+        };
+        let options = BatchOptions {
+            compress: CompressOptions {
+                bad,
+                premultiply,
+                alpha_rank,
+                ..CompressOptions::new(rank)
+            },
+            format,
+            jobs,
+            manifest,
+            salvage,
+            dedup,
+            timings: false,
+        };
+        return batch(&paths, dir, &options, stats, sheets);
+    }
+
+    let [input, output] = paths.as_slice() else {
+        return Err(USAGE.to_string());
+    };
+    if rank.is_none() && preset.is_none() {
+        return Err(USAGE.to_string());
+    }
+    // An explicit format takes precedence over the preset's encoder
+    let preset_encoding = preset.filter(|_| format.is_none());
+    let format = match (format, preset) {
+        (Some(format), _) => format,
+        (None, Some(preset)) => preset.format(),
+        (None, None) if *output == "-" => {
+            return Err("`-f` is required when writing to stdout".to_string());
+        }
+        (None, None) => {
+            ImageFormat::from_path(output).map_err(|err| format!("{}: {}", output, err))?
+        }
+    };
+
+    let wrapper = if *input == "-" {
+        let mut buf = Vec::new();
+        std::io::stdin()
+            .read_to_end(&mut buf)
+            .map_err(|err| format!("stdin: {}", err))?;
+        DynWrapper::load(Cursor::new(buf)).map_err(|err| format!("stdin: {}", err))?
+    } else {
+        load(input)?.0
+    };
+
+    let mut used_rank = rank;
+    let compressed = match (preset, rank) {
+        (Some(Preset::Document), rank) => {
+            let defaults = DocumentOptions::default();
+            let options = DocumentOptions {
+                background_rank: rank.unwrap_or(defaults.background_rank),
+                binarize: binarize.then_some(Binarize::Otsu),
+                ..defaults
+            };
+            // The output is a cleaned page rather than an approximation of the input
+            used_rank = None;
+            clean_document(&wrapper, &options).map(DynWrapper::Grey)
+        }
+        (Some(preset), rank) => {
+            let options = preset.options(&wrapper).map_err(|err| err.to_string())?;
+            used_rank = Some(rank.unwrap_or(options.rank));
+            wrapper.compress_with(&CompressOptions {
+                rank: rank.unwrap_or(options.rank),
+                bad,
+                premultiply: premultiply || options.premultiply,
+                alpha_rank,
+                ..options
+            })
+        }
+        (None, Some(rank)) if premultiply || alpha_rank != AlphaRank::Same => wrapper
+            .compress_with(&CompressOptions {
+                bad,
+                premultiply,
+                alpha_rank,
+                ..CompressOptions::new(rank)
+            }),
+        (None, Some(rank)) if bad => wrapper.compress_bad(rank),
+        (None, Some(rank)) => wrapper.compress(rank),
+        (None, None) => unreachable!(),
+    }
+    .map_err(|err| err.to_string())?;
+
+    let compressed = if annotate {
+        let comparable = (wrapper.width(), wrapper.height(), wrapper.planes().len())
+            == (
+                compressed.width(),
+                compressed.height(),
+                compressed.planes().len(),
+            );
+        compressed.annotate(&Annotation {
+            rank: used_rank,
+            psnr: comparable.then(|| wrapper.compare(&compressed).psnr),
+            ..Annotation::default()
+        })
+    } else {
+        compressed
+    };
+
+    let save = |writer: &mut dyn Write| match preset_encoding {
+        Some(preset) => preset.save(&compressed, writer),
+        None => compressed.save_stream(writer, format),
+    };
+
+    if *output == "-" {
+        save(&mut std::io::stdout().lock()).map_err(|err| format!("stdout: {}", err))
+    } else {
+        let file = File::create(output).map_err(|err| format!("{}: {}", output, err))?;
+        let mut writer = BufWriter::new(file);
+        save(&mut writer)
+            .and_then(|()| Ok(writer.flush()?))
+            .map_err(|err| format!("{}: {}", output, err))
+    }
+}
+
+fn batch(
+    inputs: &[&str],
+    dir: &str,
+    options: &BatchOptions,
+    stats: Option<&str>,
+    sheets: Option<&str>,
+) -> Result<(), String> {
+    if inputs.is_empty() {
+        return Err(USAGE.to_string());
+    }
+    std::fs::create_dir_all(dir).map_err(|err| format!("{}: {}", dir, err))?;
+
+    let jobs: Vec<BatchJob> = inputs
+        .iter()
+        .map(|input| BatchJob::into_dir(input, dir, options.format))
+        .collect();
+    let results = run_batch(&jobs, options).map_err(|err| err.to_string())?;
+
+    let width = inputs
+        .iter()
+        .map(|input| input.len())
+        .max()
+        .unwrap_or(0)
+        .max(4);
+    println!(
+        "{:<width$}  {:>7}  {:>10}  {:>10}  {:>7}  {:>8}",
+        "FILE", "STATUS", "INPUT", "OUTPUT", "SAVED", "TIME"
+    );
+
+    let (mut failed, mut resumed) = (0, 0);
+    for result in &results {
+        let input = result.job.input.display();
+        match &result.outcome {
+            Ok(report) => {
+                resumed += report.resumed as usize;
+                let status = if report.resumed {
+                    "done"
+                } else if report.duplicate_of.is_some() && report.output_bytes == 0 {
+                    "dup"
+                } else if report.duplicate_of.is_some() {
+                    "linked"
+                } else if !report.failed_channels.is_empty() {
+                    "partial"
+                } else {
+                    "ok"
+                };
+                println!(
+                    "{:<width$}  {:>7}  {:>10}  {:>10}  {:>6.1}%  {:>7.2}s",
+                    input,
+                    status,
+                    report.input_bytes,
+                    report.output_bytes,
+                    100.0 * (1.0 - report.output_bytes as f64 / report.input_bytes.max(1) as f64),
+                    report.elapsed.as_secs_f64()
+                );
+                if let Some(original) = &report.duplicate_of {
+                    eprintln!("{}: duplicate of {}", input, original.display());
+                }
+                for failure in &report.failed_channels {
+                    eprintln!(
+                        "{}: channel {} left uncompressed: {}",
+                        input, failure.channel, failure.error
+                    );
+                }
+            }
+            Err(err) => {
+                failed += 1;
+                println!("{:<width$}  {:>7}", input, "error");
+                eprintln!("{}: {}", input, err);
+            }
+        }
+    }
+
+    println!(
+        "{} of {} files compressed ({} already done)",
+        results.len() - failed,
+        results.len(),
+        resumed
+    );
+    if let Some(path) = stats {
+        write_stats(path, &BatchStats::from_results(&results))
+            .map_err(|err| format!("{}: {}", path, err))?;
+    }
+    if let Some(dir) = sheets {
+        save_contact_sheets(&results, dir, &ContactSheetOptions::default())
+            .map_err(|err| format!("{}: {}", dir, err))?;
+    }
+    if failed > 0 {
+        return Err(format!("{} of {} files failed", failed, results.len()));
+    }
+    Ok(())
+}
+
+fn write_stats(path: &str, stats: &BatchStats) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    if path.ends_with(".json") {
+        writeln!(writer, "{}", stats.to_json())?;
+    } else {
+        stats.write_csv(&mut writer)?;
+    }
+    writer.flush()
+}
+
+fn compare(args: &[String]) -> Result<(), String> {
+    let (error_map, args) = match args {
+        [flag, path, rest @ ..] if flag == "--error-map" => (Some(path), rest),
+        _ => (None, args),
+    };
+    let [original, compressed] = args else {
+        return Err(USAGE.to_string());
+    };
+
+    let (a, a_size) = load(original)?;
+    let (b, b_size) = load(compressed)?;
+
+    if (a.width(), a.height(), a.planes().len()) != (b.width(), b.height(), b.planes().len()) {
+        return Err(format!("cannot compare `{}` with `{}`", a, b));
+    }
+
+    let comparison = a.compare(&b);
+    let saved = a_size as i64 - b_size as i64;
+
+    println!("PSNR:       {:.2} dB", comparison.psnr);
+    println!("SSIM:       {:.4}", comparison.ssim);
+    println!("Edges:      {:.4}", comparison.edges);
+    println!("Max error:  {}", comparison.max_error);
+    println!(
+        "Size:       {} -> {} bytes ({} bytes, {:.1}% saved)",
+        a_size,
+        b_size,
+        saved,
+        100.0 * saved as f64 / a_size.max(1) as f64
+    );
+
+    if let Some(path) = error_map {
+        let format = ImageFormat::from_path(path).map_err(|err| format!("{}: {}", path, err))?;
+        let file = File::create(path).map_err(|err| format!("{}: {}", path, err))?;
+        a.error_map(&b, &ErrorMapOptions::default())
+            .save(BufWriter::new(file), format)
+            .map_err(|err| format!("{}: {}", path, err))?;
+    }
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    set_diagnostics_handler(|diagnostic| eprintln!("warning: {}", diagnostic));
+
+    let result = match args.first().map(String::as_str) {
+        Some("compare") => compare(&args[1..]),
+        Some("-h" | "--help") => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Some(_) => compress(&args),
+        None => Err(USAGE.to_string()),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
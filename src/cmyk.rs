@@ -0,0 +1,182 @@
+use crate::imagewrapper::{ImageWrapper, Planes, RgbImageWrapper, describe, from_mats, to_mats};
+use faer_core::{Mat, MatRef};
+use image::error::{DecodingError, ImageFormatHint, UnsupportedError, UnsupportedErrorKind};
+use image::*;
+use std::io::{BufReader, Cursor, Read, Seek, Write};
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::encoder::{TiffEncoder, colortype::CMYK8};
+use zune_core::colorspace::ColorSpace;
+use zune_core::options::DecoderOptions;
+use zune_jpeg::JpegDecoder;
+
+// Four ink planes (C, M, Y, K, with 255 meaning full ink) loaded without going through RGB, so
+// print scans can be compressed in their native color space
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CmykImageWrapper {
+    pub mats: [Mat<f32>; 4],
+    pub width: usize,
+    pub height: usize,
+}
+
+fn unsupported(format: ImageFormat, color: ExtendedColorType) -> ImageError {
+    ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+        ImageFormatHint::Exact(format),
+        UnsupportedErrorKind::Color(color),
+    ))
+}
+
+fn decoding_error(
+    format: ImageFormat,
+    err: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormatHint::Exact(format), err))
+}
+
+// Photoshop and most other writers of Adobe (APP14) CMYK JPEGs store the samples inverted
+fn has_adobe_marker(buf: &[u8]) -> bool {
+    buf.windows(9)
+        .any(|w| w[0] == 0xFF && w[1] == 0xEE && &w[4..9] == b"Adobe")
+}
+
+fn decode_tiff(buf: &[u8]) -> ImageResult<(Vec<u8>, u32, u32)> {
+    let tiff_err = |err| decoding_error(ImageFormat::Tiff, err);
+
+    let mut decoder = Decoder::new(Cursor::new(buf)).map_err(tiff_err)?;
+    let (width, height) = decoder.dimensions().map_err(tiff_err)?;
+
+    match decoder.colortype().map_err(tiff_err)? {
+        tiff::ColorType::CMYK(8) => match decoder.read_image().map_err(tiff_err)? {
+            DecodingResult::U8(data) => Ok((data, width, height)),
+            _ => Err(unsupported(ImageFormat::Tiff, ExtendedColorType::Cmyk8)),
+        },
+        _ => Err(unsupported(ImageFormat::Tiff, ExtendedColorType::Rgb8)),
+    }
+}
+
+fn decode_jpeg(buf: &[u8]) -> ImageResult<(Vec<u8>, u32, u32)> {
+    let jpeg_err =
+        |err: zune_jpeg::errors::DecodeErrors| decoding_error(ImageFormat::Jpeg, err.to_string());
+
+    let options = DecoderOptions::default().jpeg_set_out_colorspace(ColorSpace::CMYK);
+    let mut decoder = JpegDecoder::new_with_options(buf, options);
+    decoder.decode_headers().map_err(jpeg_err)?;
+
+    // YCCK would need an inverse transform zune-jpeg only offers towards RGB
+    if decoder.get_input_colorspace() != Some(ColorSpace::CMYK) {
+        return Err(unsupported(ImageFormat::Jpeg, ExtendedColorType::Rgb8));
+    }
+
+    let mut data = decoder.decode().map_err(jpeg_err)?;
+    let info = decoder.info().unwrap();
+
+    if has_adobe_marker(buf) {
+        data.iter_mut().for_each(|x| *x = 255 - *x);
+    }
+
+    Ok((data, info.width as u32, info.height as u32))
+}
+
+impl CmykImageWrapper {
+    // Uncalibrated conversion (no ICC profile), the same formula `image` uses for CMYK TIFFs
+    pub fn to_rgb(&self) -> RgbImageWrapper {
+        let ink = |mat: &Mat<f32>, i, j| 1.0 - mat.read(i, j).clamp(0.0, 255.0) / 255.0;
+        let [c, m, y, k] = &self.mats;
+
+        let channel = |mat: &Mat<f32>| {
+            Mat::from_fn(self.height, self.width, |i, j| {
+                255.0 * ink(mat, i, j) * ink(k, i, j)
+            })
+        };
+
+        RgbImageWrapper {
+            mats: [channel(c), channel(m), channel(y)],
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    // Interleaved C, M, Y, K bytes, carried in an `Rgba` buffer purely for its 4-byte layout
+    fn to_bytes(&self) -> Vec<u8> {
+        from_mats::<Rgba<u8>, 4>(&self.mats, self.width, self.height).into_raw()
+    }
+}
+
+impl Planes for CmykImageWrapper {
+    fn planes(&self) -> &[Mat<f32>] {
+        &self.mats
+    }
+
+    fn planes_mut(&mut self) -> &mut [Mat<f32>] {
+        &mut self.mats
+    }
+
+    fn map_planes<F>(&self, f: F) -> Self
+    where
+        F: Fn(MatRef<f32>) -> Mat<f32> + Sync,
+    {
+        let mats = self.mats.each_ref().map(|mat| f(mat.as_ref()));
+        let (height, width) = (mats[0].nrows(), mats[0].ncols());
+
+        Self {
+            mats,
+            width,
+            height,
+        }
+    }
+
+    fn rebuild(&self, planes: Vec<Mat<f32>>) -> Self {
+        let mats: [Mat<f32>; 4] = planes.try_into().expect("expected 4 planes");
+        let (height, width) = (mats[0].nrows(), mats[0].ncols());
+
+        Self {
+            mats,
+            width,
+            height,
+        }
+    }
+}
+
+impl std::fmt::Display for CmykImageWrapper {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        describe(f, "CMYK", self.width, self.height, 4)
+    }
+}
+
+impl ImageWrapper for CmykImageWrapper {
+    // Accepts 8-bit CMYK TIFFs and CMYK JPEGs
+    fn load<R: Read + Seek>(reader: R) -> ImageResult<Self> {
+        let mut reader = BufReader::new(reader);
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf)?;
+
+        let format = guess_format(&buf)?;
+        let (data, width, height) = match format {
+            ImageFormat::Tiff => decode_tiff(&buf)?,
+            ImageFormat::Jpeg => decode_jpeg(&buf)?,
+            format => return Err(unsupported(format, ExtendedColorType::Cmyk8)),
+        };
+
+        let img: RgbaImage = ImageBuffer::from_raw(width, height, data)
+            .ok_or_else(|| decoding_error(format, "truncated CMYK image data"))?;
+
+        Ok(Self {
+            mats: to_mats(&img),
+            width: width as usize,
+            height: height as usize,
+        })
+    }
+
+    // TIFF output stays CMYK; any other format goes through `to_rgb`
+    fn save<W: Write + Seek>(&self, writer: W, format: ImageFormat) -> ImageResult<()> {
+        if format != ImageFormat::Tiff {
+            return self.to_rgb().save(writer, format);
+        }
+
+        let mut encoder = TiffEncoder::new(writer)
+            .map_err(|err| ImageError::IoError(std::io::Error::other(err)))?;
+        encoder
+            .write_image::<CMYK8>(self.width as u32, self.height as u32, &self.to_bytes())
+            .map_err(|err| ImageError::IoError(std::io::Error::other(err)))
+    }
+}
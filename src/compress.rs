@@ -1,12 +1,73 @@
-use crate::imagewrapper::{GreyImageWrapper, RgbImageWrapper};
-use faer_core::{Mat, MatRef, Parallelism, dyn_stack::PodStack};
+use crate::anisotropic::anisotropic_approx;
+use crate::arena::svdapprox_in_arena;
+#[cfg(feature = "cmyk")]
+use crate::cmyk::CmykImageWrapper;
+#[cfg(feature = "dicom")]
+use crate::dicom::DicomImageWrapper;
+use crate::dynwrapper::DynWrapper;
+#[cfg(feature = "fits")]
+use crate::fits::FitsImageWrapper;
+use crate::geometry::Geometry;
+use crate::imagewrapper::{
+    GreyAlphaImageWrapper, GreyImageWrapper, LumaWeights, Planes, RgbImageWrapper,
+    RgbaImageWrapper, quantize,
+};
+use crate::instrument::{self, span};
+use crate::jacobi::jacobi_svd;
+use crate::job::{JobHandle, Priority, Progress, spawn};
+use crate::memory::reserve;
+use crate::stats::{ChannelStats, channel_stats};
+use crate::tiling::{Tiling, map_tiles};
+use faer_core::mul::matmul;
+use faer_core::reborrow::ReborrowMut;
+use faer_core::{Mat, MatMut, MatRef, Parallelism, dyn_stack::PodStack, get_global_parallelism};
 use faer_svd::*;
+use image::imageops::FilterType;
+use image::{ColorType, DynamicImage, ImageBuffer};
 use rayon::prelude::*;
+use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 pub enum SvdApproxError {
     InvalidRank(usize, usize),
     ComputeReqFailed,
+    ShapeMismatch((usize, usize), (usize, usize)),
+    // The channel count of the wrapper, and the number of per-channel inputs given for it
+    ChannelCountMismatch(usize, usize),
+    MemoryBudgetExceeded(usize, usize),
+    // The bytes a channel needed, and those left in the global `MemoryBudget`
+    MemoryPoolExhausted(usize, usize),
+    // The bytes `Compressible::compress_in_arena` needed, and the length of the arena
+    ArenaTooSmall(usize, usize),
+    // An SVD failed `is_accurate` where there was no fallback to recompute it with
+    InaccurateSvd,
+    TimeBudgetExceeded(Duration),
+    // Stopped through `JobHandle::cancel`
+    Cancelled,
+    // Every failing channel of a multi-channel wrapper, and the total channel count
+    ChannelsFailed(Vec<ChannelError>, usize),
+}
+
+#[derive(Debug)]
+pub struct ChannelError {
+    pub channel: usize,
+    pub error: SvdApproxError,
+}
+
+// Output of `Compressible::compress_salvaged`: the image, with failed channels left
+// uncompressed, and what went wrong with each of them
+#[derive(Debug)]
+pub struct Salvaged<W> {
+    pub wrapper: W,
+    pub failures: Vec<ChannelError>,
+}
+
+// One output of `Factorizable::ladder`, with the rank its target resolved to
+#[derive(Debug)]
+pub struct Rung<W> {
+    pub target: QualityTarget,
+    pub rank: usize,
+    pub wrapper: W,
 }
 
 impl std::fmt::Display for SvdApproxError {
@@ -18,28 +79,834 @@ impl std::fmt::Display for SvdApproxError {
             SvdApproxError::ComputeReqFailed => {
                 write!(f, "Failed to compute buffer requirements for SVD.")
             }
+            SvdApproxError::ShapeMismatch((m1, n1), (m2, n2)) => {
+                write!(f, "Shapes must match, got {}x{} and {}x{}.", m1, n1, m2, n2)
+            }
+            SvdApproxError::ChannelCountMismatch(channels, given) => {
+                write!(f, "Expected {} channels, got {}.", channels, given)
+            }
+            SvdApproxError::MemoryBudgetExceeded(required, budget) => {
+                write!(
+                    f,
+                    "Compressing a single channel needs {} bytes, over the budget of {}.",
+                    required, budget
+                )
+            }
+            SvdApproxError::MemoryPoolExhausted(required, available) => {
+                write!(
+                    f,
+                    "Compressing a channel needs {} bytes, but only {} are left in the memory pool.",
+                    required, available
+                )
+            }
+            SvdApproxError::ArenaTooSmall(required, available) => {
+                write!(
+                    f,
+                    "Compressing a channel needs an arena of {} bytes, got {}.",
+                    required, available
+                )
+            }
+            SvdApproxError::InaccurateSvd => {
+                write!(f, "The SVD lost accuracy and could not be recomputed.")
+            }
+            SvdApproxError::TimeBudgetExceeded(budget) => {
+                write!(
+                    f,
+                    "Compression took longer than the budget of {:?}.",
+                    budget
+                )
+            }
+            SvdApproxError::Cancelled => write!(f, "Compression was cancelled."),
+            SvdApproxError::ChannelsFailed(errors, channels) => {
+                write!(f, "{} of {} channels failed:", errors.len(), channels)?;
+                for ChannelError { channel, error } in errors {
+                    write!(f, " channel {}: {}", channel, error)?;
+                }
+                Ok(())
+            }
         }
     }
 }
 
-fn svdapprox(mat: MatRef<f32>, rank: usize, bad: bool) -> Result<Mat<f32>, SvdApproxError> {
-    let m = mat.nrows();
-    let n = mat.ncols();
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SvdFactors {
+    pub u: Mat<f32>,
+    pub s: Vec<f32>,
+    pub v: Mat<f32>,
+    // Squared Frobenius norm of the decomposed matrix, i.e. the energy of its full spectrum
+    pub energy: f32,
+}
+
+// Columns reconstructed at a time by `SvdFactors::reconstruct_into` and `factors_to_image`
+const COLUMN_BLOCK: usize = 256;
+
+impl SvdFactors {
+    pub fn rank(&self) -> usize {
+        self.s.len()
+    }
+
+    // Rank of the full decomposition, i.e. the smaller side of the matrix
+    pub fn max_rank(&self) -> usize {
+        self.u.nrows().min(self.v.nrows())
+    }
+
+    // Number of kept singular values above `tol` times the largest kept one
+    pub fn numeric_rank(&self, tol: f32) -> usize {
+        numeric_rank(&self.s, tol)
+    }
+
+    // Smallest rank retaining at least `energy` (a fraction in [0, 1]) of the full spectrum's
+    // energy, or `None` if even the kept singular values fall short
+    pub fn effective_rank(&self, energy: f32) -> Option<usize> {
+        effective_rank(&self.s, self.energy, energy, self.rank() == self.max_rank())
+    }
+
+    pub fn retained_energy(&self) -> f32 {
+        if self.energy > 0.0 {
+            // Folding from +0.0 rather than summing, which starts at -0.0 for rank 0
+            self.s.iter().fold(0.0, |acc, x| acc + x * x) / self.energy
+        } else {
+            1.0
+        }
+    }
+
+    pub fn reconstruct(&self) -> Mat<f32> {
+        self.reconstruct_with(get_global_parallelism())
+    }
+
+    pub fn reconstruct_with(&self, parallelism: Parallelism) -> Mat<f32> {
+        let mut mat = Mat::zeros(self.u.nrows(), self.v.nrows());
+        self.reconstruct_into(mat.as_mut(), parallelism);
+        mat
+    }
+
+    // Overwrites `dst`, which must be `m x n`, with the reconstruction, a block of columns at a
+    // time, so reusing an existing matrix never allocates a second one of full size
+    pub fn reconstruct_into(&self, mut dst: MatMut<f32>, parallelism: Parallelism) {
+        let span = span("reconstruct");
+        let (m, n) = (self.u.nrows(), self.v.nrows());
+        assert!(
+            dst.nrows() == m && dst.ncols() == n,
+            "destination must be {}x{}, got {}x{}",
+            m,
+            n,
+            dst.nrows(),
+            dst.ncols()
+        );
+
+        let us = self.scaled_u();
+        for j0 in (0..n).step_by(COLUMN_BLOCK) {
+            let cols = COLUMN_BLOCK.min(n - j0);
+            matmul(
+                dst.rb_mut().submatrix_mut(0, j0, m, cols),
+                us.as_ref(),
+                self.v.as_ref().subrows(j0, cols).transpose(),
+                None,
+                1.0,
+                parallelism,
+            );
+        }
+
+        span.finish(m, n);
+    }
+
+    // Scales the columns of U by the singular values rather than multiplying by diag(S)
+    fn scaled_u(&self) -> Mat<f32> {
+        Mat::from_fn(self.u.nrows(), self.rank(), |i, j| {
+            self.u.read(i, j) * self.s[j]
+        })
+    }
+
+    // Box-filtered downscale of the reconstruction so that neither side exceeds `max_dim`.
+    // Averaging blocks of rows of the matrix is the same as averaging those rows of U (and
+    // likewise for columns and V), so only the small factors are touched before the product.
+    pub fn thumbnail(&self, max_dim: usize) -> Mat<f32> {
+        let (m, n) = (self.u.nrows(), self.v.nrows());
+        let step = m.max(n).div_ceil(max_dim.max(1)).max(1);
+
+        let shrink = |factor: &Mat<f32>| {
+            let rows = factor.nrows().div_ceil(step);
+            Mat::from_fn(rows, self.rank(), |i, j| {
+                let block = i * step..((i + 1) * step).min(factor.nrows());
+                let len = block.len() as f32;
+                block.map(|r| factor.read(r, j)).sum::<f32>() / len
+            })
+        };
+
+        SvdFactors {
+            u: shrink(&self.u),
+            s: self.s.clone(),
+            v: shrink(&self.v),
+            energy: self.energy,
+        }
+        .reconstruct()
+    }
+
+    // Factors with `s[k]` scaled by `boost[k]`; pairs past the end of `boost` are unchanged.
+    // Gains above 1 on the middle of the spectrum sharpen, emphasizing structure the leading
+    // pairs only outline, while the energy still refers to the original matrix.
+    pub fn enhanced(&self, boost: &[f32]) -> SvdFactors {
+        let s = self
+            .s
+            .iter()
+            .enumerate()
+            .map(|(k, &x)| x * boost.get(k).copied().unwrap_or(1.0))
+            .collect();
+        SvdFactors { s, ..self.clone() }
+    }
+
+    // Rank-`rank()` factors of `mat`, a matrix similar to the one these factors came from (the
+    // next frame of a video, say), by block power iteration from this V instead of a full SVD.
+    // Each iteration costs two products with `mat`; one or two suffice when little changed.
+    pub fn warm_start(
+        &self,
+        mat: MatRef<f32>,
+        iterations: usize,
+    ) -> Result<SvdFactors, SvdApproxError> {
+        let (m, n) = (self.u.nrows(), self.v.nrows());
+        if (mat.nrows(), mat.ncols()) != (m, n) {
+            return Err(SvdApproxError::ShapeMismatch(
+                (m, n),
+                (mat.nrows(), mat.ncols()),
+            ));
+        }
+
+        let norm = mat.norm_l2();
+        if self.rank() == 0 {
+            return Ok(SvdFactors {
+                energy: norm * norm,
+                ..self.clone()
+            });
+        }
+
+        let mut q = orthonormalize(product(mat, self.v.as_ref()));
+        for _ in 0..iterations {
+            let z = orthonormalize(product(mat.transpose(), q.as_ref()));
+            q = orthonormalize(product(mat, z.as_ref()));
+        }
+
+        // `mat` restricted to the subspace is only `rank x n`, so its SVD is cheap
+        let small = svd(product(q.transpose(), mat).as_ref(), SvdBackend::default())?;
+
+        Ok(SvdFactors {
+            u: product(q.as_ref(), small.u.as_ref()),
+            s: small.s,
+            v: small.v,
+            energy: norm * norm,
+        })
+    }
+
+    // Rank 0 keeps no singular pairs but, as `compress` does, the mean of the reconstruction
+    // (that of the matrix itself for a full decomposition), as one constant pair
+    pub fn truncate(&self, rank: usize, bad: bool) -> Result<SvdFactors, SvdApproxError> {
+        let k = self.rank();
+        if rank > k {
+            return Err(SvdApproxError::InvalidRank(k, rank));
+        }
+        if rank == 0 {
+            let (m, n) = (self.u.nrows(), self.v.nrows());
+            let sum: f64 = (0..k)
+                .map(|j| {
+                    let u: f64 = self.u.col_as_slice(j).iter().map(|&x| x as f64).sum();
+                    let v: f64 = self.v.col_as_slice(j).iter().map(|&x| x as f64).sum();
+                    self.s[j] as f64 * u * v
+                })
+                .sum();
+            let mean = if m * n > 0 { sum / (m * n) as f64 } else { 0.0 };
+            return Ok(mean_factors(m, n, mean as f32, self.energy));
+        }
+
+        // If `bad` is false, apply the Eckart-Young-Mirsky theorem to get the best low-rank
+        // approximation, using the `rank` largest singular values and corresponding singular
+        // vectors. Otherwise, use the smallest singular pairs to get the worst approximation.
+        let start = if bad { k - rank } else { 0 };
+
+        Ok(SvdFactors {
+            u: self.u.as_ref().subcols(start, rank).to_owned(),
+            s: self.s[start..start + rank].to_vec(),
+            v: self.v.as_ref().subcols(start, rank).to_owned(),
+            energy: self.energy,
+        })
+    }
+}
+
+// Energy the effective rank shown by `Display` for `SvdFactors` retains
+const DISPLAY_ENERGY: f32 = 0.99;
+
+impl std::fmt::Display for SvdFactors {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // Truncated factors may fall short, in which case it lies beyond their rank
+        let effective = match self.effective_rank(DISPLAY_ENERGY) {
+            Some(rank) => rank.to_string(),
+            None => format!(">{}", self.rank()),
+        };
+        write!(
+            f,
+            "SVD factors {}x{}, rank {} of {}, effective rank {} ({}% energy), {:.2}% energy \
+             retained",
+            self.v.nrows(),
+            self.u.nrows(),
+            self.rank(),
+            self.max_rank(),
+            effective,
+            100.0 * DISPLAY_ENERGY,
+            100.0 * self.retained_energy()
+        )
+    }
+}
+
+pub(crate) fn product(a: MatRef<f32>, b: MatRef<f32>) -> Mat<f32> {
+    let mut out = Mat::zeros(a.nrows(), b.ncols());
+    matmul(out.as_mut(), a, b, None, 1.0, get_global_parallelism());
+    out
+}
+
+// Modified Gram-Schmidt with one re-orthogonalization pass; columns that vanish (a subspace
+// larger than the matrix's rank) are left zero
+pub(crate) fn orthonormalize(q: Mat<f32>) -> Mat<f32> {
+    let mut cols: Vec<Vec<f32>> = (0..q.ncols()).map(|j| q.col_as_slice(j).to_vec()).collect();
+
+    for j in 0..cols.len() {
+        let (done, rest) = cols.split_at_mut(j);
+        let col = &mut rest[0];
+
+        for _ in 0..2 {
+            for prev in done.iter() {
+                let dot: f32 = prev.iter().zip(col.iter()).map(|(a, b)| a * b).sum();
+                col.iter_mut().zip(prev).for_each(|(x, p)| *x -= dot * p);
+            }
+        }
+
+        let norm = col.iter().map(|x| x * x).sum::<f32>().sqrt();
+        let scale = if norm > f32::EPSILON { 1.0 / norm } else { 0.0 };
+        col.iter_mut().for_each(|x| *x *= scale);
+    }
+
+    Mat::from_fn(q.nrows(), q.ncols(), |i, j| cols[j][i])
+}
+
+// The 8-bit image of one to four channels' factors, built without ever holding a full f32
+// reconstruction: each block of columns is reconstructed, quantized into the pixel buffer, and
+// its scratch reused for the next, so peak memory is the pixels plus one block per channel.
+// Rank-0 factors hold the channel mean (see `SvdFactors::truncate`), so they give it here
+// just as `compress` does. `None` for other channel counts or factors of differing shapes.
+pub fn factors_to_image(factors: &[SvdFactors]) -> Option<DynamicImage> {
+    let first = factors.first()?;
+    let (m, n) = (first.u.nrows(), first.v.nrows());
+    let channels = factors.len();
+    if channels > 4
+        || factors
+            .iter()
+            .any(|factors| factors.u.nrows() != m || factors.v.nrows() != n)
+    {
+        return None;
+    }
+
+    let span = span("reconstruct");
+    let scaled: Vec<Mat<f32>> = factors.iter().map(SvdFactors::scaled_u).collect();
+    let mut block = Mat::zeros(m, COLUMN_BLOCK.min(n));
+    let mut column = vec![0u8; m];
+    let mut buf = vec![0u8; m * n * channels];
+
+    for j0 in (0..n).step_by(COLUMN_BLOCK) {
+        let cols = COLUMN_BLOCK.min(n - j0);
+        for (c, (factors, us)) in factors.iter().zip(&scaled).enumerate() {
+            matmul(
+                block.as_mut().submatrix_mut(0, 0, m, cols),
+                us.as_ref(),
+                factors.v.as_ref().subrows(j0, cols).transpose(),
+                None,
+                1.0,
+                get_global_parallelism(),
+            );
+            for b in 0..cols {
+                quantize(block.col_as_slice(b), &mut column);
+                for (i, &x) in column.iter().enumerate() {
+                    buf[(i * n + j0 + b) * channels + c] = x;
+                }
+            }
+        }
+    }
+    span.finish(m, n);
+
+    let (width, height) = (n as u32, m as u32);
+    Some(match channels {
+        1 => DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, buf)?),
+        2 => DynamicImage::ImageLumaA8(ImageBuffer::from_raw(width, height, buf)?),
+        3 => DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, buf)?),
+        _ => DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, height, buf)?),
+    })
+}
+
+// Expects `s` in descending order, as the SVD returns it
+fn numeric_rank(s: &[f32], tol: f32) -> usize {
+    let max = s.first().copied().unwrap_or(0.0);
+    s.iter().take_while(|&&x| x > tol * max).count()
+}
+
+// `complete` means `s` is the full spectrum, in which case it always reaches the target, even
+// when rounding leaves the running sum a hair short of `total`
+pub(crate) fn effective_rank(s: &[f32], total: f32, energy: f32, complete: bool) -> Option<usize> {
+    let target = energy as f64 * total as f64;
+    let mut sum = 0.0f64;
+
+    for (r, x) in s.iter().enumerate() {
+        if sum >= target {
+            return Some(r);
+        }
+        sum += (x * x) as f64;
+    }
+
+    (complete || sum >= target).then_some(s.len())
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RankWeighting {
+    // Every channel's squared error counts the same
+    #[default]
+    Energy,
+    // Rec. 601 luma weights on the first three (RGB) channels and 1/3 on any others, favoring
+    // green and red, to which the eye is most sensitive
+    Luma,
+    Custom(Vec<f32>),
+}
+
+impl RankWeighting {
+    // One weight per channel; `Custom` must give exactly that many
+    fn weights(&self, channels: usize) -> Result<Vec<f32>, SvdApproxError> {
+        Ok(match self {
+            RankWeighting::Energy => vec![1.0; channels],
+            RankWeighting::Luma if channels >= 3 => (0..channels)
+                .map(|k| {
+                    let luma = LumaWeights::Rec601.coefficients();
+                    luma.get(k).copied().unwrap_or(1.0 / 3.0)
+                })
+                .collect(),
+            RankWeighting::Luma => vec![1.0; channels],
+            RankWeighting::Custom(weights) if weights.len() == channels => weights.clone(),
+            RankWeighting::Custom(weights) => {
+                return Err(SvdApproxError::ChannelCountMismatch(
+                    channels,
+                    weights.len(),
+                ));
+            }
+        })
+    }
+}
+
+// Splits `total` singular pairs across channels by repeatedly taking the pair that removes the
+// most weighted squared error, i.e. the largest `weight * s^2` left over all spectra. For a
+// fixed total this minimizes the weighted Frobenius error of the whole image.
+fn allocate_ranks(
+    spectra: &[&[f32]],
+    weights: &[f32],
+    total: usize,
+) -> Result<Vec<usize>, SvdApproxError> {
+    let available = spectra.iter().map(|s| s.len()).sum();
+    if total > available {
+        return Err(SvdApproxError::InvalidRank(available, total));
+    }
+
+    let mut pairs: Vec<(f32, usize)> = spectra
+        .iter()
+        .zip(weights)
+        .enumerate()
+        .flat_map(|(k, (s, w))| s.iter().map(move |x| (w * x * x, k)))
+        .collect();
+    pairs.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let mut ranks = vec![0; spectra.len()];
+    pairs.iter().take(total).for_each(|&(_, k)| ranks[k] += 1);
+    Ok(ranks)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum QualityTarget {
+    Rank(usize),
+    // Fraction in [0, 1] of every channel's energy to retain
+    Energy(f32),
+    // Minimum PSNR in dB, predicted from the discarded singular values before rounding to u8
+    Psnr(f32),
+}
+
+impl QualityTarget {
+    // Smallest rank meeting the target given every channel's full factors
+    fn rank(self, factors: &[SvdFactors], k: usize) -> Result<usize, SvdApproxError> {
+        match self {
+            QualityTarget::Rank(rank) if rank > k => Err(SvdApproxError::InvalidRank(k, rank)),
+            QualityTarget::Rank(rank) => Ok(rank),
+            QualityTarget::Energy(energy) => Ok(factors
+                .iter()
+                .map(|f| effective_rank(&f.s, f.energy, energy, true).unwrap_or(k))
+                .max()
+                .unwrap_or(0)),
+            QualityTarget::Psnr(psnr) => {
+                // By Eckart-Young-Mirsky, the squared error of a truncation is the energy of
+                // the singular values it drops
+                let pixels = factors
+                    .iter()
+                    .map(|f| f.u.nrows() * f.v.nrows())
+                    .sum::<usize>();
+                let budget = 255.0f64.powi(2) / 10f64.powf(psnr as f64 / 10.0) * pixels as f64;
+                let mut tail: f64 = factors
+                    .iter()
+                    .flat_map(|f| &f.s)
+                    .map(|&x| x as f64 * x as f64)
+                    .sum();
+
+                for rank in 0..k {
+                    if tail <= budget {
+                        return Ok(rank);
+                    }
+                    tail -= factors
+                        .iter()
+                        .map(|f| f.s[rank] as f64 * f.s[rank] as f64)
+                        .sum::<f64>();
+                }
+                Ok(k)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SvdBackend {
+    // faer's divide-and-conquer bidiagonal SVD. Its results are spot-checked (see
+    // `is_accurate`), and those failing the check are recomputed with `Jacobi`.
+    #[default]
+    Faer,
+    // One-sided Jacobi in double precision: several times slower, but small singular values
+    // come out with full relative accuracy, which matters when analysing the spectrum itself
+    Jacobi,
+}
+
+// Rank of the alpha plane of a wrapper that has one, relative to the color planes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AlphaRank {
+    // Alpha is compressed like any other channel
+    #[default]
+    Same,
+    // Alpha is kept exactly
+    Lossless,
+    // Masks are mostly flat with sharp edges, which a low rank rarely captures: starved, the
+    // edges ring into halos. A typical mask needs only a few pairs, or its full rank.
+    Rank(usize),
+}
+
+// Convergence settings of the SVD, passed straight to faer. The defaults are the machine
+// precision of f32. A larger `epsilon` lets the iterations stop sooner, but faer converges fast
+// at any setting, so on typical images loosening it saves little time and can lose a lot of
+// accuracy: check the result (e.g. with `Metrics::mse`) before relying on it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SvdTolerance {
+    // Relative precision at which a singular value counts as converged
+    pub epsilon: f32,
+    // Magnitude below which values are treated as zero
+    pub zero_threshold: f32,
+}
+
+impl Default for SvdTolerance {
+    fn default() -> Self {
+        SvdTolerance {
+            epsilon: f32::EPSILON,
+            zero_threshold: f32::MIN_POSITIVE,
+        }
+    }
+}
+
+pub(crate) fn svd(mat: MatRef<f32>, backend: SvdBackend) -> Result<SvdFactors, SvdApproxError> {
+    svd_with(mat, backend, SvdTolerance::default())
+}
+
+pub(crate) fn svd_with(
+    mat: MatRef<f32>,
+    backend: SvdBackend,
+    tolerance: SvdTolerance,
+) -> Result<SvdFactors, SvdApproxError> {
+    let span = span("svd");
+    let factors = match backend {
+        SvdBackend::Faer => svd_faer(mat, tolerance).map(|factors| {
+            if is_accurate(mat, &factors) {
+                factors
+            } else {
+                jacobi_svd(mat, tolerance)
+            }
+        }),
+        SvdBackend::Jacobi => Ok(jacobi_svd(mat, tolerance)),
+    };
+    span.finish(mat.nrows(), mat.ncols());
+    factors
+}
+
+// Error allowed by `is_accurate`, relative to the largest singular value; f32 round-off over
+// thousands of terms stays well below it
+const ACCURACY_TOL: f32 = 1e-3;
+// Singular pairs checked by `is_accurate`, spread evenly over the spectrum
+const ACCURACY_SAMPLES: usize = 16;
+
+// Cheap test of a full thin SVD that catches faer's occasional loss of accuracy, which leaves
+// U and V orthonormal but not matching `mat`: the spectrum's energy must equal the squared
+// norm of `mat`, and a sample of pairs must satisfy `A v = s u` with unit, mutually orthogonal
+// singular vectors. Costs `ACCURACY_SAMPLES` matrix-vector products.
+fn is_accurate(mat: MatRef<f32>, factors: &SvdFactors) -> bool {
+    let s = faer_core::mat::from_column_major_slice::<f32>(&factors.s, factors.rank(), 1);
+    is_accurate_parts(mat, factors.u.as_ref(), s, factors.v.as_ref())
+}
+
+// `is_accurate` on factors held as views, `s` being a column, such as those carved out of an
+// arena
+pub(crate) fn is_accurate_parts(
+    mat: MatRef<f32>,
+    u: MatRef<f32>,
+    s: MatRef<f32>,
+    v: MatRef<f32>,
+) -> bool {
+    let k = s.nrows();
+    if k == 0 {
+        return true;
+    }
+    let max = s.read(0, 0);
+    if !max.is_finite() {
+        return false;
+    }
+    if max == 0.0 {
+        return true;
+    }
+
+    let norm = mat.norm_l2() as f64;
+    let energy: f64 = (0..k)
+        .map(|j| s.read(j, 0) as f64 * s.read(j, 0) as f64)
+        .sum();
+    if (energy - norm * norm).abs() > ACCURACY_TOL as f64 * norm * norm {
+        return false;
+    }
+
+    let samples: Vec<usize> = (0..ACCURACY_SAMPLES.min(k))
+        .map(|i| i * (k - 1) / (ACCURACY_SAMPLES.min(k) - 1).max(1))
+        .collect();
+    let dot = |a: MatRef<f32>, b: MatRef<f32>| -> f32 {
+        (0..a.nrows()).map(|i| a.read(i, 0) * b.read(i, 0)).sum()
+    };
+
+    for (idx, &j) in samples.iter().enumerate() {
+        let (uj, vj, sj) = (u.subcols(j, 1), v.subcols(j, 1), s.read(j, 0));
+        let av = product(mat, vj);
+        let residual = (0..av.nrows())
+            .map(|i| (av.read(i, 0) - sj * uj.read(i, 0)).powi(2))
+            .sum::<f32>()
+            .sqrt();
+        if residual > ACCURACY_TOL * max {
+            return false;
+        }
+
+        for &i in &samples[..=idx] {
+            let expected = if i == j { 1.0 } else { 0.0 };
+            if (dot(u.subcols(i, 1), uj) - expected).abs() > ACCURACY_TOL
+                || (dot(v.subcols(i, 1), vj) - expected).abs() > ACCURACY_TOL
+            {
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
+// Multiply by 1.5 to allocate a bit more space for the PodStack
+fn svd_buffer_size(stack_req: faer_core::dyn_stack::StackReq) -> usize {
+    (1.5 * stack_req.size_bytes() as f32) as usize
+}
+
+// Peak heap use of `svdapprox_with` on an `m x n` channel: the SVD workspace and factors, the
+// truncated factors, the scaled U and the reconstructed matrix
+fn channel_peak_memory(m: usize, n: usize, rank: usize) -> Result<usize, SvdApproxError> {
+    let k = m.min(n);
+    let stack_req = compute_svd_req::<f32>(
+        m,
+        n,
+        ComputeVectors::Thin,
+        ComputeVectors::Thin,
+        Parallelism::None,
+        SvdParams::default(),
+    )
+    .map_err(|_| SvdApproxError::ComputeReqFailed)?;
+    let floats = (m + n + 1) * k + (2 * m + n) * rank + m * n;
+
+    Ok(svd_buffer_size(stack_req) + floats * size_of::<f32>())
+}
+
+// Peak heap use of each of `channels` planes of `m x n`, the one at `alpha` being alpha and
+// compressed at `alpha_rank`, tiled as `options.tiling` says; largest first
+fn channel_peaks(
+    m: usize,
+    n: usize,
+    channels: usize,
+    alpha: Option<usize>,
+    options: &CompressOptions,
+) -> Result<Vec<usize>, SvdApproxError> {
     let k = m.min(n);
+    let plane = m * n * size_of::<f32>();
 
-    if rank <= 0 || rank > k {
-        return Err(SvdApproxError::InvalidRank(k, rank));
+    let channel = |rank: usize| -> Result<usize, SvdApproxError> {
+        let (tm, tn) = options.tiling.map_or((m, n), |tiling| {
+            let side = tiling.size.max(1) + 2 * tiling.overlap;
+            (side.min(m), side.min(n))
+        });
+        let mut peak = channel_peak_memory(tm, tn, rank.min(tm.min(tn)))?;
+        // Tiles are assembled into a sum and a weight matrix of their own
+        if options.tiling.is_some() {
+            peak += 2 * plane;
+        }
+        // Jacobi works on f64 copies of the matrix and of V
+        if options.backend == SvdBackend::Jacobi {
+            let tk = tm.min(tn);
+            peak += (tm * tn + tk * tk) * size_of::<f64>();
+        }
+        Ok(peak)
+    };
+    let mut peaks = (0..channels)
+        .map(|c| {
+            let rank = match options.alpha_rank {
+                AlphaRank::Lossless if alpha == Some(c) => k,
+                AlphaRank::Rank(rank) if alpha == Some(c) => rank,
+                _ => options.rank,
+            };
+            channel(rank)
+        })
+        .collect::<Result<Vec<_>, SvdApproxError>>()?;
+    peaks.sort_unstable_by(|a, b| b.cmp(a));
+    Ok(peaks)
+}
+
+// Tiling `compress_with` falls back to when a single untiled channel of `m x n` planes would
+// exceed `memory_budget`: the largest tiles, halving from half the longer side, whose channel
+// fits. Tiles stay at least as large as the rank, and `None` means no fallback is needed, set
+// or possible.
+fn budget_tiling(
+    m: usize,
+    n: usize,
+    channels: usize,
+    alpha: Option<usize>,
+    options: &CompressOptions,
+) -> Result<Option<Tiling>, SvdApproxError> {
+    let Some(budget) = options.memory_budget.filter(|_| options.tiling.is_none()) else {
+        return Ok(None);
+    };
+    let fits = |tiling: Option<Tiling>| -> Result<bool, SvdApproxError> {
+        let options = CompressOptions {
+            tiling,
+            ..options.clone()
+        };
+        let peaks = channel_peaks(m, n, channels, alpha, &options)?;
+        Ok(peaks.first().copied().unwrap_or(0) <= budget)
+    };
+    if fits(None)? {
+        return Ok(None);
     }
 
-    if rank == k {
-        return Ok(mat.to_owned());
+    let mut size = m.max(n) / 2;
+    while size >= options.rank.max(1) {
+        if fits(Some(Tiling::new(size)))? {
+            return Ok(Some(Tiling::new(size)));
+        }
+        size /= 2;
+    }
+    Ok(None)
+}
+
+// `options` with the tiling of `budget_tiling`, if it has one
+fn budget_options(
+    m: usize,
+    n: usize,
+    channels: usize,
+    alpha: Option<usize>,
+    options: &CompressOptions,
+) -> Result<Option<CompressOptions>, SvdApproxError> {
+    Ok(
+        budget_tiling(m, n, channels, alpha, options)?.map(|tiling| CompressOptions {
+            tiling: Some(tiling),
+            ..options.clone()
+        }),
+    )
+}
+
+// Peak heap use of `compress_with` on `channels` planes of `width x height`, the one at `alpha`
+// being alpha, beyond the input itself
+fn peak_memory(
+    width: usize,
+    height: usize,
+    channels: usize,
+    alpha: Option<usize>,
+    options: &CompressOptions,
+) -> Result<usize, SvdApproxError> {
+    let (n, m) = options
+        .resize
+        .map_or((width, height), |resize| (resize.width, resize.height));
+    let k = m.min(n);
+    if options.rank > k {
+        return Err(SvdApproxError::InvalidRank(k, options.rank));
     }
+    let plane = m * n * size_of::<f32>();
+
+    // Whole-image copies made before any SVD: resized, premultiplied and normalized
+    let copies = [
+        options.resize.is_some(),
+        options.premultiply && alpha.is_some(),
+        options.normalize,
+    ]
+    .into_iter()
+    .filter(|&copy| copy)
+    .count();
+
+    let tiled = budget_options(m, n, channels, alpha, options)?;
+    let options = tiled.as_ref().unwrap_or(options);
+    let peaks = channel_peaks(m, n, channels, alpha, options)?;
+
+    // Unless channels run one at a time, any number may be in flight: even a single worker
+    // picks up other channels while it waits inside a parallel reconstruction. Each finished
+    // channel holds only its output.
+    let sequential = options.deterministic
+        || options
+            .memory_budget
+            .is_some_and(|budget| peaks.first().is_some_and(|&peak| peak * channels > budget));
+    let concurrent = if sequential { 1 } else { channels };
+
+    Ok(copies * channels * plane
+        + peaks.iter().take(concurrent).sum::<usize>()
+        + channels.saturating_sub(concurrent) * plane)
+}
+
+// Predicts the peak memory `compress_with` will need on a `width x height` image of `color`,
+// beyond the input image itself: SVD workspaces, factors and reconstructions for the channels
+// it runs at once, outputs of the finished ones, and any copies its options make. Servers can
+// call this with the dimensions from an image header to refuse or queue jobs up front.
+pub fn estimate_peak_memory(
+    width: usize,
+    height: usize,
+    color: ColorType,
+    options: &CompressOptions,
+) -> Result<usize, SvdApproxError> {
+    let channels = color.channel_count() as usize;
+    let alpha = color.has_alpha().then(|| channels - 1);
+    peak_memory(width, height, channels, alpha, options)
+}
+
+fn svd_faer(mat: MatRef<f32>, tolerance: SvdTolerance) -> Result<SvdFactors, SvdApproxError> {
+    let m = mat.nrows();
+    let n = mat.ncols();
+    let k = m.min(n);
 
     let mut s = Mat::zeros(k, 1);
     let s_mut = s.as_mut();
-    let mut u = Mat::zeros(m, m);
+    let mut u = Mat::zeros(m, k);
     let u_mut = u.as_mut();
-    let mut v = Mat::zeros(n, n);
+    let mut v = Mat::zeros(n, k);
     let v_mut = v.as_mut();
 
     let parallelism = Parallelism::None;
@@ -48,119 +915,1224 @@ fn svdapprox(mat: MatRef<f32>, rank: usize, bad: bool) -> Result<Mat<f32>, SvdAp
     let stack_req = compute_svd_req::<f32>(
         m,
         n,
-        ComputeVectors::Full,
-        ComputeVectors::Full,
+        ComputeVectors::Thin,
+        ComputeVectors::Thin,
         parallelism,
         params,
     )
     .map_err(|_| SvdApproxError::ComputeReqFailed)?;
 
-    // Multiply by 1.5 to allocate a bit more space for the PodStack
-    let required_size = (1.5 * stack_req.size_bytes() as f32) as usize;
+    let required_size = svd_buffer_size(stack_req);
     let mut buffer = vec![0u8; required_size];
     let stack = PodStack::new(&mut buffer);
 
-    // `compute_svd` automatically sorts the singular values in descending order
-    compute_svd(
+    // `compute_svd_custom_epsilon` automatically sorts the singular values in descending order
+    compute_svd_custom_epsilon(
         mat,
         s_mut,
         Some(u_mut),
         Some(v_mut),
+        tolerance.epsilon,
+        tolerance.zero_threshold,
         parallelism,
         stack,
         params,
     );
 
-    // If `bad` is false, apply the Eckart-Young-Mirsky theorem to get the best low-rank
-    // approximation, using the `rank` largest singular values and corresponding singular vectors.
-    // Otherwise, use the smallest singular pairs to get the worst low-rank approximation.
-    let (s_new, u_new, v_new) = if bad {
-        (
-            Mat::from_fn(
-                rank,
-                rank,
-                |i, j| {
-                    if i == j { s[(k - rank + i, 0)] } else { 0.0 }
-                },
-            ),
-            u.as_ref().submatrix(0, m - rank, m, rank),
-            v.as_ref().submatrix(0, n - rank, n, rank),
-        )
-    } else {
-        (
-            Mat::from_fn(rank, rank, |i, j| if i == j { s[(i, 0)] } else { 0.0 }),
-            u.as_ref().submatrix(0, 0, m, rank),
-            v.as_ref().submatrix(0, 0, n, rank),
-        )
-    };
+    let s: Vec<f32> = (0..k).map(|i| s[(i, 0)]).collect();
+    let energy = s.iter().map(|x| x * x).sum();
 
-    Ok(u_new * s_new * v_new.transpose())
+    Ok(SvdFactors { u, s, v, energy })
 }
 
-pub trait Compressible {
-    type Error;
-    fn compress(&self, rank: usize) -> Result<Self, Self::Error>
-    where
-        Self: Sized;
-    fn compress_bad(&self, rank: usize) -> Result<Self, Self::Error>
-    where
-        Self: Sized;
+// Singular values only, which skips computing U and V
+pub(crate) fn singular_values(mat: MatRef<f32>) -> Result<(Vec<f32>, f32), SvdApproxError> {
+    let (m, n) = (mat.nrows(), mat.ncols());
+    let mut s = Mat::zeros(m.min(n), 1);
+    let parallelism = Parallelism::None;
+    let params = SvdParams::default();
+
+    let stack_req = compute_svd_req::<f32>(
+        m,
+        n,
+        ComputeVectors::No,
+        ComputeVectors::No,
+        parallelism,
+        params,
+    )
+    .map_err(|_| SvdApproxError::ComputeReqFailed)?;
+    let mut buffer = vec![0u8; svd_buffer_size(stack_req)];
+
+    compute_svd(
+        mat,
+        s.as_mut(),
+        None,
+        None,
+        parallelism,
+        PodStack::new(&mut buffer),
+        params,
+    );
+
+    let s: Vec<f32> = (0..m.min(n)).map(|i| s[(i, 0)]).collect();
+    let energy = s.iter().map(|x| x * x).sum();
+    Ok((s, energy))
 }
 
-impl Compressible for GreyImageWrapper {
-    type Error = SvdApproxError;
+// Collects per-channel results, reporting every failure with its channel index rather than
+// just the first; single-channel errors are passed through as they are
+fn collect_channels<T>(results: Vec<Result<T, SvdApproxError>>) -> Result<Vec<T>, SvdApproxError> {
+    let channels = results.len();
+    let mut values = Vec::with_capacity(channels);
+    let mut errors = Vec::new();
 
-    fn compress(&self, rank: usize) -> Result<Self, Self::Error> {
-        let mat = svdapprox(self.mat.as_ref(), rank, false)?;
-        Ok(GreyImageWrapper {
-            mat,
-            width: self.width,
-            height: self.height,
-        })
+    for (channel, result) in results.into_iter().enumerate() {
+        match result {
+            Ok(value) => values.push(value),
+            Err(error) => errors.push(ChannelError { channel, error }),
+        }
     }
 
-    fn compress_bad(&self, rank: usize) -> Result<Self, Self::Error> {
-        let mat = svdapprox(self.mat.as_ref(), rank, true)?;
-        Ok(GreyImageWrapper {
-            mat,
-            width: self.width,
-            height: self.height,
-        })
+    match errors.len() {
+        0 => Ok(values),
+        1 if channels == 1 => Err(errors.pop().unwrap().error),
+        _ => Err(SvdApproxError::ChannelsFailed(errors, channels)),
     }
 }
 
-impl Compressible for RgbImageWrapper {
-    type Error = SvdApproxError;
+fn check_rank(mat: MatRef<f32>, rank: usize) -> Result<(), SvdApproxError> {
+    let k = mat.nrows().min(mat.ncols());
 
-    fn compress(&self, rank: usize) -> Result<Self, Self::Error> {
-        let compressed_mats: [Mat<f32>; 3] = self
-            .mats
-            .par_iter()
-            .map(|mat| svdapprox(mat.as_ref(), rank, false))
-            .collect::<Result<Vec<_>, SvdApproxError>>()?
-            .try_into()
-            .unwrap();
+    if rank > k {
+        return Err(SvdApproxError::InvalidRank(k, rank));
+    }
 
-        Ok(RgbImageWrapper {
-            mats: compressed_mats,
-            width: self.width,
+    Ok(())
+}
+
+// What rank-0 factors hold in place of any singular pair: `mean` everywhere of an `m x n`
+// matrix, as one pair of constant unit vectors, the sign going to U so that `s` stays positive
+pub(crate) fn mean_factors(m: usize, n: usize, mean: f32, energy: f32) -> SvdFactors {
+    let sign = if mean < 0.0 { -1.0 } else { 1.0 };
+    SvdFactors {
+        u: Mat::from_fn(m, 1, |_, _| sign / (m as f32).sqrt()),
+        s: vec![mean.abs() * ((m * n) as f32).sqrt()],
+        v: Mat::from_fn(n, 1, |_, _| 1.0 / (n as f32).sqrt()),
+        energy,
+    }
+}
+
+// The rank-0 "approximation" of a channel: its mean everywhere, rather than the zero matrix
+// that truncating the SVD to no singular pairs would give
+pub(crate) fn dc(mat: MatRef<f32>) -> Mat<f32> {
+    let mean = channel_stats(mat).mean;
+    Mat::from_fn(mat.nrows(), mat.ncols(), |_, _| mean)
+}
+
+// All planes share a shape, so a bad rank is reported once up front rather than per channel
+fn check_planes_rank(mats: &[Mat<f32>], rank: usize) -> Result<(), SvdApproxError> {
+    mats.first()
+        .map_or(Ok(()), |mat| check_rank(mat.as_ref(), rank))
+}
+
+fn svd_factors(mat: MatRef<f32>, rank: usize, bad: bool) -> Result<SvdFactors, SvdApproxError> {
+    check_rank(mat, rank)?;
+
+    // No singular pairs are kept, so skip the SVD; the energy is just the squared norm
+    if rank == 0 {
+        let (m, n) = (mat.nrows(), mat.ncols());
+        let norm = mat.norm_l2();
+        let mean = if m * n > 0 {
+            channel_stats(mat).mean
+        } else {
+            0.0
+        };
+        return Ok(mean_factors(m, n, mean, norm * norm));
+    }
+
+    svd(mat, SvdBackend::default())?.truncate(rank, bad)
+}
+
+fn svdapprox_with(
+    mat: MatRef<f32>,
+    options: &CompressOptions,
+    parallelism: Parallelism,
+) -> Result<Mat<f32>, SvdApproxError> {
+    check_rank(mat, options.rank)?;
+
+    if options.rank == 0 {
+        return Ok(dc(mat));
+    }
+    if options.rank == mat.nrows().min(mat.ncols()) {
+        return Ok(mat.to_owned());
+    }
+
+    let _reservation = reserve(channel_peak_memory(mat.nrows(), mat.ncols(), options.rank)?)?;
+    Ok(svd_with(mat, options.backend, options.tolerance)?
+        .truncate(options.rank, options.bad)?
+        .reconstruct_with(parallelism))
+}
+
+fn svdapprox(mat: MatRef<f32>, rank: usize, bad: bool) -> Result<Mat<f32>, SvdApproxError> {
+    let options = CompressOptions {
+        bad,
+        ..CompressOptions::new(rank)
+    };
+    svdapprox_with(mat, &options, get_global_parallelism())
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Resize {
+    pub width: usize,
+    pub height: usize,
+    pub filter: FilterType,
+}
+
+#[derive(Clone, Debug)]
+pub struct CompressOptions {
+    pub rank: usize,
+    pub bad: bool,
+    // Downscaling happens on the float matrices before the SVD, so no precision is lost to u8
+    pub resize: Option<Resize>,
+    // Zero-centers and scales each channel to unit range around the SVD, undoing it afterwards
+    pub normalize: bool,
+    // Processes channels sequentially and reconstructs without multithreading, so identical
+    // inputs give bit-identical outputs from run to run
+    pub deterministic: bool,
+    // Wall-clock limit, checked as each channel finishes (a single SVD is not interruptible)
+    pub time_budget: Option<Duration>,
+    // Limit in bytes on the working memory, as `estimate_peak_memory` counts it; channels are
+    // processed one at a time when running them concurrently would not fit. Without `tiling`,
+    // a channel too large to fit whole is split into the largest tiles that do, which changes
+    // the output as tiling does; compression fails upfront if even that doesn't fit.
+    pub memory_budget: Option<usize>,
+    // Convergence settings passed to the SVD
+    pub tolerance: SvdTolerance,
+    // Algorithm computing each channel's SVD
+    pub backend: SvdBackend,
+    // Splits each channel into tiles compressed separately, at `rank` each
+    pub tiling: Option<Tiling>,
+    // Multiplies the colors by alpha around the SVD. Otherwise the colors of transparent
+    // pixels, invisible but approximated together with their neighbors, fringe visible edges.
+    // Has no effect on images without alpha.
+    pub premultiply: bool,
+    pub alpha_rank: AlphaRank,
+}
+
+impl CompressOptions {
+    pub fn new(rank: usize) -> Self {
+        CompressOptions {
+            rank,
+            bad: false,
+            resize: None,
+            normalize: false,
+            deterministic: false,
+            time_budget: None,
+            memory_budget: None,
+            tolerance: SvdTolerance::default(),
+            backend: SvdBackend::default(),
+            tiling: None,
+            premultiply: false,
+            alpha_rank: AlphaRank::default(),
+        }
+    }
+}
+
+pub trait Compressible {
+    type Error;
+    fn compress(&self, rank: usize) -> Result<Self, Self::Error>
+    where
+        Self: Sized;
+    fn compress_bad(&self, rank: usize) -> Result<Self, Self::Error>
+    where
+        Self: Sized;
+
+    fn compress_with(&self, options: &CompressOptions) -> Result<Self, Self::Error>
+    where
+        Self: Planes + Sized,
+        Self::Error: From<SvdApproxError>,
+    {
+        Ok(compress_channels(self, options, false, None)?.wrapper)
+    }
+
+    // `compress`, overwriting the planes with the reconstruction instead of allocating new
+    // ones, for loops that can't afford two copies of each image. Ranks are checked before any
+    // plane is touched, but a channel failing later leaves the others already compressed.
+    fn compress_in_place(&mut self, rank: usize) -> Result<(), Self::Error>
+    where
+        Self: Planes,
+        Self::Error: From<SvdApproxError>,
+    {
+        Ok(svdapprox_in_place(self.planes_mut(), rank, false)?)
+    }
+
+    fn compress_bad_in_place(&mut self, rank: usize) -> Result<(), Self::Error>
+    where
+        Self: Planes,
+        Self::Error: From<SvdApproxError>,
+    {
+        Ok(svdapprox_in_place(self.planes_mut(), rank, true)?)
+    }
+
+    // `compress_in_place` with the SVD workspace, factors and scaled U of each channel carved
+    // out of `arena` instead of the heap, for hosts that keep a fixed pool for scratch memory;
+    // size it with `arena_size`. faer's matrix kernels still allocate packing buffers of their
+    // own. Channels run one after another on the calling thread, always with the faer backend;
+    // a plane whose SVD fails the accuracy check (which `compress` recomputes with `Jacobi`) is
+    // left as it was and stops the run with `InaccurateSvd`, earlier planes being compressed.
+    // Ranks are checked first, and a too small arena fails before any plane changes.
+    fn compress_in_arena(&mut self, rank: usize, arena: &mut [u8]) -> Result<(), Self::Error>
+    where
+        Self: Planes,
+        Self::Error: From<SvdApproxError>,
+    {
+        Ok(svdapprox_in_arena(self.planes_mut(), rank, false, arena)?)
+    }
+
+    fn compress_bad_in_arena(&mut self, rank: usize, arena: &mut [u8]) -> Result<(), Self::Error>
+    where
+        Self: Planes,
+        Self::Error: From<SvdApproxError>,
+    {
+        Ok(svdapprox_in_arena(self.planes_mut(), rank, true, arena)?)
+    }
+
+    // `estimate_peak_memory` for this image
+    fn estimate_peak_memory(&self, options: &CompressOptions) -> Result<usize, SvdApproxError>
+    where
+        Self: Planes,
+    {
+        let planes = self.planes();
+        let (m, n) = planes
+            .first()
+            .map_or((0, 0), |mat| (mat.nrows(), mat.ncols()));
+        peak_memory(n, m, planes.len(), self.alpha(), options)
+    }
+
+    // Like `compress_with`, but a channel whose compression fails is kept as it was (after any
+    // resize) instead of failing the whole image; the failures are returned alongside.
+    // Errors that don't belong to a single channel, such as an invalid rank, still fail.
+    fn compress_salvaged(&self, options: &CompressOptions) -> Result<Salvaged<Self>, Self::Error>
+    where
+        Self: Planes + Sized,
+        Self::Error: From<SvdApproxError>,
+    {
+        Ok(compress_channels(self, options, true, None)?)
+    }
+
+    // Runs `compress_with` in the background, for frontends that must stay responsive; the
+    // handle reports progress channel by channel and can cancel the job
+    fn compress_spawn(&self, options: &CompressOptions) -> JobHandle<Self>
+    where
+        Self: Planes + Clone + Send + Sync + 'static,
+    {
+        self.compress_spawn_with(options, Priority::default())
+    }
+
+    // Like `compress_spawn`, but jobs of lower `priority` wait to start while this one is
+    // unfinished, so an interactive preview need not wait out a background batch
+    fn compress_spawn_with(&self, options: &CompressOptions, priority: Priority) -> JobHandle<Self>
+    where
+        Self: Planes + Clone + Send + Sync + 'static,
+    {
+        let (wrapper, options) = (self.clone(), options.clone());
+        spawn(self.planes().len(), priority, move |progress| {
+            Ok(compress_channels(&wrapper, &options, false, Some(progress))?.wrapper)
+        })
+    }
+
+    // Keeps `rows` degrees of freedom vertically and `cols` horizontally (see
+    // `anisotropic_approx`) instead of a rank, for strongly directional content such as
+    // documents and barcodes; each budget can be at most that side of the image
+    fn compress_anisotropic(&self, rows: usize, cols: usize) -> Result<Self, Self::Error>
+    where
+        Self: Planes + Sized,
+        Self::Error: From<SvdApproxError>,
+    {
+        let planes = self.planes();
+        if let Some(mat) = planes.first() {
+            let (m, n) = (mat.nrows(), mat.ncols());
+            if rows > m {
+                return Err(SvdApproxError::InvalidRank(m, rows).into());
+            }
+            if cols > n {
+                return Err(SvdApproxError::InvalidRank(n, cols).into());
+            }
+        }
+
+        let mats = planes
+            .par_iter()
+            .map(|mat| anisotropic_approx(mat.as_ref(), rows, cols))
+            .collect();
+        Ok(self.rebuild(mats))
+    }
+
+    // Fast approximation of `compress(rank)` for interactive use: the SVD runs on a copy
+    // subsampled so that its longer side is at most `max_dim`. Call `compress` afterwards for
+    // the exact result.
+    fn compress_preview(&self, rank: usize, max_dim: usize) -> Result<Self, Self::Error>
+    where
+        Self: Planes + Sized,
+        Self::Error: From<SvdApproxError>,
+    {
+        check_planes_rank(self.planes(), rank)?;
+        let mats = collect_channels(
+            self.planes()
+                .par_iter()
+                .map(|mat| preview_approx(mat.as_ref(), rank, max_dim))
+                .collect(),
+        )?;
+
+        Ok(self.rebuild(mats))
+    }
+
+    // Compresses with `total` singular pairs shared among the channels by `allocate_ranks`
+    // rather than `total / channels` each; returns the ranks chosen alongside
+    fn compress_allocated(
+        &self,
+        total: usize,
+        weighting: &RankWeighting,
+    ) -> Result<(Self, Vec<usize>), Self::Error>
+    where
+        Self: Planes + Sized,
+        Self::Error: From<SvdApproxError>,
+    {
+        let planes = self.planes();
+        let weights = weighting.weights(planes.len())?;
+        let factors = collect_channels(
+            planes
+                .par_iter()
+                .map(|mat| svd(mat.as_ref(), SvdBackend::default()))
+                .collect(),
+        )?;
+
+        let spectra: Vec<&[f32]> = factors.iter().map(|f| f.s.as_slice()).collect();
+        let ranks = allocate_ranks(&spectra, &weights, total)?;
+
+        let mats = factors
+            .par_iter()
+            .zip(planes)
+            .zip(&ranks)
+            .map(|((factors, mat), &rank)| {
+                Ok(if rank == 0 {
+                    dc(mat.as_ref())
+                } else {
+                    factors.truncate(rank, false)?.reconstruct()
+                })
+            })
+            .collect::<Result<Vec<_>, SvdApproxError>>()?;
+
+        Ok((self.rebuild(mats), ranks))
+    }
+
+    // Compresses each channel at the smallest rank whose reconstruction is within `epsilon` of
+    // the original at every pixel, for imagery where the worst case matters more than the
+    // average; returns the ranks chosen alongside. Full rank always qualifies, as the image
+    // is then kept exactly.
+    fn compress_max_error(&self, epsilon: f32) -> Result<(Self, Vec<usize>), Self::Error>
+    where
+        Self: Planes + Sized,
+        Self::Error: From<SvdApproxError>,
+    {
+        let results = collect_channels(
+            self.planes()
+                .par_iter()
+                .map(|mat| max_error_approx(mat.as_ref(), epsilon))
+                .collect(),
+        )?;
+
+        let (mats, ranks) = results.into_iter().unzip();
+        Ok((self.rebuild(mats), ranks))
+    }
+}
+
+// Shared by `compress_with` and `compress_salvaged`
+fn compress_channels<W: Planes>(
+    wrapper: &W,
+    options: &CompressOptions,
+    salvage: bool,
+    progress: Option<&Progress>,
+) -> Result<Salvaged<W>, SvdApproxError> {
+    let resized = options
+        .resize
+        .map(|resize| wrapper.resize(resize.width, resize.height, resize.filter));
+    let source = resized.as_ref().unwrap_or(wrapper);
+
+    let alpha = source.alpha().filter(|_| options.premultiply);
+    let premultiplied = alpha.map(|alpha| premultiply(source, alpha));
+    let source = premultiplied.as_ref().unwrap_or(source);
+
+    let normalized = options.normalize.then(|| normalize(source));
+    let source = normalized.as_ref().map_or(source, |(wrapper, _)| wrapper);
+
+    let planes = source.planes();
+    check_planes_rank(planes, options.rank)?;
+    let mut sequential = options.deterministic;
+
+    let (m, n) = (planes[0].nrows(), planes[0].ncols());
+    let tiled = budget_options(m, n, planes.len(), source.alpha(), options)?;
+    let options = tiled.as_ref().unwrap_or(options);
+    if let Some(budget) = options.memory_budget {
+        let peaks = channel_peaks(m, n, planes.len(), source.alpha(), options)?;
+        let required = peaks.first().copied().unwrap_or(0);
+        if required > budget {
+            return Err(SvdApproxError::MemoryBudgetExceeded(required, budget));
+        }
+        sequential |= required * planes.len() > budget;
+    }
+
+    let alpha_options = match (source.alpha(), options.alpha_rank) {
+        (Some(alpha), AlphaRank::Lossless) => {
+            let rank = planes[alpha].nrows().min(planes[alpha].ncols());
+            Some((alpha, rank))
+        }
+        (Some(alpha), AlphaRank::Rank(rank)) => {
+            check_rank(planes[alpha].as_ref(), rank)?;
+            Some((alpha, rank))
+        }
+        _ => None,
+    }
+    .map(|(alpha, rank)| {
+        let options = CompressOptions {
+            rank,
+            ..options.clone()
+        };
+        (alpha, options)
+    });
+
+    let tiles = options.tiling.map(|tiling| {
+        let (rows, cols) = tiling.cuts(planes);
+        (rows, cols, tiling.overlap)
+    });
+    let collector = instrument::current();
+    let start = Instant::now();
+    let over_budget = || match options.time_budget {
+        Some(budget) if start.elapsed() > budget => Err(SvdApproxError::TimeBudgetExceeded(budget)),
+        _ => Ok(()),
+    };
+    // Checked before as well as after, so later sequential channels don't start late
+    let approx = |channel: usize, mat: &Mat<f32>, parallelism| {
+        let _guard = instrument::enter(collector.clone());
+        let options = match &alpha_options {
+            Some((alpha, options)) if *alpha == channel => options,
+            _ => options,
+        };
+        over_budget()?;
+        progress.map_or(Ok(()), Progress::check)?;
+        let approx = match &tiles {
+            Some((rows, cols, overlap)) => map_tiles(mat.as_ref(), rows, cols, *overlap, |tile| {
+                let rank = options.rank.min(tile.nrows().min(tile.ncols()));
+                let options = CompressOptions {
+                    rank,
+                    ..options.clone()
+                };
+                svdapprox_with(tile, &options, parallelism)
+            })?,
+            None => svdapprox_with(mat.as_ref(), options, parallelism)?,
+        };
+        progress.inspect(|progress| progress.channel_done());
+        over_budget().map(|()| approx)
+    };
+
+    let results: Vec<_> = if sequential {
+        let parallelism = if options.deterministic {
+            Parallelism::None
+        } else {
+            get_global_parallelism()
+        };
+        planes
+            .iter()
+            .enumerate()
+            .map(|(channel, mat)| approx(channel, mat, parallelism))
+            .collect()
+    } else {
+        planes
+            .par_iter()
+            .enumerate()
+            .map(|(channel, mat)| approx(channel, mat, get_global_parallelism()))
+            .collect()
+    };
+
+    // A cancelled job fails as a whole, not channel by channel
+    progress.map_or(Ok(()), Progress::check)?;
+
+    let (mats, failures) = if salvage {
+        let mut failures = Vec::new();
+        let mats = results
+            .into_iter()
+            .zip(planes)
+            .enumerate()
+            .map(|(channel, (result, mat))| {
+                result.unwrap_or_else(|error| {
+                    failures.push(ChannelError { channel, error });
+                    mat.to_owned()
+                })
+            })
+            .collect();
+        (mats, failures)
+    } else {
+        (collect_channels(results)?, Vec::new())
+    };
+
+    let mut compressed = source.rebuild(mats);
+    if let Some((_, params)) = &normalized {
+        denormalize(&mut compressed, params);
+    }
+    if let Some(alpha) = alpha {
+        unpremultiply(&mut compressed, alpha);
+    }
+
+    Ok(Salvaged {
+        wrapper: compressed,
+        failures,
+    })
+}
+
+// Linearly interpolates the rows of a factor of a matrix subsampled every `step` rows back to
+// `rows` rows. Interpolating U and V this way makes their product the bilinear upscale of the
+// subsampled approximation, without ever forming it at the small size.
+pub(crate) fn upsample_rows(factor: &Mat<f32>, rows: usize, step: usize) -> Mat<f32> {
+    let last = factor.nrows() - 1;
+
+    Mat::from_fn(rows, factor.ncols(), |i, j| {
+        let (lo, t) = (i / step, (i % step) as f32 / step as f32);
+        let hi = (lo + 1).min(last);
+        (1.0 - t) * factor.read(lo, j) + t * factor.read(hi, j)
+    })
+}
+
+fn max_deviation(a: MatRef<f32>, b: MatRef<f32>) -> f32 {
+    (0..a.ncols())
+        .flat_map(|j| (0..a.nrows()).map(move |i| (a.read(i, j) - b.read(i, j)).abs()))
+        .fold(0.0, f32::max)
+}
+
+// The maximum error need not fall monotonically with the rank, but the search only ever
+// settles on a rank it has checked, so the bound holds regardless
+fn max_error_approx(mat: MatRef<f32>, epsilon: f32) -> Result<(Mat<f32>, usize), SvdApproxError> {
+    let k = mat.nrows().min(mat.ncols());
+    let factors = svd(mat, SvdBackend::default())?;
+    let approx = |rank: usize| -> Result<Mat<f32>, SvdApproxError> {
+        Ok(match rank {
+            0 => dc(mat),
+            _ if rank == k => mat.to_owned(),
+            _ => factors.truncate(rank, false)?.reconstruct(),
+        })
+    };
+
+    let mut best = (approx(k)?, k);
+    let (mut lo, mut hi) = (0, k);
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        let candidate = approx(mid)?;
+        if max_deviation(candidate.as_ref(), mat) <= epsilon {
+            best = (candidate, mid);
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+    Ok(best)
+}
+
+fn preview_approx(
+    mat: MatRef<f32>,
+    rank: usize,
+    max_dim: usize,
+) -> Result<Mat<f32>, SvdApproxError> {
+    check_rank(mat, rank)?;
+
+    if rank == 0 {
+        return Ok(dc(mat));
+    }
+
+    let (m, n) = (mat.nrows(), mat.ncols());
+    let step = m.max(n).div_ceil(max_dim.max(1)).max(1);
+    let sub = Mat::from_fn(m.div_ceil(step), n.div_ceil(step), |i, j| {
+        mat.read(i * step, j * step)
+    });
+
+    // The subsampled matrix may have a smaller rank than requested
+    let factors = svd(sub.as_ref(), SvdBackend::default())?;
+    let factors = factors.truncate(rank.min(factors.rank()), false)?;
+
+    Ok(SvdFactors {
+        u: upsample_rows(&factors.u, m, step),
+        v: upsample_rows(&factors.v, n, step),
+        ..factors
+    }
+    .reconstruct())
+}
+
+// Zero-centers each channel and scales it to unit range, returning the `(offset, scale)` pairs
+// needed to undo it
+fn normalize<W: Planes>(wrapper: &W) -> (W, Vec<(f32, f32)>) {
+    let mut normalized = wrapper.map_planes(|mat| mat.to_owned());
+    let params = normalized
+        .planes_mut()
+        .iter_mut()
+        .map(|mat| {
+            let ChannelStats { min, max, mean, .. } = channel_stats(mat.as_ref());
+            let scale = if max > min { max - min } else { 1.0 };
+            *mat = Mat::from_fn(mat.nrows(), mat.ncols(), |i, j| {
+                (mat.read(i, j) - mean) / scale
+            });
+            (mean, scale)
+        })
+        .collect();
+
+    (normalized, params)
+}
+
+fn premultiply<W: Planes>(wrapper: &W, alpha: usize) -> W {
+    let planes = wrapper.planes();
+    let a = &planes[alpha];
+    let mats = planes
+        .iter()
+        .enumerate()
+        .map(|(k, mat)| {
+            if k == alpha {
+                return mat.to_owned();
+            }
+            Mat::from_fn(mat.nrows(), mat.ncols(), |i, j| {
+                mat.read(i, j) * a.read(i, j).clamp(0.0, 255.0) / 255.0
+            })
+        })
+        .collect();
+    wrapper.rebuild(mats)
+}
+
+// Divides the colors by the approximated alpha. Where that is below one 8-bit step the pixel is
+// invisible and the quotient meaningless, so it is left black; elsewhere the quotient is
+// clamped, since approximation error grows as alpha shrinks.
+fn unpremultiply<W: Planes>(wrapper: &mut W, alpha: usize) {
+    let a = wrapper.planes()[alpha].to_owned();
+    for (k, mat) in wrapper.planes_mut().iter_mut().enumerate() {
+        if k == alpha {
+            continue;
+        }
+        *mat = Mat::from_fn(mat.nrows(), mat.ncols(), |i, j| {
+            let a = a.read(i, j);
+            if a < 1.0 {
+                0.0
+            } else {
+                (mat.read(i, j) * 255.0 / a.min(255.0)).clamp(0.0, 255.0)
+            }
+        });
+    }
+}
+
+fn denormalize<W: Planes>(wrapper: &mut W, params: &[(f32, f32)]) {
+    for (mat, &(offset, scale)) in wrapper.planes_mut().iter_mut().zip(params) {
+        *mat = Mat::from_fn(mat.nrows(), mat.ncols(), |i, j| {
+            mat.read(i, j) * scale + offset
+        });
+    }
+}
+
+pub trait Factorizable {
+    // Truncated SVD of each channel; rank 0 keeps each channel's mean, as `SvdFactors::truncate`
+    // does
+    fn factors(&self, rank: usize) -> Result<Vec<SvdFactors>, SvdApproxError>;
+
+    // Rank-`rank` preview whose longer side is at most `max_dim`, built with
+    // `SvdFactors::thumbnail` rather than a full-size reconstruction
+    fn thumbnail(&self, rank: usize, max_dim: usize) -> Result<Self, SvdApproxError>
+    where
+        Self: Planes + Sized,
+    {
+        let mats = self
+            .factors(rank)?
+            .iter()
+            .map(|factors| factors.thumbnail(max_dim))
+            .collect();
+        Ok(self.rebuild(mats))
+    }
+
+    // Largest `numeric_rank` over the channels, from their full spectra
+    fn numeric_rank(&self, tol: f32) -> Result<usize, SvdApproxError>
+    where
+        Self: Planes,
+    {
+        let ranks = self
+            .planes()
+            .par_iter()
+            .map(|mat| Ok(numeric_rank(&singular_values(mat.as_ref())?.0, tol)))
+            .collect::<Result<Vec<_>, SvdApproxError>>()?;
+        Ok(ranks.into_iter().max().unwrap_or(0))
+    }
+
+    // Factors of each channel by `SvdFactors::warm_start` from `previous`, i.e. the factors of
+    // the previous frame
+    fn factors_warm(
+        &self,
+        previous: &[SvdFactors],
+        iterations: usize,
+    ) -> Result<Vec<SvdFactors>, SvdApproxError>
+    where
+        Self: Planes,
+    {
+        let planes = self.planes();
+        if previous.len() != planes.len() {
+            return Err(SvdApproxError::ChannelCountMismatch(
+                planes.len(),
+                previous.len(),
+            ));
+        }
+
+        collect_channels(
+            planes
+                .par_iter()
+                .zip(previous)
+                .map(|(mat, previous)| previous.warm_start(mat.as_ref(), iterations))
+                .collect(),
+        )
+    }
+
+    // Per-channel ranks `compress_allocated` would use for `total` singular pairs
+    fn allocate_ranks(
+        &self,
+        total: usize,
+        weighting: &RankWeighting,
+    ) -> Result<Vec<usize>, SvdApproxError>
+    where
+        Self: Planes,
+    {
+        let weights = weighting.weights(self.planes().len())?;
+        let spectra = self
+            .planes()
+            .par_iter()
+            .map(|mat| Ok(singular_values(mat.as_ref())?.0))
+            .collect::<Result<Vec<_>, SvdApproxError>>()?;
+        let spectra: Vec<&[f32]> = spectra.iter().map(Vec::as_slice).collect();
+
+        allocate_ranks(&spectra, &weights, total)
+    }
+
+    // One output per target, like a streaming bitrate ladder, all from a single SVD of each
+    // channel; rungs come back in the order of `targets`
+    fn ladder(&self, targets: &[QualityTarget]) -> Result<Vec<Rung<Self>>, SvdApproxError>
+    where
+        Self: Planes + Sized,
+    {
+        let planes = self.planes();
+        let k = planes.first().map_or(0, |mat| mat.nrows().min(mat.ncols()));
+        let factors = collect_channels(
+            planes
+                .par_iter()
+                .map(|mat| svd(mat.as_ref(), SvdBackend::default()))
+                .collect(),
+        )?;
+
+        targets
+            .iter()
+            .map(|&target| {
+                let rank = target.rank(&factors, k)?;
+                let mats = factors
+                    .par_iter()
+                    .zip(planes)
+                    .map(|(factors, mat)| {
+                        Ok(match rank {
+                            0 => dc(mat.as_ref()),
+                            rank if rank == k => mat.clone(),
+                            rank => factors.truncate(rank, false)?.reconstruct(),
+                        })
+                    })
+                    .collect::<Result<Vec<_>, SvdApproxError>>()?;
+
+                Ok(Rung {
+                    target,
+                    rank,
+                    wrapper: self.rebuild(mats),
+                })
+            })
+            .collect()
+    }
+
+    // The inverse of compression: rescales each channel's singular values by `boost` (see
+    // `SvdFactors::enhanced`) and reconstructs at full rank
+    fn enhance(&self, boost: &[f32]) -> Result<Self, SvdApproxError>
+    where
+        Self: Planes + Sized,
+    {
+        let mats = collect_channels(
+            self.planes()
+                .par_iter()
+                .map(|mat| {
+                    Ok(svd(mat.as_ref(), SvdBackend::default())?
+                        .enhanced(boost)
+                        .reconstruct())
+                })
+                .collect(),
+        )?;
+        Ok(self.rebuild(mats))
+    }
+
+    // Smallest rank at which every channel retains at least `energy` of its energy
+    fn effective_rank(&self, energy: f32) -> Result<usize, SvdApproxError>
+    where
+        Self: Planes,
+    {
+        let ranks = self
+            .planes()
+            .par_iter()
+            .map(|mat| {
+                let (s, total) = singular_values(mat.as_ref())?;
+                Ok(effective_rank(&s, total, energy, true).unwrap_or(s.len()))
+            })
+            .collect::<Result<Vec<_>, SvdApproxError>>()?;
+        Ok(ranks.into_iter().max().unwrap_or(0))
+    }
+}
+
+impl Compressible for GreyImageWrapper {
+    type Error = SvdApproxError;
+
+    fn compress(&self, rank: usize) -> Result<Self, Self::Error> {
+        let mat = svdapprox(self.mat.as_ref(), rank, false)?;
+        Ok(GreyImageWrapper {
+            mat,
+            width: self.width,
             height: self.height,
         })
     }
 
     fn compress_bad(&self, rank: usize) -> Result<Self, Self::Error> {
-        let compressed_mats: [Mat<f32>; 3] = self
-            .mats
-            .par_iter()
-            .map(|mat| svdapprox(mat.as_ref(), rank, true))
-            .collect::<Result<Vec<_>, SvdApproxError>>()?
-            .try_into()
-            .unwrap();
+        let mat = svdapprox(self.mat.as_ref(), rank, true)?;
+        Ok(GreyImageWrapper {
+            mat,
+            width: self.width,
+            height: self.height,
+        })
+    }
+}
+
+fn svdapprox_vec(
+    mats: &[Mat<f32>],
+    rank: usize,
+    bad: bool,
+) -> Result<Vec<Mat<f32>>, SvdApproxError> {
+    check_planes_rank(mats, rank)?;
+    collect_channels(
+        mats.par_iter()
+            .map(|mat| svdapprox(mat.as_ref(), rank, bad))
+            .collect(),
+    )
+}
+
+fn svdapprox_in_place(mats: &mut [Mat<f32>], rank: usize, bad: bool) -> Result<(), SvdApproxError> {
+    check_planes_rank(mats, rank)?;
+    collect_channels(
+        mats.par_iter_mut()
+            .map(|mat| {
+                let (m, n) = (mat.nrows(), mat.ncols());
+                if rank == 0 {
+                    let mean = channel_stats(mat.as_ref()).mean;
+                    mat.as_mut().fill(mean);
+                    return Ok(());
+                }
+                if rank == m.min(n) {
+                    return Ok(());
+                }
+
+                let _reservation = reserve(channel_peak_memory(m, n, rank)?)?;
+                svd(mat.as_ref(), SvdBackend::default())?
+                    .truncate(rank, bad)?
+                    .reconstruct_into(mat.as_mut(), get_global_parallelism());
+                Ok(())
+            })
+            .collect(),
+    )?;
+    Ok(())
+}
+
+fn svdapprox_all<const N: usize>(
+    mats: &[Mat<f32>; N],
+    rank: usize,
+    bad: bool,
+) -> Result<[Mat<f32>; N], SvdApproxError> {
+    let compressed_mats = svdapprox_vec(mats, rank, bad)?;
+    let len = compressed_mats.len();
 
+    // `svdapprox_vec` returns one matrix per input, so this can't fail in practice
+    compressed_mats
+        .try_into()
+        .map_err(|_| SvdApproxError::ShapeMismatch((N, 1), (len, 1)))
+}
+
+impl Compressible for GreyAlphaImageWrapper {
+    type Error = SvdApproxError;
+
+    fn compress(&self, rank: usize) -> Result<Self, Self::Error> {
+        Ok(GreyAlphaImageWrapper {
+            mats: svdapprox_all(&self.mats, rank, false)?,
+            width: self.width,
+            height: self.height,
+        })
+    }
+
+    fn compress_bad(&self, rank: usize) -> Result<Self, Self::Error> {
+        Ok(GreyAlphaImageWrapper {
+            mats: svdapprox_all(&self.mats, rank, true)?,
+            width: self.width,
+            height: self.height,
+        })
+    }
+}
+
+impl Compressible for RgbImageWrapper {
+    type Error = SvdApproxError;
+
+    fn compress(&self, rank: usize) -> Result<Self, Self::Error> {
         Ok(RgbImageWrapper {
-            mats: compressed_mats,
+            mats: svdapprox_all(&self.mats, rank, false)?,
             width: self.width,
             height: self.height,
         })
     }
+
+    fn compress_bad(&self, rank: usize) -> Result<Self, Self::Error> {
+        Ok(RgbImageWrapper {
+            mats: svdapprox_all(&self.mats, rank, true)?,
+            width: self.width,
+            height: self.height,
+        })
+    }
+}
+
+impl Compressible for RgbaImageWrapper {
+    type Error = SvdApproxError;
+
+    fn compress(&self, rank: usize) -> Result<Self, Self::Error> {
+        Ok(RgbaImageWrapper {
+            mats: svdapprox_all(&self.mats, rank, false)?,
+            width: self.width,
+            height: self.height,
+        })
+    }
+
+    fn compress_bad(&self, rank: usize) -> Result<Self, Self::Error> {
+        Ok(RgbaImageWrapper {
+            mats: svdapprox_all(&self.mats, rank, true)?,
+            width: self.width,
+            height: self.height,
+        })
+    }
+}
+
+#[cfg(feature = "cmyk")]
+impl Compressible for CmykImageWrapper {
+    type Error = SvdApproxError;
+
+    fn compress(&self, rank: usize) -> Result<Self, Self::Error> {
+        Ok(CmykImageWrapper {
+            mats: svdapprox_all(&self.mats, rank, false)?,
+            width: self.width,
+            height: self.height,
+        })
+    }
+
+    fn compress_bad(&self, rank: usize) -> Result<Self, Self::Error> {
+        Ok(CmykImageWrapper {
+            mats: svdapprox_all(&self.mats, rank, true)?,
+            width: self.width,
+            height: self.height,
+        })
+    }
+}
+
+#[cfg(feature = "dicom")]
+impl Compressible for DicomImageWrapper {
+    type Error = SvdApproxError;
+
+    fn compress(&self, rank: usize) -> Result<Self, Self::Error> {
+        Ok(self.rebuild(svdapprox_vec(&self.frames, rank, false)?))
+    }
+
+    fn compress_bad(&self, rank: usize) -> Result<Self, Self::Error> {
+        Ok(self.rebuild(svdapprox_vec(&self.frames, rank, true)?))
+    }
+}
+
+#[cfg(feature = "fits")]
+impl Compressible for FitsImageWrapper {
+    type Error = SvdApproxError;
+
+    fn compress(&self, rank: usize) -> Result<Self, Self::Error> {
+        Ok(self.rebuild(svdapprox_vec(&self.planes, rank, false)?))
+    }
+
+    fn compress_bad(&self, rank: usize) -> Result<Self, Self::Error> {
+        Ok(self.rebuild(svdapprox_vec(&self.planes, rank, true)?))
+    }
+}
+
+impl Compressible for DynWrapper {
+    type Error = SvdApproxError;
+
+    fn compress(&self, rank: usize) -> Result<Self, Self::Error> {
+        Ok(match self {
+            DynWrapper::Grey(wrapper) => DynWrapper::Grey(wrapper.compress(rank)?),
+            DynWrapper::GreyAlpha(wrapper) => DynWrapper::GreyAlpha(wrapper.compress(rank)?),
+            DynWrapper::Rgb(wrapper) => DynWrapper::Rgb(wrapper.compress(rank)?),
+            DynWrapper::Rgba(wrapper) => DynWrapper::Rgba(wrapper.compress(rank)?),
+        })
+    }
+
+    fn compress_bad(&self, rank: usize) -> Result<Self, Self::Error> {
+        Ok(match self {
+            DynWrapper::Grey(wrapper) => DynWrapper::Grey(wrapper.compress_bad(rank)?),
+            DynWrapper::GreyAlpha(wrapper) => DynWrapper::GreyAlpha(wrapper.compress_bad(rank)?),
+            DynWrapper::Rgb(wrapper) => DynWrapper::Rgb(wrapper.compress_bad(rank)?),
+            DynWrapper::Rgba(wrapper) => DynWrapper::Rgba(wrapper.compress_bad(rank)?),
+        })
+    }
+}
+
+fn factors_all(mats: &[Mat<f32>], rank: usize) -> Result<Vec<SvdFactors>, SvdApproxError> {
+    check_planes_rank(mats, rank)?;
+    collect_channels(
+        mats.par_iter()
+            .map(|mat| svd_factors(mat.as_ref(), rank, false))
+            .collect(),
+    )
+}
+
+impl Factorizable for GreyImageWrapper {
+    fn factors(&self, rank: usize) -> Result<Vec<SvdFactors>, SvdApproxError> {
+        Ok(vec![svd_factors(self.mat.as_ref(), rank, false)?])
+    }
+}
+
+impl Factorizable for GreyAlphaImageWrapper {
+    fn factors(&self, rank: usize) -> Result<Vec<SvdFactors>, SvdApproxError> {
+        factors_all(&self.mats, rank)
+    }
+}
+
+impl Factorizable for RgbImageWrapper {
+    fn factors(&self, rank: usize) -> Result<Vec<SvdFactors>, SvdApproxError> {
+        factors_all(&self.mats, rank)
+    }
+}
+
+impl Factorizable for RgbaImageWrapper {
+    fn factors(&self, rank: usize) -> Result<Vec<SvdFactors>, SvdApproxError> {
+        factors_all(&self.mats, rank)
+    }
+}
+
+#[cfg(feature = "cmyk")]
+impl Factorizable for CmykImageWrapper {
+    fn factors(&self, rank: usize) -> Result<Vec<SvdFactors>, SvdApproxError> {
+        factors_all(&self.mats, rank)
+    }
+}
+
+#[cfg(feature = "dicom")]
+impl Factorizable for DicomImageWrapper {
+    fn factors(&self, rank: usize) -> Result<Vec<SvdFactors>, SvdApproxError> {
+        factors_all(&self.frames, rank)
+    }
+}
+
+#[cfg(feature = "fits")]
+impl Factorizable for FitsImageWrapper {
+    fn factors(&self, rank: usize) -> Result<Vec<SvdFactors>, SvdApproxError> {
+        factors_all(&self.planes, rank)
+    }
+}
+
+impl Factorizable for DynWrapper {
+    fn factors(&self, rank: usize) -> Result<Vec<SvdFactors>, SvdApproxError> {
+        match self {
+            DynWrapper::Grey(wrapper) => wrapper.factors(rank),
+            DynWrapper::GreyAlpha(wrapper) => wrapper.factors(rank),
+            DynWrapper::Rgb(wrapper) => wrapper.factors(rank),
+            DynWrapper::Rgba(wrapper) => wrapper.factors(rank),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutils::{assert_images_close, assert_psnr_at_least, checkerboard, low_rank};
+
+    #[test]
+    fn rank_zero_factors_hold_the_mean() {
+        let mat = Mat::from_fn(5, 4, |i, j| (i * 4 + j) as f32 - 12.0);
+        let mean = channel_stats(mat.as_ref()).mean;
+        let full = svd(mat.as_ref(), SvdBackend::Faer).unwrap();
+        for factors in [
+            full.truncate(0, false).unwrap(),
+            svd_factors(mat.as_ref(), 0, false).unwrap(),
+        ] {
+            let rec = factors.reconstruct();
+            assert_eq!((rec.nrows(), rec.ncols()), (5, 4));
+            for i in 0..5 {
+                for j in 0..4 {
+                    assert!((rec.read(i, j) - mean).abs() < 1e-3);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn memory_budget_falls_back_to_tiling() {
+        let img = low_rank(256, 256, &[200.0, 80.0, 20.0, 5.0], 3);
+        let whole = img.estimate_peak_memory(&CompressOptions::new(8)).unwrap();
+        let options = CompressOptions {
+            memory_budget: Some(whole / 4),
+            ..CompressOptions::new(8)
+        };
+        assert!(img.estimate_peak_memory(&options).unwrap() <= whole / 4);
+        assert_psnr_at_least(&img.compress_with(&options).unwrap(), &img, 30.0);
+
+        let options = CompressOptions {
+            memory_budget: Some(1000),
+            ..CompressOptions::new(8)
+        };
+        assert!(matches!(
+            img.compress_with(&options),
+            Err(SvdApproxError::MemoryBudgetExceeded(_, 1000))
+        ));
+    }
+
+    #[test]
+    fn rank_zero_gives_the_mean_everywhere() {
+        let board = checkerboard(16, 12, 2);
+        let mean = board.compress(0).unwrap();
+        let factors = board.factors(0).unwrap();
+        let reconstructed = GreyImageWrapper {
+            mat: factors[0].reconstruct(),
+            ..board.clone()
+        };
+        assert_images_close(&reconstructed, &mean, 1e-3);
+
+        let thumbnail = board.thumbnail(0, 4).unwrap();
+        let expected = mean.mat.read(0, 0);
+        for j in 0..thumbnail.mat.ncols() {
+            for i in 0..thumbnail.mat.nrows() {
+                assert!((thumbnail.mat.read(i, j) - expected).abs() < 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn rank_weights_must_match_the_channel_count() {
+        let img = low_rank(8, 8, &[10.0, 1.0], 2);
+        assert!(matches!(
+            img.allocate_ranks(2, &RankWeighting::Custom(vec![1.0, 1.0])),
+            Err(SvdApproxError::ChannelCountMismatch(1, 2))
+        ));
+    }
+
+    #[test]
+    fn warm_starts_must_match_the_channel_count() {
+        let img = low_rank(8, 8, &[10.0, 1.0], 2);
+        assert!(matches!(
+            img.factors_warm(&[], 2),
+            Err(SvdApproxError::ChannelCountMismatch(1, 0))
+        ));
+    }
 }
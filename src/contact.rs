@@ -0,0 +1,177 @@
+use crate::batch::{BatchError, BatchResult};
+use crate::font::{GLYPH_HEIGHT, GLYPH_WIDTH, draw_text, text_width};
+use crate::imagewrapper::{ImageWrapper, RgbImageWrapper};
+use image::{ImageFormat, Rgb, RgbImage, imageops};
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+// Space between tiles and around the sheet, and below each thumbnail for its two label lines
+const PAD: u32 = 8;
+const LABEL: u32 = 2 * (GLYPH_HEIGHT as u32 + 4) + 2;
+
+const BACKGROUND: Rgb<u8> = Rgb([32, 32, 32]);
+const FRAME: Rgb<u8> = Rgb([64, 64, 64]);
+const TEXT: Rgb<u8> = Rgb([230, 230, 230]);
+const ERROR: Rgb<u8> = Rgb([235, 80, 70]);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ContactSheetOptions {
+    pub columns: usize,
+    // Rows per sheet; a batch with more results than fit spills onto further sheets
+    pub rows: usize,
+    // Longest side of each thumbnail, in pixels
+    pub thumbnail: usize,
+}
+
+impl Default for ContactSheetOptions {
+    fn default() -> Self {
+        ContactSheetOptions {
+            columns: 6,
+            rows: 5,
+            thumbnail: 192,
+        }
+    }
+}
+
+// `text` cut down to `width` pixels, marking the cut with `..`
+fn fit(text: &str, width: u32) -> String {
+    let max = (width as usize + 1) / (GLYPH_WIDTH + 1);
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let kept: String = text.chars().take(max.saturating_sub(2)).collect();
+    format!("{}..", kept)
+}
+
+// The output of a finished job shrunk to fit the tile, or why there is none to show
+fn thumbnail(result: &BatchResult, size: u32) -> Result<RgbImage, &'static str> {
+    let report = result.outcome.as_ref().map_err(|_| "FAILED")?;
+    if report.duplicate_of.is_some() && report.output_bytes == 0 {
+        return Err("DUPLICATE");
+    }
+    let img = image::open(&result.job.output).map_err(|_| "NO OUTPUT")?;
+    let img = img.to_rgb8();
+    let (w, h) = img.dimensions();
+    let scale = size as f32 / w.max(h).max(1) as f32;
+    let (w, h) = (
+        ((w as f32 * scale).round() as u32).max(1),
+        ((h as f32 * scale).round() as u32).max(1),
+    );
+    Ok(imageops::thumbnail(&img, w, h))
+}
+
+// Name, then rank and PSNR or the reason the tile is empty
+fn labels(result: &BatchResult, width: u32) -> (String, String) {
+    let name = result.job.input.file_name().map_or_else(
+        || result.job.input.display().to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    );
+    let detail = match &result.outcome {
+        Ok(report) => match report.mse {
+            Some(mse) if mse > 0.0 => format!(
+                "RANK {}  {:.1} DB",
+                report.rank,
+                10.0 * (255.0 * 255.0 / mse).log10()
+            ),
+            Some(_) => format!("RANK {}  EXACT", report.rank),
+            None => format!("RANK {}", report.rank),
+        },
+        Err(_) => "ERROR".to_string(),
+    };
+    (fit(&name, width), fit(&detail, width))
+}
+
+fn text(sheet: &mut RgbImage, text: &str, x: u32, y: u32, color: Rgb<u8>) {
+    let (w, h) = sheet.dimensions();
+    draw_text(text, x as usize, y as usize, 1, |x, y| {
+        if (x as u32) < w && (y as u32) < h {
+            sheet.put_pixel(x as u32, y as u32, color);
+        }
+    });
+}
+
+// Tiles the outputs of a batch run into sheets of labelled thumbnails, in the order of
+// `results`, for looking over a batch at a glance. Each tile shows the input's file name, the
+// rank and the PSNR against the input; failed jobs and outputs that can't be read get an empty
+// tile saying so. Outputs are read back from disk.
+pub fn contact_sheets(
+    results: &[BatchResult],
+    options: &ContactSheetOptions,
+) -> Vec<RgbImageWrapper> {
+    let size = options.thumbnail.max(1) as u32;
+    let columns = options.columns.max(1);
+    let per_sheet = columns * options.rows.max(1);
+
+    results
+        .chunks(per_sheet)
+        .map(|chunk| {
+            let rows = chunk.len().div_ceil(columns) as u32;
+            let mut sheet = RgbImage::from_pixel(
+                PAD + columns as u32 * (size + PAD),
+                PAD + rows * (size + LABEL + PAD),
+                BACKGROUND,
+            );
+            let thumbnails: Vec<_> = chunk
+                .par_iter()
+                .map(|result| thumbnail(result, size))
+                .collect();
+
+            for (k, (result, thumb)) in chunk.iter().zip(thumbnails).enumerate() {
+                let x = PAD + (k % columns) as u32 * (size + PAD);
+                let y = PAD + (k / columns) as u32 * (size + LABEL + PAD);
+                for dy in 0..size {
+                    for dx in 0..size {
+                        sheet.put_pixel(x + dx, y + dy, FRAME);
+                    }
+                }
+
+                let color = match thumb {
+                    Ok(thumb) => {
+                        let (w, h) = thumb.dimensions();
+                        imageops::replace(
+                            &mut sheet,
+                            &thumb,
+                            (x + (size - w) / 2) as i64,
+                            (y + (size - h) / 2) as i64,
+                        );
+                        TEXT
+                    }
+                    Err(reason) => {
+                        let width = text_width(reason, 1) as u32;
+                        let tx = x + size.saturating_sub(width) / 2;
+                        text(&mut sheet, reason, tx, y + size / 2, ERROR);
+                        ERROR
+                    }
+                };
+
+                let (name, detail) = labels(result, size);
+                let line = GLYPH_HEIGHT as u32 + 4;
+                text(&mut sheet, &name, x, y + size + 4, TEXT);
+                text(&mut sheet, &detail, x, y + size + 4 + line, color);
+            }
+
+            RgbImageWrapper::from_image(&sheet)
+        })
+        .collect()
+}
+
+// Writes `contact_sheets` to `dir` as `sheet_001.png` and so on, returning their paths
+pub fn save_contact_sheets(
+    results: &[BatchResult],
+    dir: impl AsRef<Path>,
+    options: &ContactSheetOptions,
+) -> Result<Vec<PathBuf>, BatchError> {
+    std::fs::create_dir_all(dir.as_ref())?;
+    contact_sheets(results, options)
+        .iter()
+        .enumerate()
+        .map(|(k, sheet)| {
+            let path = dir.as_ref().join(format!("sheet_{:03}.png", k + 1));
+            sheet.save(BufWriter::new(File::create(&path)?), ImageFormat::Png)?;
+            Ok(path)
+        })
+        .collect()
+}
@@ -0,0 +1,685 @@
+use crate::compress::{Factorizable, SvdApproxError, SvdFactors};
+use crate::dynwrapper::DynWrapper;
+use crate::fixedpoint::{MAX_SHIFT, QuantizedFactors};
+use crate::imagewrapper::Planes;
+use faer_core::Mat;
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use std::io::{self, Read, Write};
+
+const MAGIC: &[u8; 4] = b"SVDC";
+// Version 2 added the ancillary chunks; version 1 files are version 2 files without them.
+// Version 3 added the residual chunks of lossless containers; containers without residuals are
+// still written as version 2, so older readers keep opening lossy files.
+const VERSION: u16 = 3;
+const LOSSY_VERSION: u16 = 2;
+
+// Chunk types follow PNG: an uppercase first letter marks a critical chunk, which a reader must
+// understand, a lowercase one an ancillary chunk, which a reader that doesn't know it skips. New
+// optional metadata therefore goes in lowercase chunks and doesn't need a version bump.
+const FACTORS: &[u8; 4] = b"FACT";
+const DIGEST: &[u8; 4] = b"DGST";
+const RESIDUAL: &[u8; 4] = b"RESD";
+const RANKS: &[u8; 4] = b"rank";
+const COLOR_SPACE: &[u8; 4] = b"colr";
+const ICC_PROFILE: &[u8; 4] = b"iccp";
+const QUANTIZATION: &[u8; 4] = b"qant";
+
+// Pixels a channel may declare. Rank-0 factors take a few bytes whatever their size, so without
+// a cap a tiny file could have `reconstruct` allocate any matrix; this allows 16384 x 16384.
+const MAX_PIXELS: usize = 1 << 28;
+
+fn is_critical(chunk_type: &[u8; 4]) -> bool {
+    chunk_type[0].is_ascii_uppercase()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ColorSpace {
+    Grey,
+    GreyAlpha,
+    Rgb,
+    Rgba,
+    Cmyk,
+}
+
+impl ColorSpace {
+    const ALL: [ColorSpace; 5] = [
+        ColorSpace::Grey,
+        ColorSpace::GreyAlpha,
+        ColorSpace::Rgb,
+        ColorSpace::Rgba,
+        ColorSpace::Cmyk,
+    ];
+
+    fn code(self) -> u8 {
+        ColorSpace::ALL.iter().position(|&x| x == self).unwrap() as u8
+    }
+
+    fn from_code(code: u8) -> Option<Self> {
+        ColorSpace::ALL.get(code as usize).copied()
+    }
+}
+
+// Optional metadata, each field stored in its own ancillary chunk when present
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ContainerMeta {
+    // Needed to tell CMYK from RGBA; without it readers go by the channel count
+    pub color_space: Option<ColorSpace>,
+    pub icc_profile: Option<Vec<u8>>,
+    // Per-channel `(u_shift, v_shift)` of the `QuantizedFactors` the factors came from
+    pub quantization: Option<Vec<(u32, u32)>>,
+}
+
+// What a container holds, read without decoding the factors
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContainerInfo {
+    pub version: u16,
+    pub channels: usize,
+    pub ranks: Vec<usize>,
+    pub meta: ContainerMeta,
+    // Whether the container holds residuals, and so reconstructs its image exactly
+    pub lossless: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Container {
+    pub factors: Vec<SvdFactors>,
+    pub meta: ContainerMeta,
+    // Per channel, the row-major 8-bit pixels minus the integer-only reconstruction of the
+    // factors (see `QuantizedFactors`), wrapping around. Only meaningful with
+    // `meta.quantization`, which the factors must have been quantized with.
+    pub residuals: Option<Vec<Vec<u8>>>,
+}
+
+#[derive(Debug)]
+pub enum ContainerError {
+    Io(io::Error),
+    InvalidFormat(String),
+    // A checksum did not match, i.e. the bytes changed after they were written
+    CorruptContainer(String),
+    Svd(SvdApproxError),
+}
+
+impl std::fmt::Display for ContainerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ContainerError::Io(err) => write!(f, "I/O error: {}", err),
+            ContainerError::InvalidFormat(msg) => write!(f, "Invalid container: {}.", msg),
+            ContainerError::CorruptContainer(msg) => write!(f, "Corrupt container: {}.", msg),
+            ContainerError::Svd(err) => write!(f, "SVD error: {}", err),
+        }
+    }
+}
+
+impl From<io::Error> for ContainerError {
+    fn from(err: io::Error) -> Self {
+        ContainerError::Io(err)
+    }
+}
+
+impl From<SvdApproxError> for ContainerError {
+    fn from(err: SvdApproxError) -> Self {
+        ContainerError::Svd(err)
+    }
+}
+
+fn push_chunk(buf: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(chunk_type);
+    hasher.update(data);
+
+    buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    buf.extend_from_slice(chunk_type);
+    buf.extend_from_slice(data);
+    buf.extend_from_slice(&hasher.finalize().to_le_bytes());
+}
+
+// Byte length of a factors chunk: the header words, then S, U and V; `None` on overflow, since
+// the sizes may come from an untrusted file
+fn factors_len(m: usize, n: usize, rank: usize) -> Option<usize> {
+    m.checked_add(n)?
+        .checked_add(1)?
+        .checked_mul(rank)?
+        .checked_mul(4)?
+        .checked_add(16)
+}
+
+fn factors_to_bytes(factors: &SvdFactors) -> Vec<u8> {
+    let (m, n, rank) = (factors.u.nrows(), factors.v.nrows(), factors.rank());
+    let mut data = Vec::with_capacity(factors_len(m, n, rank).unwrap_or(0));
+
+    for x in [m as u32, n as u32, rank as u32] {
+        data.extend_from_slice(&x.to_le_bytes());
+    }
+    data.extend_from_slice(&factors.energy.to_le_bytes());
+    factors
+        .s
+        .iter()
+        .for_each(|x| data.extend_from_slice(&x.to_le_bytes()));
+    for mat in [&factors.u, &factors.v] {
+        for j in 0..rank {
+            mat.col_as_slice(j)
+                .iter()
+                .for_each(|x| data.extend_from_slice(&x.to_le_bytes()));
+        }
+    }
+
+    data
+}
+
+fn factors_from_bytes(data: &[u8]) -> Result<SvdFactors, ContainerError> {
+    let invalid = || ContainerError::InvalidFormat("truncated factors chunk".to_string());
+    let word = |k: usize| -> Result<[u8; 4], ContainerError> {
+        Ok(data
+            .get(4 * k..4 * k + 4)
+            .ok_or_else(invalid)?
+            .try_into()
+            .unwrap())
+    };
+
+    let (m, n, rank) = (
+        u32::from_le_bytes(word(0)?) as usize,
+        u32::from_le_bytes(word(1)?) as usize,
+        u32::from_le_bytes(word(2)?) as usize,
+    );
+    let len = factors_len(m, n, rank).ok_or_else(|| {
+        ContainerError::InvalidFormat(format!("{}x{} factors of rank {} overflow", m, n, rank))
+    })?;
+    if data.len() != len {
+        return Err(invalid());
+    }
+    if m.checked_mul(n).is_none_or(|pixels| pixels > MAX_PIXELS) {
+        return Err(ContainerError::InvalidFormat(format!(
+            "{}x{} factors exceed {} pixels",
+            m, n, MAX_PIXELS
+        )));
+    }
+
+    let float = |k: usize| f32::from_le_bytes(data[4 * k..4 * k + 4].try_into().unwrap());
+    let s_start = 4;
+    let u_start = s_start + rank;
+    let v_start = u_start + m * rank;
+
+    Ok(SvdFactors {
+        u: Mat::from_fn(m, rank, |i, j| float(u_start + j * m + i)),
+        s: (0..rank).map(|k| float(s_start + k)).collect(),
+        v: Mat::from_fn(n, rank, |i, j| float(v_start + j * n + i)),
+        energy: float(3),
+    })
+}
+
+fn words_to_bytes(words: impl IntoIterator<Item = u32>) -> Vec<u8> {
+    words.into_iter().flat_map(u32::to_le_bytes).collect()
+}
+
+fn bytes_to_words(data: &[u8], name: &str) -> Result<Vec<u32>, ContainerError> {
+    if !data.len().is_multiple_of(4) {
+        return Err(ContainerError::InvalidFormat(format!(
+            "{} chunk length is not a multiple of 4",
+            name
+        )));
+    }
+    Ok(data
+        .chunks_exact(4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .collect())
+}
+
+// Checks the header and every checksum, then hands each chunk other than the digest to `visit`;
+// returns the version and channel count from the header
+fn walk_chunks(
+    buf: &[u8],
+    mut visit: impl FnMut(&[u8; 4], &str, &[u8]) -> Result<(), ContainerError>,
+) -> Result<(u16, usize), ContainerError> {
+    if buf.get(..4) != Some(MAGIC) || buf.len() < 8 {
+        return Err(ContainerError::InvalidFormat(
+            "missing SVDC header".to_string(),
+        ));
+    }
+    let version = u16::from_le_bytes([buf[4], buf[5]]);
+    if !(1..=VERSION).contains(&version) {
+        return Err(ContainerError::InvalidFormat(format!(
+            "unsupported version {}",
+            version
+        )));
+    }
+    let channels = u16::from_le_bytes([buf[6], buf[7]]) as usize;
+    let mut pos = 8;
+
+    loop {
+        let header = buf
+            .get(pos..pos + 8)
+            .ok_or_else(|| ContainerError::InvalidFormat("missing digest chunk".to_string()))?;
+        let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+        let chunk_type: [u8; 4] = header[4..].try_into().unwrap();
+        let name = String::from_utf8_lossy(&chunk_type).into_owned();
+
+        let body = buf.get(pos + 4..pos + 8 + len + 4).ok_or_else(|| {
+            ContainerError::CorruptContainer(format!("{} chunk is truncated", name))
+        })?;
+        let (typed_data, crc) = body.split_at(4 + len);
+        if crc32fast::hash(typed_data).to_le_bytes() != crc {
+            return Err(ContainerError::CorruptContainer(format!(
+                "checksum mismatch in {} chunk at byte {}",
+                name, pos
+            )));
+        }
+        let data = &typed_data[4..];
+
+        if &chunk_type == DIGEST {
+            if crc32fast::hash(&buf[..pos]).to_le_bytes() != data {
+                return Err(ContainerError::CorruptContainer(
+                    "overall digest mismatch".to_string(),
+                ));
+            }
+            return Ok((version, channels));
+        }
+        visit(&chunk_type, &name, data)?;
+
+        pos += 12 + len;
+    }
+}
+
+// Handles the ancillary chunks this version knows, skipping any others
+fn read_meta(
+    meta: &mut ContainerMeta,
+    chunk_type: &[u8; 4],
+    name: &str,
+    data: &[u8],
+) -> Result<(), ContainerError> {
+    match chunk_type {
+        COLOR_SPACE => meta.color_space = data.first().copied().and_then(ColorSpace::from_code),
+        ICC_PROFILE => meta.icc_profile = Some(data.to_vec()),
+        QUANTIZATION => {
+            let words = bytes_to_words(data, name)?;
+            // Larger shifts would overflow the integer reconstruction of `QuantizedFactors`
+            if words.iter().any(|&shift| shift > MAX_SHIFT) {
+                return Err(ContainerError::InvalidFormat(format!(
+                    "quantization shifts exceed {}",
+                    MAX_SHIFT
+                )));
+            }
+            meta.quantization = Some(words.chunks_exact(2).map(|w| (w[0], w[1])).collect());
+        }
+        _ if is_critical(chunk_type) => {
+            return Err(ContainerError::InvalidFormat(format!(
+                "unknown critical chunk type {}",
+                name
+            )));
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+impl Container {
+    // Writes the header (magic number, version, channel count), the ancillary chunks, one
+    // `FACT` chunk per channel, a zlib-compressed `RESD` chunk per channel if there are
+    // residuals (only then stamping version 3), then a `DGST` chunk holding the CRC-32 of
+    // everything before it. Every chunk also carries its own CRC-32, PNG-style, so corruption
+    // can be pinned to a section.
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut buf = MAGIC.to_vec();
+        let version = if self.residuals.iter().flatten().next().is_some() {
+            VERSION
+        } else {
+            LOSSY_VERSION
+        };
+        buf.extend_from_slice(&version.to_le_bytes());
+        buf.extend_from_slice(&(self.factors.len() as u16).to_le_bytes());
+
+        let ranks = self.factors.iter().map(|f| f.rank() as u32);
+        push_chunk(&mut buf, RANKS, &words_to_bytes(ranks));
+        if let Some(color_space) = self.meta.color_space {
+            push_chunk(&mut buf, COLOR_SPACE, &[color_space.code()]);
+        }
+        if let Some(profile) = &self.meta.icc_profile {
+            push_chunk(&mut buf, ICC_PROFILE, profile);
+        }
+        if let Some(shifts) = &self.meta.quantization {
+            let words = shifts.iter().flat_map(|&(u, v)| [u, v]);
+            push_chunk(&mut buf, QUANTIZATION, &words_to_bytes(words));
+        }
+
+        for f in &self.factors {
+            push_chunk(&mut buf, FACTORS, &factors_to_bytes(f));
+        }
+        for residual in self.residuals.iter().flatten() {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+            encoder.write_all(residual)?;
+            push_chunk(&mut buf, RESIDUAL, &encoder.finish()?);
+        }
+
+        let digest = crc32fast::hash(&buf);
+        push_chunk(&mut buf, DIGEST, &digest.to_le_bytes());
+
+        writer.write_all(&buf)
+    }
+
+    // Reads a container written by `write`, verifying every checksum before decoding
+    pub fn read<R: Read>(mut reader: R) -> Result<Self, ContainerError> {
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf)?;
+
+        let mut factors = Vec::new();
+        let mut residuals = Vec::new();
+        let mut meta = ContainerMeta::default();
+        let (_, channels) = walk_chunks(&buf, |chunk_type, name, data| match chunk_type {
+            FACTORS => {
+                factors.push(factors_from_bytes(data)?);
+                Ok(())
+            }
+            // Inflating at most one byte past the channel's pixels, so a zlib bomb stops there
+            RESIDUAL => {
+                let expected = factors
+                    .get(residuals.len())
+                    .map(|f: &SvdFactors| f.u.nrows() * f.v.nrows())
+                    .ok_or_else(|| {
+                        ContainerError::InvalidFormat("residual chunk without factors".to_string())
+                    })?;
+                let mut residual = Vec::new();
+                ZlibDecoder::new(data)
+                    .take(expected as u64 + 1)
+                    .read_to_end(&mut residual)?;
+                if residual.len() != expected {
+                    return Err(ContainerError::InvalidFormat(
+                        "residual size does not match the factors".to_string(),
+                    ));
+                }
+                residuals.push(residual);
+                Ok(())
+            }
+            _ => read_meta(&mut meta, chunk_type, name, data),
+        })?;
+
+        if factors.len() != channels {
+            return Err(ContainerError::InvalidFormat(format!(
+                "expected {} channels, found {}",
+                channels,
+                factors.len()
+            )));
+        }
+        let shape = |f: &SvdFactors| (f.u.nrows(), f.v.nrows());
+        if factors.iter().any(|f| shape(f) != shape(&factors[0])) {
+            return Err(ContainerError::InvalidFormat(
+                "channels have different sizes".to_string(),
+            ));
+        }
+        if !residuals.is_empty() && residuals.len() != channels {
+            return Err(ContainerError::InvalidFormat(format!(
+                "expected {} residuals, found {}",
+                channels,
+                residuals.len()
+            )));
+        }
+
+        Ok(Container {
+            factors,
+            meta,
+            residuals: (!residuals.is_empty()).then_some(residuals),
+        })
+    }
+}
+
+// Reads the header and metadata, still verifying every checksum but leaving the factors encoded
+pub fn inspect_container<R: Read>(mut reader: R) -> Result<ContainerInfo, ContainerError> {
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+
+    let mut ranks = None;
+    let mut fallback_ranks = Vec::new();
+    let mut meta = ContainerMeta::default();
+    let mut lossless = false;
+    let (version, channels) = walk_chunks(&buf, |chunk_type, name, data| match chunk_type {
+        RESIDUAL => {
+            lossless = true;
+            Ok(())
+        }
+        RANKS => {
+            ranks = Some(bytes_to_words(data, name)?);
+            Ok(())
+        }
+        // Version 1 files have no rank chunk, but the rank is the third word of each channel
+        FACTORS => {
+            let word = data.get(8..12).ok_or_else(|| {
+                ContainerError::InvalidFormat("truncated factors chunk".to_string())
+            })?;
+            fallback_ranks.push(u32::from_le_bytes(word.try_into().unwrap()));
+            Ok(())
+        }
+        _ => read_meta(&mut meta, chunk_type, name, data),
+    })?;
+
+    Ok(ContainerInfo {
+        version,
+        channels,
+        ranks: ranks
+            .unwrap_or(fallback_ranks)
+            .into_iter()
+            .map(|rank| rank as usize)
+            .collect(),
+        meta,
+        lossless,
+    })
+}
+
+pub fn write_container<W: Write>(writer: W, factors: &[SvdFactors]) -> io::Result<()> {
+    Container {
+        factors: factors.to_vec(),
+        meta: ContainerMeta::default(),
+        residuals: None,
+    }
+    .write(writer)
+}
+
+// Stores rank-`rank` factors of every channel along with the residual left by their integer-only
+// reconstruction, which `load_container` adds back to recover the 8-bit image exactly. The
+// residual is smallest, and compresses best, where the image is well approximated at `rank`.
+pub fn write_lossless<W: Write>(
+    writer: W,
+    wrapper: &DynWrapper,
+    rank: usize,
+) -> Result<(), ContainerError> {
+    let quantized: Vec<(QuantizedFactors, f32)> = wrapper
+        .factors(rank)?
+        .iter()
+        .map(|factors| (QuantizedFactors::quantize(factors), factors.energy))
+        .collect();
+
+    let residuals = wrapper
+        .planes()
+        .iter()
+        .zip(&quantized)
+        .map(|(mat, (q, _))| {
+            let approx = q.reconstruct();
+            (0..mat.nrows())
+                .flat_map(|i| (0..mat.ncols()).map(move |j| (i, j)))
+                .zip(approx)
+                .map(|((i, j), x)| (mat.read(i, j).round().clamp(0.0, 255.0) as u8).wrapping_sub(x))
+                .collect()
+        })
+        .collect();
+
+    let color_space = match wrapper {
+        DynWrapper::Grey(_) => ColorSpace::Grey,
+        DynWrapper::GreyAlpha(_) => ColorSpace::GreyAlpha,
+        DynWrapper::Rgb(_) => ColorSpace::Rgb,
+        DynWrapper::Rgba(_) => ColorSpace::Rgba,
+    };
+    Container {
+        factors: quantized
+            .iter()
+            .map(|(q, energy)| q.dequantize(*energy))
+            .collect(),
+        meta: ContainerMeta {
+            color_space: Some(color_space),
+            quantization: Some(
+                quantized
+                    .iter()
+                    .map(|(q, _)| (q.u_shift, q.v_shift))
+                    .collect(),
+            ),
+            ..ContainerMeta::default()
+        },
+        residuals: Some(residuals),
+    }
+    .write(writer)?;
+    Ok(())
+}
+
+pub fn read_container<R: Read>(reader: R) -> Result<Vec<SvdFactors>, ContainerError> {
+    Ok(Container::read(reader)?.factors)
+}
+
+// Reconstructs the image stored in a container, picking the wrapper from the channel count
+pub fn load_container<R: Read>(reader: R) -> Result<DynWrapper, ContainerError> {
+    let container = Container::read(reader)?;
+    if container.meta.color_space == Some(ColorSpace::Cmyk) {
+        return Err(ContainerError::InvalidFormat(
+            "CMYK containers have no DynWrapper variant".to_string(),
+        ));
+    }
+
+    let planes = match (&container.residuals, &container.meta.quantization) {
+        (Some(residuals), Some(shifts)) if shifts.len() == residuals.len() => container
+            .factors
+            .iter()
+            .zip(residuals)
+            .zip(shifts)
+            .map(|((factors, residual), &(u_shift, v_shift))| {
+                let q = QuantizedFactors::requantize(factors, u_shift, v_shift);
+                let pixels: Vec<u8> = q
+                    .reconstruct()
+                    .iter()
+                    .zip(residual)
+                    .map(|(x, r)| x.wrapping_add(*r))
+                    .collect();
+                Mat::from_fn(q.nrows, q.ncols, |i, j| pixels[i * q.ncols + j] as f32)
+            })
+            .collect(),
+        (Some(_), _) => {
+            return Err(ContainerError::InvalidFormat(
+                "residuals need the quantization of every channel".to_string(),
+            ));
+        }
+        (None, _) => container
+            .factors
+            .iter()
+            .map(SvdFactors::reconstruct)
+            .collect(),
+    };
+
+    DynWrapper::from_planes(planes).ok_or_else(|| {
+        ContainerError::InvalidFormat("channels must number 1 to 4 and share a size".to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutils::{assert_images_close, checkerboard, low_rank};
+    use crate::{Factorizable, GreyImageWrapper};
+
+    // A lossy container with a factors chunk per channel, each given as its words: `m`, `n`, the
+    // rank and the energy, then the payload
+    fn container(channels: &[Vec<u32>]) -> Vec<u8> {
+        let mut buf = MAGIC.to_vec();
+        buf.extend_from_slice(&LOSSY_VERSION.to_le_bytes());
+        buf.extend_from_slice(&(channels.len() as u16).to_le_bytes());
+        for words in channels {
+            push_chunk(&mut buf, FACTORS, &words_to_bytes(words.iter().copied()));
+        }
+        let digest = crc32fast::hash(&buf).to_le_bytes();
+        push_chunk(&mut buf, DIGEST, &digest);
+        buf
+    }
+
+    fn invalid(buf: Vec<u8>) -> bool {
+        matches!(
+            read_container(buf.as_slice()),
+            Err(ContainerError::InvalidFormat(_))
+        )
+    }
+
+    #[test]
+    fn round_trips() {
+        let img = low_rank(32, 24, &[500.0, 90.0, 7.0], 5);
+        let mut buf = Vec::new();
+        write_container(&mut buf, &img.factors(3).unwrap()).unwrap();
+        let factors = read_container(buf.as_slice()).unwrap();
+        let read = GreyImageWrapper {
+            mat: factors[0].reconstruct(),
+            ..img.clone()
+        };
+        assert_images_close(&read, &img, 1e-2);
+        // Lossy containers stay readable by version 2 readers
+        assert_eq!(inspect_container(buf.as_slice()).unwrap().version, 2);
+
+        let board = DynWrapper::Grey(checkerboard(20, 14, 3));
+        let mut buf = Vec::new();
+        write_lossless(&mut buf, &board, 1).unwrap();
+        let info = inspect_container(buf.as_slice()).unwrap();
+        assert!(info.lossless && info.version == 3);
+        assert_images_close(&load_container(buf.as_slice()).unwrap(), &board, 0.0);
+    }
+
+    #[test]
+    fn rejects_overflowing_factor_sizes() {
+        // Dimensions and rank whose chunk size overflows
+        assert!(invalid(container(&[vec![u32::MAX, u32::MAX, u32::MAX, 0]])));
+    }
+
+    #[test]
+    fn rejects_oversized_rank_zero_factors() {
+        // Rank 0 needs no payload, so only the pixel cap stops a 160 GB reconstruction
+        assert!(invalid(container(&[vec![200_000, 200_000, 0, 0]])));
+        assert!(invalid(container(&[vec![u32::MAX, u32::MAX, 0, 0]])));
+        assert!(read_container(container(&[vec![16384, 16384, 0, 0]]).as_slice()).is_ok());
+    }
+
+    #[test]
+    fn rejects_channels_of_different_sizes() {
+        assert!(invalid(container(&[vec![4, 3, 0, 0], vec![3, 4, 0, 0]])));
+        assert!(
+            read_container(container(&[vec![4, 3, 0, 0], vec![4, 3, 0, 0]]).as_slice()).is_ok()
+        );
+    }
+
+    // A lossless container of one 4x3 rank-0 channel with the given shifts and residual
+    fn lossless(shifts: (u32, u32), residual: Vec<u8>) -> Vec<u8> {
+        let mut buf = Vec::new();
+        Container {
+            factors: vec![SvdFactors {
+                u: Mat::zeros(4, 0),
+                s: Vec::new(),
+                v: Mat::zeros(3, 0),
+                energy: 0.0,
+            }],
+            meta: ContainerMeta {
+                quantization: Some(vec![shifts]),
+                ..ContainerMeta::default()
+            },
+            residuals: Some(vec![residual]),
+        }
+        .write(&mut buf)
+        .unwrap();
+        buf
+    }
+
+    #[test]
+    fn rejects_out_of_range_shifts() {
+        assert!(load_container(lossless((30, 30), vec![7; 12]).as_slice()).is_ok());
+        assert!(invalid(lossless((70, 70), vec![7; 12])));
+        assert!(invalid(lossless((0, 31), vec![7; 12])));
+    }
+
+    #[test]
+    fn rejects_residuals_of_the_wrong_size() {
+        // Inflating stops one byte past the 12 pixels, however much more the chunk holds
+        assert!(invalid(lossless((0, 0), vec![0; 1 << 20])));
+        assert!(invalid(lossless((0, 0), vec![0; 11])));
+    }
+}
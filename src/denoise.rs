@@ -0,0 +1,104 @@
+use crate::compress::{SvdApproxError, SvdBackend, svd};
+use crate::imagewrapper::{GreyImageWrapper, Planes};
+use faer_core::Mat;
+use rayon::prelude::*;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Threshold {
+    // Gavish and Donoho's (2014) optimal hard threshold for unknown white noise, a multiple of
+    // the median singular value that depends only on the aspect ratio
+    #[default]
+    Optimal,
+    // Their optimal threshold for white noise of this known standard deviation (in 0-255 units)
+    Sigma(f32),
+    // Keeps this many singular pairs, like `compress`
+    Rank(usize),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DenoiseOptions {
+    pub threshold: Threshold,
+    // Also returns the map of how much each pixel changed
+    pub change_map: bool,
+}
+
+#[derive(Debug)]
+pub struct Denoised<W> {
+    pub wrapper: W,
+    // Rank kept in each channel
+    pub ranks: Vec<usize>,
+    // Root mean square over the channels of the change at each pixel, in 0-255 units. Where it
+    // traces edges and texture rather than looking like noise, the denoiser is erasing detail.
+    pub change: Option<GreyImageWrapper>,
+}
+
+// Rank kept from spectrum `s` of an `m x n` matrix
+fn kept_rank(s: &[f32], m: usize, n: usize, threshold: Threshold) -> usize {
+    let beta = m.min(n) as f32 / m.max(n) as f32;
+    let tau = match threshold {
+        Threshold::Rank(rank) => return rank.min(s.len()),
+        Threshold::Optimal => {
+            let omega = 0.56 * beta.powi(3) - 0.95 * beta.powi(2) + 1.82 * beta + 1.43;
+            let mut sorted = s.to_vec();
+            sorted.sort_by(f32::total_cmp);
+            omega * sorted.get(sorted.len() / 2).copied().unwrap_or(0.0)
+        }
+        Threshold::Sigma(sigma) => {
+            let lambda = (2.0 * (beta + 1.0)
+                + 8.0 * beta / (beta + 1.0 + (beta * beta + 14.0 * beta + 1.0).sqrt()))
+            .sqrt();
+            lambda * (m.max(n) as f32).sqrt() * sigma
+        }
+    };
+    s.iter().take_while(|&&x| x > tau).count()
+}
+
+pub trait Denoise: Planes {
+    // Truncates each channel's SVD at `options.threshold`: white noise spreads evenly over the
+    // whole spectrum, so the singular values rising clear of it carry the image
+    fn denoise(&self, options: &DenoiseOptions) -> Result<Denoised<Self>, SvdApproxError>
+    where
+        Self: Sized,
+    {
+        let planes = self.planes();
+        let (m, n) = (planes[0].nrows(), planes[0].ncols());
+        if let Threshold::Rank(rank) = options.threshold
+            && rank > m.min(n)
+        {
+            return Err(SvdApproxError::InvalidRank(m.min(n), rank));
+        }
+
+        let results = planes
+            .par_iter()
+            .map(|mat| {
+                let factors = svd(mat.as_ref(), SvdBackend::default())?;
+                let rank = kept_rank(&factors.s, m, n, options.threshold);
+                Ok((factors.truncate(rank, false)?.reconstruct(), rank))
+            })
+            .collect::<Result<Vec<_>, SvdApproxError>>()?;
+        let (mats, ranks): (Vec<Mat<f32>>, Vec<usize>) = results.into_iter().unzip();
+
+        let change = options.change_map.then(|| GreyImageWrapper {
+            mat: Mat::from_fn(m, n, |i, j| {
+                let sum: f32 = planes
+                    .iter()
+                    .zip(&mats)
+                    .map(|(a, b)| (a.read(i, j) - b.read(i, j)).powi(2))
+                    .sum();
+                (sum / planes.len() as f32).sqrt()
+            }),
+            width: n,
+            height: m,
+        });
+
+        Ok(Denoised {
+            wrapper: self.rebuild(mats),
+            ranks,
+            change,
+        })
+    }
+}
+
+impl<W: Planes> Denoise for W {}
@@ -0,0 +1,111 @@
+use image::ColorType;
+use image::error::{ImageError, ParameterError, ParameterErrorKind};
+use std::sync::{Arc, RwLock};
+
+// Something worth knowing about an input that loading went ahead with anyway
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Diagnostic {
+    // The input had more precision per sample than the 8 bits a wrapper holds, and was rounded
+    // down to it
+    Downcast { from: ColorType, to: ColorType },
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Diagnostic::Downcast { from, to } => {
+                write!(
+                    f,
+                    "{:?} input converted to {:?}, losing precision",
+                    from, to
+                )
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DowncastPolicy {
+    // Load silently
+    Allow,
+    // Load, and report a `Diagnostic::Downcast`
+    #[default]
+    Warn,
+    // Refuse to load
+    Error,
+}
+
+type Handler = Arc<dyn Fn(&Diagnostic) + Send + Sync>;
+
+// Process-wide, like a logger: loading goes through the `ImageWrapper` trait, whose signature
+// has no room for options
+static POLICY: RwLock<DowncastPolicy> = RwLock::new(DowncastPolicy::Warn);
+static HANDLER: RwLock<Option<Handler>> = RwLock::new(None);
+
+pub fn set_downcast_policy(policy: DowncastPolicy) {
+    *POLICY.write().unwrap() = policy;
+}
+
+pub fn downcast_policy() -> DowncastPolicy {
+    *POLICY.read().unwrap()
+}
+
+// Receives every diagnostic from then on, from any thread, replacing the previous handler.
+// Diagnostics are also logged as warnings with the `log` feature.
+pub fn set_diagnostics_handler(handler: impl Fn(&Diagnostic) + Send + Sync + 'static) {
+    *HANDLER.write().unwrap() = Some(Arc::new(handler));
+}
+
+pub fn clear_diagnostics_handler() {
+    *HANDLER.write().unwrap() = None;
+}
+
+pub(crate) fn report(diagnostic: Diagnostic) {
+    #[cfg(feature = "log")]
+    log::warn!(target: "svdimagecompress", "{}", diagnostic);
+
+    // Cloned out so a handler may itself set a new one without deadlocking
+    let handler = HANDLER.read().unwrap().clone();
+    if let Some(handler) = handler {
+        handler(&diagnostic);
+    }
+}
+
+fn eight_bit(color: ColorType) -> ColorType {
+    match (color.has_color(), color.has_alpha()) {
+        (false, false) => ColorType::L8,
+        (false, true) => ColorType::La8,
+        (true, false) => ColorType::Rgb8,
+        (true, true) => ColorType::Rgba8,
+    }
+}
+
+// Applies the downcast policy to an input of type `color` about to be converted to 8 bits; the
+// error is a message for the caller to wrap in its own error type
+pub(crate) fn check_downcast(color: ColorType) -> Result<(), String> {
+    if color.bytes_per_pixel() as usize <= color.channel_count() as usize {
+        return Ok(());
+    }
+
+    let diagnostic = Diagnostic::Downcast {
+        from: color,
+        to: eight_bit(color),
+    };
+    match downcast_policy() {
+        DowncastPolicy::Allow => Ok(()),
+        DowncastPolicy::Warn => {
+            report(diagnostic);
+            Ok(())
+        }
+        DowncastPolicy::Error => Err(format!(
+            "{:?} input would lose precision as {:?}",
+            color,
+            eight_bit(color)
+        )),
+    }
+}
+
+pub(crate) fn downcast_error(msg: String) -> ImageError {
+    ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(msg)))
+}
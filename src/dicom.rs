@@ -0,0 +1,590 @@
+use crate::imagewrapper::{GreyImageWrapper, ImageWrapper, Planes, describe};
+use faer_core::{Mat, MatRef};
+use image::error::{DecodingError, ImageFormatHint, ParameterError, ParameterErrorKind};
+use image::*;
+use std::io::{BufReader, Read, Seek, Write};
+
+const EXPLICIT_VR_LE: &str = "1.2.840.10008.1.2.1";
+const IMPLICIT_VR_LE: &str = "1.2.840.10008.1.2";
+
+const TRANSFER_SYNTAX: (u16, u16) = (0x0002, 0x0010);
+const SAMPLES_PER_PIXEL: (u16, u16) = (0x0028, 0x0002);
+const PHOTOMETRIC: (u16, u16) = (0x0028, 0x0004);
+const NUMBER_OF_FRAMES: (u16, u16) = (0x0028, 0x0008);
+const ROWS: (u16, u16) = (0x0028, 0x0010);
+const COLUMNS: (u16, u16) = (0x0028, 0x0011);
+const BITS_ALLOCATED: (u16, u16) = (0x0028, 0x0100);
+const BITS_STORED: (u16, u16) = (0x0028, 0x0101);
+const PIXEL_REPRESENTATION: (u16, u16) = (0x0028, 0x0103);
+const WINDOW_CENTER: (u16, u16) = (0x0028, 0x1050);
+const WINDOW_WIDTH: (u16, u16) = (0x0028, 0x1051);
+const RESCALE_INTERCEPT: (u16, u16) = (0x0028, 0x1052);
+const RESCALE_SLOPE: (u16, u16) = (0x0028, 0x1053);
+const PIXEL_DATA: (u16, u16) = (0x7FE0, 0x0010);
+const ITEM_DELIMITATION: (u16, u16) = (0xFFFE, 0xE00D);
+const SEQUENCE_DELIMITATION: (u16, u16) = (0xFFFE, 0xE0DD);
+const UNDEFINED_LENGTH: u32 = 0xFFFF_FFFF;
+// Nesting of undefined-length sequences and items beyond which a data set is taken as malformed
+const MAX_NESTING: usize = 64;
+
+fn dicom_error(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> ImageError {
+    ImageError::Decoding(DecodingError::new(
+        ImageFormatHint::Name("DICOM".to_string()),
+        err,
+    ))
+}
+
+// VRs whose explicit encoding has two reserved bytes followed by a 32-bit length
+const LONG_LENGTH_VRS: [&[u8]; 13] = [
+    b"OB", b"OD", b"OF", b"OL", b"OV", b"OW", b"SQ", b"SV", b"UC", b"UN", b"UR", b"UT", b"UV",
+];
+
+struct Element {
+    tag: (u16, u16),
+    start: usize,
+    value: usize,
+    length: u32,
+}
+
+struct Parser<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    explicit: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn bytes(&self, offset: usize, len: usize) -> ImageResult<&'a [u8]> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.buf.get(offset..end))
+            .ok_or_else(|| dicom_error("unexpected end of data set"))
+    }
+
+    fn u16_at(&self, offset: usize) -> ImageResult<u16> {
+        let b = self.bytes(offset, 2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32_at(&self, offset: usize) -> ImageResult<u32> {
+        let b = self.bytes(offset, 4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn next_header(&mut self) -> ImageResult<Element> {
+        let start = self.pos;
+        let tag = (self.u16_at(start)?, self.u16_at(start + 2)?);
+
+        // Item and delimitation tags carry no VR, and the file meta group is always explicit
+        let explicit = (self.explicit || tag.0 == 0x0002) && tag.0 != 0xFFFE;
+        let (value, length) = if !explicit {
+            (start + 8, self.u32_at(start + 4)?)
+        } else if LONG_LENGTH_VRS.contains(&self.bytes(start + 4, 2)?) {
+            (start + 12, self.u32_at(start + 8)?)
+        } else {
+            (start + 8, self.u16_at(start + 6)? as u32)
+        };
+
+        self.pos = value;
+        Ok(Element {
+            tag,
+            start,
+            value,
+            length,
+        })
+    }
+
+    // Skips the value of `element`, walking nested items when its length is undefined
+    fn skip(&mut self, element: &Element) -> ImageResult<()> {
+        self.skip_nested(element, 0)
+    }
+
+    fn skip_nested(&mut self, element: &Element, depth: usize) -> ImageResult<()> {
+        if element.length != UNDEFINED_LENGTH {
+            self.pos = element.value + element.length as usize;
+            return self.bytes(self.pos, 0).map(|_| ());
+        }
+        if depth >= MAX_NESTING {
+            return Err(dicom_error("sequences nested too deeply"));
+        }
+
+        loop {
+            let inner = self.next_header()?;
+            match inner.tag {
+                SEQUENCE_DELIMITATION | ITEM_DELIMITATION => return Ok(()),
+                _ => self.skip_nested(&inner, depth + 1)?,
+            }
+        }
+    }
+
+    fn value(&self, element: &Element) -> ImageResult<&'a [u8]> {
+        self.bytes(element.value, element.length as usize)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Window {
+    pub center: f32,
+    pub width: f32,
+}
+
+// Grayscale DICOM frames as raw stored values (before the modality rescale), one plane per frame,
+// along with the encoded data set so that `save_dicom` can write everything but the pixel data
+// back unchanged. Only uncompressed little-endian transfer syntaxes are supported.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DicomImageWrapper {
+    pub frames: Vec<Mat<f32>>,
+    pub width: usize,
+    pub height: usize,
+    pub bits_allocated: u16,
+    pub bits_stored: u16,
+    pub signed: bool,
+    // MONOCHROME1, where the lowest value is displayed as white
+    pub inverted: bool,
+    pub rescale_slope: f32,
+    pub rescale_intercept: f32,
+    pub window: Option<Window>,
+    header: Vec<u8>,
+    trailer: Vec<u8>,
+    explicit: bool,
+    // Width, height and frame count as the header gives them
+    shape: (usize, usize, usize),
+}
+
+fn parse_number(bytes: &[u8]) -> Option<f32> {
+    // Multi-valued strings are backslash-separated; the first value is the default one
+    let text = std::str::from_utf8(bytes).ok()?;
+    text.split('\\')
+        .next()?
+        .trim_matches([' ', '\0'])
+        .parse()
+        .ok()
+}
+
+// Samples are 8 or 16 bits, of which at least one and at most all are stored
+fn check_bits(bits_allocated: u16, bits_stored: u16) -> Result<(), String> {
+    if bits_allocated != 8 && bits_allocated != 16 {
+        return Err(format!("unsupported bits allocated {}", bits_allocated));
+    }
+    if bits_stored == 0 || bits_stored > bits_allocated {
+        return Err(format!(
+            "bits stored {} outside 1-{}",
+            bits_stored, bits_allocated
+        ));
+    }
+    Ok(())
+}
+
+impl DicomImageWrapper {
+    fn parse(buf: &[u8]) -> ImageResult<Self> {
+        if buf.get(128..132) != Some(b"DICM") {
+            return Err(dicom_error("missing DICM prefix"));
+        }
+
+        let mut parser = Parser {
+            buf,
+            pos: 132,
+            explicit: true,
+        };
+        let mut numbers = std::collections::HashMap::new();
+        let mut photometric = String::new();
+
+        let pixel_data = loop {
+            let element = parser.next_header()?;
+            let value = || parser.value(&element);
+
+            match element.tag {
+                PIXEL_DATA => break element,
+                TRANSFER_SYNTAX => {
+                    let uid = String::from_utf8_lossy(value()?);
+                    parser.explicit = match uid.trim_matches([' ', '\0']) {
+                        EXPLICIT_VR_LE => true,
+                        IMPLICIT_VR_LE => false,
+                        uid => {
+                            return Err(dicom_error(format!(
+                                "unsupported transfer syntax {}",
+                                uid
+                            )));
+                        }
+                    };
+                }
+                PHOTOMETRIC => {
+                    photometric = String::from_utf8_lossy(value()?).trim().to_string();
+                }
+                ROWS | COLUMNS | BITS_ALLOCATED | BITS_STORED | PIXEL_REPRESENTATION
+                | SAMPLES_PER_PIXEL => {
+                    numbers.insert(element.tag, parser.u16_at(element.value)? as f32);
+                }
+                NUMBER_OF_FRAMES | WINDOW_CENTER | WINDOW_WIDTH | RESCALE_INTERCEPT
+                | RESCALE_SLOPE => {
+                    if let Some(x) = parse_number(value()?) {
+                        numbers.insert(element.tag, x);
+                    }
+                }
+                _ => {}
+            }
+
+            parser.skip(&element)?;
+        };
+
+        // Encapsulated pixel data means a compressed transfer syntax slipped through
+        if pixel_data.length == UNDEFINED_LENGTH {
+            return Err(dicom_error("encapsulated pixel data is not supported"));
+        }
+
+        let number = |tag, default: Option<f32>| {
+            numbers
+                .get(&tag)
+                .copied()
+                .or(default)
+                .ok_or_else(|| dicom_error(format!("missing ({:04X},{:04X})", tag.0, tag.1)))
+        };
+
+        if number(SAMPLES_PER_PIXEL, Some(1.0))? != 1.0 || !photometric.starts_with("MONOCHROME") {
+            return Err(dicom_error(
+                "only grayscale (MONOCHROME1/2) images are supported",
+            ));
+        }
+
+        let height = number(ROWS, None)? as usize;
+        let width = number(COLUMNS, None)? as usize;
+        let frame_count = number(NUMBER_OF_FRAMES, Some(1.0))? as usize;
+        let bits_allocated = number(BITS_ALLOCATED, None)? as u16;
+        let bits_stored = number(BITS_STORED, Some(bits_allocated as f32))? as u16;
+        let signed = number(PIXEL_REPRESENTATION, Some(0.0))? == 1.0;
+
+        if frame_count == 0 {
+            return Err(dicom_error("no frames"));
+        }
+        if width == 0 || height == 0 {
+            return Err(dicom_error(format!("empty {}x{} frames", width, height)));
+        }
+        check_bits(bits_allocated, bits_stored).map_err(dicom_error)?;
+
+        let bytes_per_sample = bits_allocated as usize / 8;
+        let frame_len = width
+            .checked_mul(height)
+            .and_then(|samples| samples.checked_mul(bytes_per_sample))
+            .ok_or_else(|| dicom_error("frame size overflows"))?;
+        let data_len = frame_len
+            .checked_mul(frame_count)
+            .ok_or_else(|| dicom_error("pixel data size overflows"))?;
+        let data = parser.bytes(pixel_data.value, data_len)?;
+
+        // Stored values are the low `bits_stored` bits of each sample, sign-extended if signed
+        let shift = 32 - bits_stored as u32;
+        let sample = |offset: usize| {
+            let raw = match bytes_per_sample {
+                1 => data[offset] as u32,
+                _ => u16::from_le_bytes([data[offset], data[offset + 1]]) as u32,
+            };
+            if signed {
+                (((raw << shift) as i32) >> shift) as f32
+            } else {
+                ((raw << shift) >> shift) as f32
+            }
+        };
+
+        let frames = (0..frame_count)
+            .map(|k| {
+                Mat::from_fn(height, width, |i, j| {
+                    sample(k * frame_len + (i * width + j) * bytes_per_sample)
+                })
+            })
+            .collect();
+
+        let window = match (numbers.get(&WINDOW_CENTER), numbers.get(&WINDOW_WIDTH)) {
+            (Some(&center), Some(&width)) if width >= 1.0 => Some(Window { center, width }),
+            _ => None,
+        };
+
+        let end = pixel_data.value + pixel_data.length as usize;
+
+        Ok(Self {
+            frames,
+            width,
+            height,
+            bits_allocated,
+            bits_stored,
+            signed,
+            inverted: photometric == "MONOCHROME1",
+            rescale_slope: number(RESCALE_SLOPE, Some(1.0))?,
+            rescale_intercept: number(RESCALE_INTERCEPT, Some(0.0))?,
+            window,
+            header: buf[..pixel_data.start].to_vec(),
+            trailer: buf.get(end..).unwrap_or_default().to_vec(),
+            explicit: parser.explicit,
+            shape: (width, height, frame_count),
+        })
+    }
+
+    // Modality values (e.g. Hounsfield units for CT) of a stored value
+    fn rescale(&self, x: f32) -> f32 {
+        x * self.rescale_slope + self.rescale_intercept
+    }
+
+    // Falls back to the full range of the rescaled data when the file has no VOI window
+    fn effective_window(&self) -> Window {
+        if let Some(window) = &self.window {
+            return window.clone();
+        }
+
+        let (min, max) = self
+            .frames
+            .iter()
+            .flat_map(|mat| (0..mat.ncols()).flat_map(move |j| mat.col_as_slice(j).iter()))
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &x| {
+                let x = self.rescale(x);
+                (min.min(x), max.max(x))
+            });
+
+        Window {
+            center: (min + max) / 2.0,
+            width: (max - min).max(1.0) + 1.0,
+        }
+    }
+
+    // Applies the linear VOI window (PS3.3 C.11.2.1.2) to a frame, giving an 8-bit display image
+    pub fn to_grey(&self, frame: usize) -> GreyImageWrapper {
+        let Window { center, width } = self.effective_window();
+        let mat = &self.frames[frame];
+
+        let display = |x: f32| {
+            let y = ((self.rescale(x) - (center - 0.5)) / (width - 1.0) + 0.5).clamp(0.0, 1.0);
+            255.0 * if self.inverted { 1.0 - y } else { y }
+        };
+
+        GreyImageWrapper {
+            mat: Mat::from_fn(self.height, self.width, |i, j| display(mat.read(i, j))),
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    // Writes the original data set with the pixel data replaced by the (rounded and clamped)
+    // current frames. The rest of the header is kept as is, so the frames must still have the
+    // shape it gives.
+    pub fn save_dicom<W: Write>(&self, mut writer: W) -> ImageResult<()> {
+        let parameter = |msg| {
+            ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(msg)))
+        };
+        check_bits(self.bits_allocated, self.bits_stored).map_err(parameter)?;
+        let (width, height, frame_count) = self.shape;
+        if (self.width, self.height, self.frames.len()) != self.shape
+            || self
+                .frames
+                .iter()
+                .any(|mat| (mat.ncols(), mat.nrows()) != (width, height))
+        {
+            return Err(parameter(format!(
+                "the header describes {} frames of {}x{}, not {} of {}x{}",
+                frame_count,
+                width,
+                height,
+                self.frames.len(),
+                self.width,
+                self.height
+            )));
+        }
+        let (min, max) = if self.signed {
+            let half = (1i64 << (self.bits_stored - 1)) as f32;
+            (-half, half - 1.0)
+        } else {
+            (0.0, ((1i64 << self.bits_stored) - 1) as f32)
+        };
+        let mask = ((1u32 << self.bits_allocated) - 1) as i32;
+
+        let mut data = Vec::with_capacity(self.frames.len() * self.width * self.height * 2);
+        for mat in &self.frames {
+            for i in 0..self.height {
+                for j in 0..self.width {
+                    let x = (mat.read(i, j).round().clamp(min, max) as i32 & mask) as u16;
+                    match self.bits_allocated {
+                        8 => data.push(x as u8),
+                        _ => data.extend_from_slice(&x.to_le_bytes()),
+                    }
+                }
+            }
+        }
+        if data.len() % 2 == 1 {
+            data.push(0);
+        }
+
+        let mut element = Vec::with_capacity(12);
+        element.extend_from_slice(&PIXEL_DATA.0.to_le_bytes());
+        element.extend_from_slice(&PIXEL_DATA.1.to_le_bytes());
+        if self.explicit {
+            element.extend_from_slice(if self.bits_allocated == 8 {
+                b"OB"
+            } else {
+                b"OW"
+            });
+            element.extend_from_slice(&[0, 0]);
+        }
+        element.extend_from_slice(&(data.len() as u32).to_le_bytes());
+
+        writer.write_all(&self.header)?;
+        writer.write_all(&element)?;
+        writer.write_all(&data)?;
+        writer.write_all(&self.trailer)?;
+        Ok(())
+    }
+}
+
+impl Planes for DicomImageWrapper {
+    fn planes(&self) -> &[Mat<f32>] {
+        &self.frames
+    }
+
+    fn planes_mut(&mut self) -> &mut [Mat<f32>] {
+        &mut self.frames
+    }
+
+    fn map_planes<F>(&self, f: F) -> Self
+    where
+        F: Fn(MatRef<f32>) -> Mat<f32> + Sync,
+    {
+        self.rebuild(self.frames.iter().map(|mat| f(mat.as_ref())).collect())
+    }
+
+    fn rebuild(&self, planes: Vec<Mat<f32>>) -> Self {
+        assert_eq!(
+            planes.len(),
+            self.frames.len(),
+            "expected {} planes",
+            self.frames.len()
+        );
+        let (height, width) = (planes[0].nrows(), planes[0].ncols());
+
+        Self {
+            frames: planes,
+            width,
+            height,
+            window: self.window.clone(),
+            header: self.header.clone(),
+            trailer: self.trailer.clone(),
+            ..*self
+        }
+    }
+}
+
+impl std::fmt::Display for DicomImageWrapper {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        describe(f, "DICOM", self.width, self.height, self.frames.len())?;
+        write!(f, ", {}-bit", self.bits_stored)
+    }
+}
+
+impl ImageWrapper for DicomImageWrapper {
+    fn load<R: Read + Seek>(reader: R) -> ImageResult<Self> {
+        let mut reader = BufReader::new(reader);
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf)?;
+        Self::parse(&buf)
+    }
+
+    // Other formats get the windowed first frame; use `save_dicom` to keep the DICOM data set
+    fn save<W: Write + Seek>(&self, writer: W, format: ImageFormat) -> ImageResult<()> {
+        self.to_grey(0).save(writer, format)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutils::{assert_images_close, checkerboard};
+    use crate::{Compressible, Geometry};
+    use std::io::Cursor;
+
+    fn element(buf: &mut Vec<u8>, tag: (u16, u16), vr: &[u8; 2], value: &[u8]) {
+        buf.extend_from_slice(&tag.0.to_le_bytes());
+        buf.extend_from_slice(&tag.1.to_le_bytes());
+        buf.extend_from_slice(vr);
+        buf.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        buf.extend_from_slice(value);
+    }
+
+    // Explicit little-endian MONOCHROME2 data set; `frames` is the NumberOfFrames string
+    fn dicom_file(size: (u16, u16), bits: (u16, u16), frames: &str, pixels: &[u8]) -> Vec<u8> {
+        let mut buf = vec![0; 128];
+        buf.extend_from_slice(b"DICM");
+        element(&mut buf, (0x0002, 0x0010), b"UI", b"1.2.840.10008.1.2.1\0");
+        element(&mut buf, (0x0028, 0x0004), b"CS", b"MONOCHROME2 ");
+        let frames = format!("{:<1$}", frames, frames.len().next_multiple_of(2));
+        element(&mut buf, (0x0028, 0x0008), b"IS", frames.as_bytes());
+        element(&mut buf, (0x0028, 0x0010), b"US", &size.1.to_le_bytes());
+        element(&mut buf, (0x0028, 0x0011), b"US", &size.0.to_le_bytes());
+        element(&mut buf, (0x0028, 0x0100), b"US", &bits.0.to_le_bytes());
+        element(&mut buf, (0x0028, 0x0101), b"US", &bits.1.to_le_bytes());
+        buf.extend_from_slice(&[0xE0, 0x7F, 0x10, 0x00]);
+        buf.extend_from_slice(b"OW\0\0");
+        buf.extend_from_slice(&(pixels.len() as u32).to_le_bytes());
+        buf.extend_from_slice(pixels);
+        buf
+    }
+
+    fn error(buf: Vec<u8>) -> String {
+        DicomImageWrapper::load(Cursor::new(buf))
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn round_trips() {
+        let board = checkerboard(6, 4, 2);
+        let pixels: Vec<u8> = (0..4)
+            .flat_map(|i| (0..6).map(move |j| (i, j)))
+            .flat_map(|(i, j)| (board.mat.read(i, j) as u16 * 4).to_le_bytes())
+            .collect();
+        let dicom =
+            DicomImageWrapper::load(Cursor::new(dicom_file((6, 4), (16, 12), "1", &pixels)))
+                .unwrap();
+        assert_eq!((dicom.width, dicom.height, dicom.bits_stored), (6, 4, 12));
+        assert_images_close(&dicom.to_grey(0), &board, 0.5);
+
+        let mut compressed = dicom.compress(2).unwrap();
+        let mut buf = Vec::new();
+        compressed.save_dicom(&mut buf).unwrap();
+        let reread = DicomImageWrapper::load(Cursor::new(buf)).unwrap();
+        assert_images_close(&reread, &dicom, 0.5);
+
+        compressed.bits_stored = 0;
+        assert!(compressed.save_dicom(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn refuses_to_save_a_new_shape() {
+        let dicom = DicomImageWrapper::load(Cursor::new(dicom_file((6, 4), (8, 8), "1", &[0; 24])))
+            .unwrap();
+        let resized = dicom.resize(3, 2, imageops::FilterType::Triangle);
+        assert!(matches!(
+            resized.save_dicom(Vec::new()),
+            Err(ImageError::Parameter(_))
+        ));
+        let mut extra = dicom.clone();
+        extra.frames.push(extra.frames[0].clone());
+        assert!(extra.save_dicom(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        let pixels = [0; 8];
+        assert!(error(dicom_file((2, 2), (16, 0), "1", &pixels)).contains("bits stored"));
+        assert!(error(dicom_file((2, 2), (16, 17), "1", &pixels)).contains("bits stored"));
+        assert!(error(dicom_file((0, 2), (16, 16), "1", &pixels)).contains("empty"));
+        let huge = dicom_file((65535, 65535), (16, 16), "10000000000", &pixels);
+        assert!(error(huge).contains("overflows"));
+
+        // Items of undefined length, each opening the next and none closed
+        let mut nested = vec![0; 128];
+        nested.extend_from_slice(b"DICM");
+        nested.extend_from_slice(&[0x08, 0x00, 0x15, 0x11, b'S', b'Q', 0, 0]);
+        nested.extend_from_slice(&u32::MAX.to_le_bytes());
+        for _ in 0..1000 {
+            nested.extend_from_slice(&[0xFE, 0xFF, 0x00, 0xE0]);
+            nested.extend_from_slice(&u32::MAX.to_le_bytes());
+        }
+        assert!(error(nested).contains("nested"));
+    }
+}
@@ -0,0 +1,130 @@
+use crate::compress::SvdApproxError;
+use crate::dynwrapper::DynWrapper;
+use crate::illumination::{BackgroundOptions, background};
+use crate::imagewrapper::{GreyImageWrapper, LumaWeights};
+use faer_core::{Mat, MatRef};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Binarize {
+    // Picks the threshold separating ink from paper best (Otsu, 1979)
+    Otsu,
+    // Flattened values below this become black
+    Threshold(u8),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DocumentOptions {
+    // Rank of the page illumination model; shading and paper tint vary smoothly, so 1 to 3
+    // capture them without picking up the text
+    pub background_rank: usize,
+    // Side of the blocks whose brightest pixel stands in for the paper; must exceed the
+    // thickest strokes
+    pub block: usize,
+    pub binarize: Option<Binarize>,
+}
+
+impl Default for DocumentOptions {
+    fn default() -> Self {
+        DocumentOptions {
+            background_rank: 2,
+            block: 16,
+            binarize: None,
+        }
+    }
+}
+
+fn grey(wrapper: &DynWrapper) -> Mat<f32> {
+    let [r, g, b] = LumaWeights::Rec601.coefficients();
+    let luma = |mats: &[Mat<f32>]| {
+        Mat::from_fn(mats[0].nrows(), mats[0].ncols(), |i, j| {
+            r * mats[0].read(i, j) + g * mats[1].read(i, j) + b * mats[2].read(i, j)
+        })
+    };
+
+    match wrapper {
+        DynWrapper::Grey(wrapper) => wrapper.mat.clone(),
+        DynWrapper::GreyAlpha(wrapper) => wrapper.mats[0].clone(),
+        DynWrapper::Rgb(wrapper) => luma(&wrapper.mats),
+        DynWrapper::Rgba(wrapper) => luma(&wrapper.mats),
+    }
+}
+
+// Threshold maximizing the between-class variance of the 256-bin histogram
+fn otsu(mat: MatRef<f32>) -> u8 {
+    let mut counts = [0usize; 256];
+    for j in 0..mat.ncols() {
+        for i in 0..mat.nrows() {
+            counts[mat.read(i, j).clamp(0.0, 255.0) as usize] += 1;
+        }
+    }
+
+    let total = (mat.nrows() * mat.ncols()) as f64;
+    let sum: f64 = counts
+        .iter()
+        .enumerate()
+        .map(|(x, &c)| x as f64 * c as f64)
+        .sum();
+    let (mut below, mut below_sum) = (0.0f64, 0.0f64);
+    let (mut best, mut threshold) = (0.0f64, 0u8);
+
+    for (x, &count) in counts.iter().enumerate() {
+        below += count as f64;
+        below_sum += x as f64 * count as f64;
+        let above = total - below;
+        if below == 0.0 || above == 0.0 {
+            continue;
+        }
+
+        let diff = below_sum / below - (sum - below_sum) / above;
+        let variance = below * above * diff * diff;
+        if variance > best {
+            best = variance;
+            // Values up to `x` fall in the dark class
+            threshold = (x + 1).min(255) as u8;
+        }
+    }
+
+    threshold
+}
+
+// Scan cleanup: converts to grey, divides out the page illumination (see `Illumination`) so
+// that paper comes out uniformly white whatever the lighting, and optionally binarizes
+pub fn clean_document(
+    wrapper: &DynWrapper,
+    options: &DocumentOptions,
+) -> Result<GreyImageWrapper, SvdApproxError> {
+    let mat = grey(wrapper);
+    let background = background(
+        mat.as_ref(),
+        &BackgroundOptions {
+            block: options.block,
+            ..BackgroundOptions::new(options.background_rank)
+        },
+    )?;
+
+    let mut flat = Mat::from_fn(mat.nrows(), mat.ncols(), |i, j| {
+        (255.0 * mat.read(i, j) / background.read(i, j).max(1.0)).clamp(0.0, 255.0)
+    });
+
+    if let Some(binarize) = options.binarize {
+        let threshold = match binarize {
+            Binarize::Otsu => otsu(flat.as_ref()),
+            Binarize::Threshold(threshold) => threshold,
+        } as f32;
+        flat = Mat::from_fn(flat.nrows(), flat.ncols(), |i, j| {
+            if flat.read(i, j) < threshold {
+                0.0
+            } else {
+                255.0
+            }
+        });
+    }
+
+    Ok(GreyImageWrapper {
+        mat: flat,
+        width: mat.ncols(),
+        height: mat.nrows(),
+    })
+}
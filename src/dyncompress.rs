@@ -0,0 +1,50 @@
+use crate::compress::{Compressible, SvdApproxError};
+use crate::imagewrapper::{ImageWrapper, Planes};
+use faer_core::Mat;
+use image::{ImageFormat, ImageResult};
+use std::any::Any;
+use std::io::{Seek, Write};
+
+pub trait WriteSeek: Write + Seek {}
+
+impl<T: Write + Seek> WriteSeek for T {}
+
+// Object-safe companion to `Compressible`/`ImageWrapper`, so heterogeneous wrappers can be held
+// behind `Box<dyn DynCompress>`. Implemented for every wrapper automatically.
+pub trait DynCompress: Send + Sync {
+    fn compress_boxed(&self, rank: usize) -> Result<Box<dyn DynCompress>, SvdApproxError>;
+
+    fn compress_bad_boxed(&self, rank: usize) -> Result<Box<dyn DynCompress>, SvdApproxError>;
+
+    fn save_dyn(&self, writer: &mut dyn WriteSeek, format: ImageFormat) -> ImageResult<()>;
+
+    fn planes_dyn(&self) -> &[Mat<f32>];
+
+    // For downcasting back to the concrete wrapper type
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T> DynCompress for T
+where
+    T: Compressible<Error = SvdApproxError> + ImageWrapper + Planes + Send + Sync + 'static,
+{
+    fn compress_boxed(&self, rank: usize) -> Result<Box<dyn DynCompress>, SvdApproxError> {
+        Ok(Box::new(self.compress(rank)?))
+    }
+
+    fn compress_bad_boxed(&self, rank: usize) -> Result<Box<dyn DynCompress>, SvdApproxError> {
+        Ok(Box::new(self.compress_bad(rank)?))
+    }
+
+    fn save_dyn(&self, writer: &mut dyn WriteSeek, format: ImageFormat) -> ImageResult<()> {
+        self.save(writer, format)
+    }
+
+    fn planes_dyn(&self) -> &[Mat<f32>] {
+        self.planes()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -0,0 +1,171 @@
+use crate::imagewrapper::{
+    GreyAlphaImageWrapper, GreyImageWrapper, ImageWrapper, Planes, RgbImageWrapper,
+    RgbaImageWrapper, decode,
+};
+use faer_core::{Mat, MatRef};
+use image::*;
+use std::io::{Read, Seek, Write};
+
+// Picks the wrapper matching the color type of the decoded image, so callers don't have to
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DynWrapper {
+    Grey(GreyImageWrapper),
+    GreyAlpha(GreyAlphaImageWrapper),
+    Rgb(RgbImageWrapper),
+    Rgba(RgbaImageWrapper),
+}
+
+impl DynWrapper {
+    pub fn from_dynamic(img: DynamicImage) -> Self {
+        let color = img.color();
+
+        match (color.has_color(), color.has_alpha()) {
+            (false, false) => DynWrapper::Grey(GreyImageWrapper::from_image(&img.into_luma8())),
+            (false, true) => {
+                DynWrapper::GreyAlpha(GreyAlphaImageWrapper::from_image(&img.into_luma_alpha8()))
+            }
+            (true, false) => DynWrapper::Rgb(RgbImageWrapper::from_image(&img.into_rgb8())),
+            (true, true) => DynWrapper::Rgba(RgbaImageWrapper::from_image(&img.into_rgba8())),
+        }
+    }
+
+    // Picks the wrapper from the number of planes (1 to 4); `None` for any other count or if
+    // the planes differ in size
+    pub fn from_planes(planes: Vec<Mat<f32>>) -> Option<Self> {
+        let (height, width) = (planes.first()?.nrows(), planes[0].ncols());
+        if planes
+            .iter()
+            .any(|mat| mat.nrows() != height || mat.ncols() != width)
+        {
+            return None;
+        }
+
+        Some(match planes.len() {
+            1 => DynWrapper::Grey(GreyImageWrapper {
+                mat: planes.into_iter().next()?,
+                width,
+                height,
+            }),
+            2 => DynWrapper::GreyAlpha(GreyAlphaImageWrapper {
+                mats: planes.try_into().ok()?,
+                width,
+                height,
+            }),
+            3 => DynWrapper::Rgb(RgbImageWrapper {
+                mats: planes.try_into().ok()?,
+                width,
+                height,
+            }),
+            4 => DynWrapper::Rgba(RgbaImageWrapper {
+                mats: planes.try_into().ok()?,
+                width,
+                height,
+            }),
+            _ => return None,
+        })
+    }
+
+    pub fn to_dynamic(&self) -> DynamicImage {
+        match self {
+            DynWrapper::Grey(wrapper) => DynamicImage::ImageLuma8(wrapper.to_image()),
+            DynWrapper::GreyAlpha(wrapper) => DynamicImage::ImageLumaA8(wrapper.to_image()),
+            DynWrapper::Rgb(wrapper) => DynamicImage::ImageRgb8(wrapper.to_image()),
+            DynWrapper::Rgba(wrapper) => DynamicImage::ImageRgba8(wrapper.to_image()),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        match self {
+            DynWrapper::Grey(wrapper) => wrapper.width,
+            DynWrapper::GreyAlpha(wrapper) => wrapper.width,
+            DynWrapper::Rgb(wrapper) => wrapper.width,
+            DynWrapper::Rgba(wrapper) => wrapper.width,
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        match self {
+            DynWrapper::Grey(wrapper) => wrapper.height,
+            DynWrapper::GreyAlpha(wrapper) => wrapper.height,
+            DynWrapper::Rgb(wrapper) => wrapper.height,
+            DynWrapper::Rgba(wrapper) => wrapper.height,
+        }
+    }
+}
+
+impl Planes for DynWrapper {
+    fn planes(&self) -> &[Mat<f32>] {
+        match self {
+            DynWrapper::Grey(wrapper) => wrapper.planes(),
+            DynWrapper::GreyAlpha(wrapper) => wrapper.planes(),
+            DynWrapper::Rgb(wrapper) => wrapper.planes(),
+            DynWrapper::Rgba(wrapper) => wrapper.planes(),
+        }
+    }
+
+    fn planes_mut(&mut self) -> &mut [Mat<f32>] {
+        match self {
+            DynWrapper::Grey(wrapper) => wrapper.planes_mut(),
+            DynWrapper::GreyAlpha(wrapper) => wrapper.planes_mut(),
+            DynWrapper::Rgb(wrapper) => wrapper.planes_mut(),
+            DynWrapper::Rgba(wrapper) => wrapper.planes_mut(),
+        }
+    }
+
+    fn alpha(&self) -> Option<usize> {
+        match self {
+            DynWrapper::Grey(wrapper) => wrapper.alpha(),
+            DynWrapper::GreyAlpha(wrapper) => wrapper.alpha(),
+            DynWrapper::Rgb(wrapper) => wrapper.alpha(),
+            DynWrapper::Rgba(wrapper) => wrapper.alpha(),
+        }
+    }
+
+    fn map_planes<F>(&self, f: F) -> Self
+    where
+        F: Fn(MatRef<f32>) -> Mat<f32> + Sync,
+    {
+        match self {
+            DynWrapper::Grey(wrapper) => DynWrapper::Grey(wrapper.map_planes(f)),
+            DynWrapper::GreyAlpha(wrapper) => DynWrapper::GreyAlpha(wrapper.map_planes(f)),
+            DynWrapper::Rgb(wrapper) => DynWrapper::Rgb(wrapper.map_planes(f)),
+            DynWrapper::Rgba(wrapper) => DynWrapper::Rgba(wrapper.map_planes(f)),
+        }
+    }
+
+    fn rebuild(&self, planes: Vec<Mat<f32>>) -> Self {
+        match self {
+            DynWrapper::Grey(wrapper) => DynWrapper::Grey(wrapper.rebuild(planes)),
+            DynWrapper::GreyAlpha(wrapper) => DynWrapper::GreyAlpha(wrapper.rebuild(planes)),
+            DynWrapper::Rgb(wrapper) => DynWrapper::Rgb(wrapper.rebuild(planes)),
+            DynWrapper::Rgba(wrapper) => DynWrapper::Rgba(wrapper.rebuild(planes)),
+        }
+    }
+}
+
+impl std::fmt::Display for DynWrapper {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DynWrapper::Grey(wrapper) => wrapper.fmt(f),
+            DynWrapper::GreyAlpha(wrapper) => wrapper.fmt(f),
+            DynWrapper::Rgb(wrapper) => wrapper.fmt(f),
+            DynWrapper::Rgba(wrapper) => wrapper.fmt(f),
+        }
+    }
+}
+
+impl ImageWrapper for DynWrapper {
+    fn load<R: Read + Seek>(reader: R) -> ImageResult<Self> {
+        Ok(Self::from_dynamic(decode(reader)?))
+    }
+
+    fn save<W: Write + Seek>(&self, writer: W, format: ImageFormat) -> ImageResult<()> {
+        match self {
+            DynWrapper::Grey(wrapper) => wrapper.save(writer, format),
+            DynWrapper::GreyAlpha(wrapper) => wrapper.save(writer, format),
+            DynWrapper::Rgb(wrapper) => wrapper.save(writer, format),
+            DynWrapper::Rgba(wrapper) => wrapper.save(writer, format),
+        }
+    }
+}
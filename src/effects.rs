@@ -0,0 +1,86 @@
+use crate::compress::{SvdApproxError, SvdBackend, SvdFactors, svd};
+use crate::imagewrapper::Planes;
+use crate::texture::Rng;
+use faer_core::Mat;
+use rayon::prelude::*;
+
+// Deliberate misuses of the SVD for glitch art. Each acts on the full factors of every channel,
+// in the order given, and indices count singular pairs from the largest; ranges reaching past
+// the end of a spectrum are cut short rather than rejected.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Effect {
+    // Randomly permutes the singular values of pairs `from..to` among themselves, the same way in
+    // every channel. Including pair 0, which carries most of the brightness, is the harshest.
+    Shuffle { from: usize, to: usize, seed: u64 },
+    // Truncates each channel to its own rank, which splits colors apart along edges; channels
+    // beyond the list are left whole
+    ChannelRanks(Vec<usize>),
+    // Negates every `every`-th pair starting at `from`, inverting its contribution
+    FlipSigns { from: usize, every: usize },
+    // Scales the singular values from `from` on by `gain`, which exaggerates detail and noise
+    // when above 1
+    Amplify { from: usize, gain: f32 },
+}
+
+fn apply(factors: &mut SvdFactors, channel: usize, effect: &Effect) -> Result<(), SvdApproxError> {
+    let k = factors.rank();
+    match *effect {
+        Effect::Shuffle { from, to, seed } => {
+            let (from, to) = (from.min(k), to.min(k));
+            // Fisher-Yates over the values alone; the vectors stay put, so each pair gets
+            // another pair's weight
+            let mut rng = Rng::new(seed);
+            for i in (from + 1..to).rev() {
+                let j = from + ((rng.uniform() * (i - from + 1) as f32) as usize).min(i - from);
+                factors.s.swap(i, j);
+            }
+        }
+        Effect::ChannelRanks(ref ranks) => {
+            if let Some(&rank) = ranks.get(channel) {
+                *factors = factors.truncate(rank, false)?;
+            }
+        }
+        Effect::FlipSigns { from, every } => {
+            for j in (from..k).step_by(every.max(1)) {
+                let mut col = factors.u.as_mut().col_mut(j);
+                for i in 0..col.nrows() {
+                    col.write(i, -col.read(i));
+                }
+            }
+        }
+        Effect::Amplify { from, gain } => {
+            for s in factors.s.iter_mut().skip(from) {
+                *s *= gain;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub trait Glitch: Planes {
+    // Applies `effects` in turn and reconstructs, clamping every pixel to the valid 0-255 range
+    fn glitch(&self, effects: &[Effect]) -> Result<Self, SvdApproxError>
+    where
+        Self: Sized,
+    {
+        let mats = self
+            .planes()
+            .par_iter()
+            .enumerate()
+            .map(|(channel, mat)| {
+                let mut factors = svd(mat.as_ref(), SvdBackend::default())?;
+                for effect in effects {
+                    apply(&mut factors, channel, effect)?;
+                }
+                let out = factors.reconstruct();
+                Ok(Mat::from_fn(out.nrows(), out.ncols(), |i, j| {
+                    out.read(i, j).clamp(0.0, 255.0)
+                }))
+            })
+            .collect::<Result<Vec<_>, SvdApproxError>>()?;
+        Ok(self.rebuild(mats))
+    }
+}
+
+impl<W: Planes> Glitch for W {}
@@ -0,0 +1,98 @@
+use crate::dynwrapper::DynWrapper;
+use crate::imagewrapper::{
+    GreyAlphaImageWrapper, GreyImageWrapper, RgbImageWrapper, RgbaImageWrapper,
+};
+use crate::instrument::span;
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::error::{ParameterError, ParameterErrorKind};
+use image::{DynamicImage, ImageError, ImageResult};
+use std::io::Write;
+
+// Output formats with their encoder knobs; `ImageWrapper::save` uses each encoder's defaults.
+// `image` only ships a lossless WebP encoder, so there is no lossy WebP variant.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Encoding {
+    // `quality` in 1-100
+    Jpeg { quality: u8 },
+    WebPLossless,
+    // `speed` in 1-10 (1 is slowest and smallest), `quality` in 1-100
+    Avif { speed: u8, quality: u8 },
+}
+
+fn check_range(name: &str, value: u8, max: u8) -> ImageResult<()> {
+    if (1..=max).contains(&value) {
+        Ok(())
+    } else {
+        Err(ImageError::Parameter(ParameterError::from_kind(
+            ParameterErrorKind::Generic(format!(
+                "{} must be between 1 and {}, got {}",
+                name, max, value
+            )),
+        )))
+    }
+}
+
+fn encode<W: Write>(img: DynamicImage, writer: W, encoding: Encoding) -> ImageResult<()> {
+    let span = span("encode");
+    let (width, height) = (img.width() as usize, img.height() as usize);
+
+    match encoding {
+        Encoding::Jpeg { quality } => {
+            check_range("JPEG quality", quality, 100)?;
+            // JPEG has no alpha channel
+            let img = if img.color().has_color() {
+                DynamicImage::ImageRgb8(img.into_rgb8())
+            } else {
+                DynamicImage::ImageLuma8(img.into_luma8())
+            };
+            img.write_with_encoder(JpegEncoder::new_with_quality(writer, quality))?;
+        }
+        Encoding::WebPLossless => img.write_with_encoder(WebPEncoder::new_lossless(writer))?,
+        Encoding::Avif { speed, quality } => {
+            check_range("AVIF speed", speed, 10)?;
+            check_range("AVIF quality", quality, 100)?;
+            img.write_with_encoder(AvifEncoder::new_with_speed_quality(writer, speed, quality))?;
+        }
+    }
+
+    span.finish(height, width);
+    Ok(())
+}
+
+pub trait SaveWith {
+    // Like `ImageWrapper::save`, but with explicit encoder settings; needs no `Seek`
+    fn save_with<W: Write>(&self, writer: W, encoding: Encoding) -> ImageResult<()>;
+}
+
+impl SaveWith for GreyImageWrapper {
+    fn save_with<W: Write>(&self, writer: W, encoding: Encoding) -> ImageResult<()> {
+        encode(DynamicImage::ImageLuma8(self.to_image()), writer, encoding)
+    }
+}
+
+impl SaveWith for GreyAlphaImageWrapper {
+    fn save_with<W: Write>(&self, writer: W, encoding: Encoding) -> ImageResult<()> {
+        encode(DynamicImage::ImageLumaA8(self.to_image()), writer, encoding)
+    }
+}
+
+impl SaveWith for RgbImageWrapper {
+    fn save_with<W: Write>(&self, writer: W, encoding: Encoding) -> ImageResult<()> {
+        encode(DynamicImage::ImageRgb8(self.to_image()), writer, encoding)
+    }
+}
+
+impl SaveWith for RgbaImageWrapper {
+    fn save_with<W: Write>(&self, writer: W, encoding: Encoding) -> ImageResult<()> {
+        encode(DynamicImage::ImageRgba8(self.to_image()), writer, encoding)
+    }
+}
+
+impl SaveWith for DynWrapper {
+    fn save_with<W: Write>(&self, writer: W, encoding: Encoding) -> ImageResult<()> {
+        encode(self.to_dynamic(), writer, encoding)
+    }
+}
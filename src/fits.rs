@@ -0,0 +1,351 @@
+use crate::imagewrapper::{GreyImageWrapper, ImageWrapper, Planes, describe};
+use faer_core::{Mat, MatRef};
+use image::error::{DecodingError, ImageFormatHint};
+use image::*;
+use std::io::{BufReader, Read, Seek, Write};
+
+const BLOCK: usize = 2880;
+const CARD: usize = 80;
+
+// Keywords describing the data layout, which `save_fits` regenerates rather than copies, and
+// the checksums of HDU and data unit, which rewritten data would no longer match
+const STRUCTURAL: [&str; 10] = [
+    "SIMPLE", "BITPIX", "NAXIS", "BZERO", "BSCALE", "BLANK", "EXTEND", "END", "CHECKSUM", "DATASUM",
+];
+
+fn fits_error(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> ImageError {
+    ImageError::Decoding(DecodingError::new(
+        ImageFormatHint::Name("FITS".to_string()),
+        err,
+    ))
+}
+
+fn keyword(card: &str) -> &str {
+    card.get(..8).unwrap_or(card).trim_end()
+}
+
+// The value of a `KEYWORD = value / comment` card, without the comment
+fn value(card: &str) -> Option<&str> {
+    if card.get(8..10) != Some("= ") {
+        return None;
+    }
+    Some(card[10..].split('/').next()?.trim())
+}
+
+// Primary-HDU image (2D, or 3D as a stack of planes) holding physical values, i.e. with
+// `BZERO`/`BSCALE` applied, at full float precision. Rows are kept in file order, so row 0 is
+// the bottom row in the usual FITS display convention.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FitsImageWrapper {
+    pub planes: Vec<Mat<f32>>,
+    pub width: usize,
+    pub height: usize,
+    // BITPIX of the source file: 8, 16, 32 or 64 for integers, -32 or -64 for floats
+    pub bitpix: i32,
+    // Non-structural header cards (OBJECT, DATE-OBS, WCS keywords, ...), copied on save
+    pub cards: Vec<String>,
+}
+
+impl FitsImageWrapper {
+    fn parse(buf: &[u8]) -> ImageResult<Self> {
+        let mut cards = Vec::new();
+        let mut header_len = None;
+
+        for (k, card) in buf.chunks_exact(CARD).enumerate() {
+            let card = std::str::from_utf8(card).map_err(fits_error)?;
+            if keyword(card) == "END" {
+                header_len = Some((k + 1) * CARD);
+                break;
+            }
+            cards.push(card.to_string());
+        }
+
+        let header_len = header_len.ok_or_else(|| fits_error("missing END card"))?;
+        if cards.first().map(|card| keyword(card)) != Some("SIMPLE") {
+            return Err(fits_error("not a FITS file"));
+        }
+
+        let number = |key: &str| {
+            cards
+                .iter()
+                .find(|card| keyword(card) == key)
+                .and_then(|card| value(card)?.parse::<f64>().ok())
+        };
+        let required =
+            |key: &str| number(key).ok_or_else(|| fits_error(format!("missing {}", key)));
+
+        let bitpix = required("BITPIX")? as i32;
+        if ![8, 16, 32, 64, -32, -64].contains(&bitpix) {
+            return Err(fits_error(format!("invalid BITPIX {}", bitpix)));
+        }
+
+        let naxis = required("NAXIS")? as usize;
+        if !(2..=3).contains(&naxis) {
+            return Err(fits_error(format!(
+                "expected a 2D or 3D image, got NAXIS = {}",
+                naxis
+            )));
+        }
+
+        // Axis lengths are positive; 0, negative and fractional ones are rejected rather than
+        // read as empty or truncated
+        let axis = |key: &str| {
+            let len = required(key)?;
+            if len >= 1.0 && len.fract() == 0.0 && len <= usize::MAX as f64 {
+                Ok(len as usize)
+            } else {
+                Err(fits_error(format!("invalid {} = {}", key, len)))
+            }
+        };
+        let width = axis("NAXIS1")?;
+        let height = axis("NAXIS2")?;
+        let depth = if naxis == 3 { axis("NAXIS3")? } else { 1 };
+        let bzero = number("BZERO").unwrap_or(0.0);
+        let bscale = number("BSCALE").unwrap_or(1.0);
+        let blank = number("BLANK");
+
+        let bytes_per_value = (bitpix.unsigned_abs() / 8) as usize;
+        let overflow = || {
+            fits_error(format!(
+                "{}x{}x{} data unit overflows",
+                width, height, depth
+            ))
+        };
+        let plane_len = width
+            .checked_mul(height)
+            .and_then(|len| len.checked_mul(bytes_per_value))
+            .ok_or_else(overflow)?;
+        let data_len = plane_len.checked_mul(depth).ok_or_else(overflow)?;
+        let data_start = header_len.next_multiple_of(BLOCK);
+        let data = data_start
+            .checked_add(data_len)
+            .and_then(|end| buf.get(data_start..end))
+            .ok_or_else(|| fits_error("truncated data unit"))?;
+
+        // Big-endian raw values; undefined pixels (BLANK or NaN) become 0 so the SVD stays finite
+        let read = |offset: usize| -> f64 {
+            let b = &data[offset..offset + bytes_per_value];
+            let raw = match bitpix {
+                8 => b[0] as f64,
+                16 => i16::from_be_bytes([b[0], b[1]]) as f64,
+                32 => i32::from_be_bytes(b.try_into().unwrap()) as f64,
+                64 => i64::from_be_bytes(b.try_into().unwrap()) as f64,
+                -32 => f32::from_be_bytes(b.try_into().unwrap()) as f64,
+                _ => f64::from_be_bytes(b.try_into().unwrap()),
+            };
+            if raw.is_nan() || Some(raw) == blank {
+                0.0
+            } else {
+                bzero + bscale * raw
+            }
+        };
+
+        let planes = (0..depth)
+            .map(|k| {
+                Mat::from_fn(height, width, |i, j| {
+                    read(k * plane_len + (i * width + j) * bytes_per_value) as f32
+                })
+            })
+            .collect();
+
+        cards.retain(|card| {
+            let key = keyword(card);
+            !STRUCTURAL.contains(&key) && !key.starts_with("NAXIS")
+        });
+
+        Ok(Self {
+            planes,
+            width,
+            height,
+            bitpix,
+            cards,
+        })
+    }
+
+    // Linearly stretches a plane onto 0-255, flipped so that the first FITS row is at the bottom
+    pub fn to_grey(&self, plane: usize) -> GreyImageWrapper {
+        let mat = &self.planes[plane];
+        let (min, max) = (0..mat.ncols())
+            .flat_map(|j| mat.col_as_slice(j).iter())
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &x| {
+                (min.min(x), max.max(x))
+            });
+        let scale = if max > min { 255.0 / (max - min) } else { 0.0 };
+
+        GreyImageWrapper {
+            mat: Mat::from_fn(self.height, self.width, |i, j| {
+                (mat.read(self.height - 1 - i, j) - min) * scale
+            }),
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    // Writes a single-HDU FITS file with 32-bit float data (BITPIX = -32) and the kept cards
+    pub fn save_fits<W: Write>(&self, mut writer: W) -> ImageResult<()> {
+        let card = |key: &str, value: String| format!("{:<8}= {:>20}", key, value);
+        let naxis = if self.planes.len() > 1 { 3 } else { 2 };
+
+        let mut cards = vec![
+            card("SIMPLE", "T".to_string()),
+            card("BITPIX", "-32".to_string()),
+            card("NAXIS", naxis.to_string()),
+            card("NAXIS1", self.width.to_string()),
+            card("NAXIS2", self.height.to_string()),
+        ];
+        if self.planes.len() > 1 {
+            cards.push(card("NAXIS3", self.planes.len().to_string()));
+        }
+        cards.extend(self.cards.iter().cloned());
+        cards.push("END".to_string());
+
+        let mut header: Vec<u8> = cards
+            .iter()
+            .flat_map(|card| format!("{:<80.80}", card).into_bytes())
+            .collect();
+        header.resize(header.len().next_multiple_of(BLOCK), b' ');
+
+        let mut data = Vec::with_capacity(
+            (4 * self.planes.len() * self.width * self.height).next_multiple_of(BLOCK),
+        );
+        for mat in &self.planes {
+            for i in 0..self.height {
+                for j in 0..self.width {
+                    data.extend_from_slice(&mat.read(i, j).to_be_bytes());
+                }
+            }
+        }
+        data.resize(data.len().next_multiple_of(BLOCK), 0);
+
+        writer.write_all(&header)?;
+        writer.write_all(&data)?;
+        Ok(())
+    }
+}
+
+impl Planes for FitsImageWrapper {
+    fn planes(&self) -> &[Mat<f32>] {
+        &self.planes
+    }
+
+    fn planes_mut(&mut self) -> &mut [Mat<f32>] {
+        &mut self.planes
+    }
+
+    fn map_planes<F>(&self, f: F) -> Self
+    where
+        F: Fn(MatRef<f32>) -> Mat<f32> + Sync,
+    {
+        self.rebuild(self.planes.iter().map(|mat| f(mat.as_ref())).collect())
+    }
+
+    fn rebuild(&self, planes: Vec<Mat<f32>>) -> Self {
+        assert_eq!(
+            planes.len(),
+            self.planes.len(),
+            "expected {} planes",
+            self.planes.len()
+        );
+        let (height, width) = (planes[0].nrows(), planes[0].ncols());
+
+        Self {
+            planes,
+            width,
+            height,
+            bitpix: self.bitpix,
+            cards: self.cards.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for FitsImageWrapper {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        describe(f, "FITS", self.width, self.height, self.planes.len())?;
+        write!(f, ", BITPIX {}", self.bitpix)
+    }
+}
+
+impl ImageWrapper for FitsImageWrapper {
+    fn load<R: Read + Seek>(reader: R) -> ImageResult<Self> {
+        let mut reader = BufReader::new(reader);
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf)?;
+        Self::parse(&buf)
+    }
+
+    // Other formats get the stretched first plane; use `save_fits` to keep the float data
+    fn save<W: Write + Seek>(&self, writer: W, format: ImageFormat) -> ImageResult<()> {
+        self.to_grey(0).save(writer, format)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Compressible;
+    use crate::notch::Axis;
+    use crate::testutils::{assert_images_close, gradient};
+    use std::io::Cursor;
+
+    // Loads a file holding `data` as a 2-D image of `bitpix` samples, the axis lengths given as
+    // written in the header and followed by the `extra` cards
+    fn load(
+        (width, height, bitpix): (&str, &str, &str),
+        extra: &[(&str, &str)],
+        data: &[u8],
+    ) -> ImageResult<FitsImageWrapper> {
+        let cards = [
+            ("SIMPLE", "T"),
+            ("BITPIX", bitpix),
+            ("NAXIS", "2"),
+            ("NAXIS1", width),
+            ("NAXIS2", height),
+        ];
+        let mut buf: Vec<u8> = cards
+            .iter()
+            .chain(extra)
+            .map(|(key, value)| format!("{:<8}= {:>20}", key, value))
+            .chain(["END".to_string()])
+            .flat_map(|card| format!("{:<80}", card).into_bytes())
+            .collect();
+        buf.resize(buf.len().next_multiple_of(BLOCK), b' ');
+        buf.extend_from_slice(data);
+        buf.resize(buf.len().next_multiple_of(BLOCK), 0);
+        FitsImageWrapper::load(Cursor::new(buf))
+    }
+
+    #[test]
+    fn round_trips() {
+        let ramp = gradient(5, 3, Axis::Horizontal);
+        let data: Vec<u8> = (0..3)
+            .flat_map(|i| (0..5).map(move |j| (i, j)))
+            .flat_map(|(i, j)| (ramp.mat.read(i, j) as i16).to_be_bytes())
+            .collect();
+        let extra = [("OBJECT", "'RAMP'"), ("CHECKSUM", "'0000000000000000'")];
+        let fits = load(("5", "3", "16"), &extra, &data).unwrap();
+        assert_eq!((fits.width, fits.height, fits.bitpix), (5, 3, 16));
+        assert_images_close(&fits.compress(1).unwrap(), &fits, 0.05);
+
+        let mut buf = Vec::new();
+        fits.save_fits(&mut buf).unwrap();
+        let reread = FitsImageWrapper::load(Cursor::new(buf)).unwrap();
+        assert_images_close(&reread, &fits, 0.0);
+        assert!(reread.cards.iter().any(|card| card.starts_with("OBJECT")));
+        assert!(!reread.cards.iter().any(|card| card.starts_with("CHECKSUM")));
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        let error = |width: &str, height: &str, bitpix: &str| {
+            load((width, height, bitpix), &[], &[0; 64])
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(error("0", "3", "16").contains("NAXIS1"));
+        assert!(error("4", "-2", "16").contains("NAXIS2"));
+        assert!(error("2.5", "3", "16").contains("NAXIS1"));
+        let side = (1u64 << 40).to_string();
+        assert!(error(&side, &side, "64").contains("overflows"));
+    }
+}
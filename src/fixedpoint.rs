@@ -0,0 +1,158 @@
+use crate::compress::SvdFactors;
+use faer_core::Mat;
+
+// Factors quantized to i16 with power-of-two scales, so that reconstruction needs nothing but
+// integer multiply-accumulates and shifts (no FPU). The singular values are folded into U and V
+// as `sqrt(s)` each. Rows are stored contiguously so output can be produced row by row.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QuantizedFactors {
+    pub nrows: usize,
+    pub ncols: usize,
+    pub rank: usize,
+    // Row-major `nrows x rank`, scaled by `2^u_shift`
+    pub u: Vec<i16>,
+    // Row-major `ncols x rank`, scaled by `2^v_shift`
+    pub v: Vec<i16>,
+    pub u_shift: u32,
+    pub v_shift: u32,
+}
+
+// Bound on either scale, so that the two together still shift an i64 accumulator
+pub(crate) const MAX_SHIFT: u32 = 30;
+
+// Largest shift that keeps every `|x| * 2^shift` within i16
+fn max_shift(mat: &Mat<f32>) -> u32 {
+    let max = (0..mat.ncols())
+        .flat_map(|j| (0..mat.nrows()).map(move |i| mat.read(i, j).abs()))
+        .fold(0.0f32, f32::max);
+
+    if max == 0.0 {
+        return 0;
+    }
+
+    (i16::MAX as f32 / max)
+        .log2()
+        .floor()
+        .clamp(0.0, MAX_SHIFT as f32) as u32
+}
+
+fn quantize_rows(mat: &Mat<f32>, shift: u32) -> Vec<i16> {
+    let scale = (1u64 << shift) as f32;
+
+    (0..mat.nrows())
+        .flat_map(|i| (0..mat.ncols()).map(move |j| (mat.read(i, j) * scale).round() as i16))
+        .collect()
+}
+
+impl QuantizedFactors {
+    pub fn quantize(factors: &SvdFactors) -> Self {
+        let rank = factors.rank();
+        let sqrt_s: Vec<f32> = factors.s.iter().map(|x| x.max(0.0).sqrt()).collect();
+
+        let u = Mat::from_fn(factors.u.nrows(), rank, |i, j| {
+            factors.u.read(i, j) * sqrt_s[j]
+        });
+        let v = Mat::from_fn(factors.v.nrows(), rank, |i, j| {
+            factors.v.read(i, j) * sqrt_s[j]
+        });
+
+        let (u_shift, v_shift) = (max_shift(&u), max_shift(&v));
+
+        QuantizedFactors {
+            nrows: u.nrows(),
+            ncols: v.nrows(),
+            rank,
+            u: quantize_rows(&u, u_shift),
+            v: quantize_rows(&v, v_shift),
+            u_shift,
+            v_shift,
+        }
+    }
+
+    // Float factors holding exactly these integers over their scales, with the singular values
+    // left folded in (all ones), so that `requantize` recovers `self` bit for bit
+    #[cfg(feature = "container")]
+    pub(crate) fn dequantize(&self, energy: f32) -> SvdFactors {
+        let unscale = |values: &[i16], rows: usize, shift: u32| {
+            let scale = (1u64 << shift) as f32;
+            Mat::from_fn(rows, self.rank, |i, j| {
+                values[i * self.rank + j] as f32 / scale
+            })
+        };
+        SvdFactors {
+            u: unscale(&self.u, self.nrows, self.u_shift),
+            s: vec![1.0; self.rank],
+            v: unscale(&self.v, self.ncols, self.v_shift),
+            energy,
+        }
+    }
+
+    #[cfg(feature = "container")]
+    pub(crate) fn requantize(factors: &SvdFactors, u_shift: u32, v_shift: u32) -> Self {
+        QuantizedFactors {
+            nrows: factors.u.nrows(),
+            ncols: factors.v.nrows(),
+            rank: factors.rank(),
+            u: quantize_rows(&factors.u, u_shift),
+            v: quantize_rows(&factors.v, v_shift),
+            u_shift,
+            v_shift,
+        }
+    }
+
+    // Everything from here on is integer-only
+    pub fn pixel(&self, i: usize, j: usize) -> u8 {
+        let u = &self.u[i * self.rank..(i + 1) * self.rank];
+        let v = &self.v[j * self.rank..(j + 1) * self.rank];
+        let acc: i64 = u.iter().zip(v).map(|(&a, &b)| a as i64 * b as i64).sum();
+
+        let shift = self.u_shift + self.v_shift;
+        let rounded = if shift == 0 {
+            acc
+        } else {
+            (acc + (1 << (shift - 1))) >> shift
+        };
+
+        rounded.clamp(0, 255) as u8
+    }
+
+    pub fn reconstruct_row(&self, i: usize, out: &mut [u8]) {
+        for (j, px) in out.iter_mut().enumerate().take(self.ncols) {
+            *px = self.pixel(i, j);
+        }
+    }
+
+    // Row-major `nrows x ncols` 8-bit pixels
+    pub fn reconstruct(&self) -> Vec<u8> {
+        if self.nrows == 0 || self.ncols == 0 {
+            return Vec::new();
+        }
+        let mut out = vec![0u8; self.nrows * self.ncols];
+        for (i, row) in out.chunks_exact_mut(self.ncols).enumerate() {
+            self.reconstruct_row(i, row);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_factors_reconstruct_to_nothing() {
+        for (nrows, ncols) in [(0, 3), (3, 0), (0, 0)] {
+            let q = QuantizedFactors {
+                nrows,
+                ncols,
+                rank: 1,
+                u: vec![1; nrows],
+                v: vec![1; ncols],
+                u_shift: 0,
+                v_shift: 0,
+            };
+            assert!(q.reconstruct().is_empty());
+        }
+    }
+}
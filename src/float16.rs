@@ -0,0 +1,66 @@
+use crate::compress::SvdFactors;
+use faer_core::{Mat, MatRef};
+use half::f16;
+
+// Column-major half-precision matrix, used purely for storage: convert back with `to_mat`
+// before doing any arithmetic
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HalfMat {
+    pub nrows: usize,
+    pub ncols: usize,
+    pub data: Vec<f16>,
+}
+
+impl HalfMat {
+    pub fn from_mat(mat: MatRef<f32>) -> Self {
+        let data = (0..mat.ncols())
+            .flat_map(|j| (0..mat.nrows()).map(move |i| f16::from_f32(mat.read(i, j))))
+            .collect();
+
+        HalfMat {
+            nrows: mat.nrows(),
+            ncols: mat.ncols(),
+            data,
+        }
+    }
+
+    pub fn to_mat(&self) -> Mat<f32> {
+        Mat::from_fn(self.nrows, self.ncols, |i, j| {
+            self.data[j * self.nrows + i].to_f32()
+        })
+    }
+}
+
+// `SvdFactors` with U and V stored in f16, halving their footprint. The singular values stay
+// in f32: there are few of them, and they span too wide a range for f16's exponent.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HalfFactors {
+    pub u: HalfMat,
+    pub s: Vec<f32>,
+    pub v: HalfMat,
+    pub energy: f32,
+}
+
+impl HalfFactors {
+    pub fn to_factors(&self) -> SvdFactors {
+        SvdFactors {
+            u: self.u.to_mat(),
+            s: self.s.clone(),
+            v: self.v.to_mat(),
+            energy: self.energy,
+        }
+    }
+}
+
+impl From<&SvdFactors> for HalfFactors {
+    fn from(factors: &SvdFactors) -> Self {
+        HalfFactors {
+            u: HalfMat::from_mat(factors.u.as_ref()),
+            s: factors.s.clone(),
+            v: HalfMat::from_mat(factors.v.as_ref()),
+            energy: factors.energy,
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use crate::imagewrapper::Planes;
+use faer_core::Mat;
+use image::imageops::FilterType;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+// All operations act on the float matrices directly, so no re-quantization to u8 takes place
+pub trait Geometry: Planes + Sized {
+    // Returns `None` if `rect` does not lie entirely within the image
+    fn crop(&self, rect: Rect) -> Option<Self> {
+        let mat = &self.planes()[0];
+
+        if rect.x + rect.width > mat.ncols() || rect.y + rect.height > mat.nrows() {
+            return None;
+        }
+
+        Some(self.map_planes(|mat| {
+            mat.submatrix(rect.y, rect.x, rect.height, rect.width)
+                .to_owned()
+        }))
+    }
+
+    fn flip_h(&self) -> Self {
+        self.map_planes(|mat| mat.reverse_cols().to_owned())
+    }
+
+    fn flip_v(&self) -> Self {
+        self.map_planes(|mat| mat.reverse_rows().to_owned())
+    }
+
+    // Rotates 90 degrees clockwise
+    fn rotate90(&self) -> Self {
+        self.map_planes(|mat| {
+            let height = mat.nrows();
+            Mat::from_fn(mat.ncols(), height, |i, j| mat.read(height - 1 - j, i))
+        })
+    }
+
+    fn transpose(&self) -> Self {
+        self.map_planes(|mat| mat.transpose().to_owned())
+    }
+
+    // Resamples the raw values with the kernels `image` uses, so nothing is clamped to 0-255;
+    // ringing filters (Catmull-Rom, Lanczos) may overshoot the input range near edges
+    fn resize(&self, width: usize, height: usize, filter: FilterType) -> Self {
+        self.map_planes(|mat| {
+            let rows = weights(mat.nrows(), height, filter);
+            let cols = weights(mat.ncols(), width, filter);
+            let tall: Mat<f32> = Mat::from_fn(height, mat.ncols(), |i, j| {
+                let (start, w) = &rows[i];
+                w.iter()
+                    .enumerate()
+                    .map(|(k, w)| w * mat.read(start + k, j))
+                    .sum()
+            });
+            Mat::from_fn(height, width, |i, j| {
+                let (start, w) = &cols[j];
+                w.iter()
+                    .enumerate()
+                    .map(|(k, w)| w * tall.read(i, start + k))
+                    .sum()
+            })
+        })
+    }
+}
+
+impl<W: Planes> Geometry for W {}
+
+// Kernel of `filter` and its half-width, in samples of the coarser of the two grids
+fn kernel(filter: FilterType) -> (fn(f32) -> f32, f32) {
+    fn sinc(x: f32) -> f32 {
+        if x == 0.0 {
+            1.0
+        } else {
+            let x = x * std::f32::consts::PI;
+            x.sin() / x
+        }
+    }
+    match filter {
+        FilterType::Nearest => (|_| 1.0, 0.0),
+        FilterType::Triangle => (|x| (1.0 - x.abs()).max(0.0), 1.0),
+        // Catmull-Rom is the cubic with B = 0 and C = 1/2
+        FilterType::CatmullRom => (
+            |x| {
+                let x = x.abs();
+                if x < 1.0 {
+                    1.5 * x * x * x - 2.5 * x * x + 1.0
+                } else if x < 2.0 {
+                    -0.5 * x * x * x + 2.5 * x * x - 4.0 * x + 2.0
+                } else {
+                    0.0
+                }
+            },
+            2.0,
+        ),
+        FilterType::Gaussian => (|x| (-2.0 * x * x).exp(), 3.0),
+        FilterType::Lanczos3 => (
+            |x| {
+                if x.abs() < 3.0 {
+                    sinc(x) * sinc(x / 3.0)
+                } else {
+                    0.0
+                }
+            },
+            3.0,
+        ),
+    }
+}
+
+// For each of `out` samples resampled from `len`, the first source sample it reads and the
+// normalized weights of those from there on. Downsampling widens the kernel to the source
+// spacing so every input contributes, as `image::imageops::resize` does.
+fn weights(len: usize, out: usize, filter: FilterType) -> Vec<(usize, Vec<f32>)> {
+    if len == 0 {
+        return vec![(0, Vec::new()); out];
+    }
+    let ratio = len as f32 / out.max(1) as f32;
+    let (kernel, support) = kernel(filter);
+
+    (0..out)
+        .map(|i| {
+            let center = (i as f32 + 0.5) * ratio;
+            if filter == FilterType::Nearest {
+                return ((center as usize).min(len - 1), vec![1.0]);
+            }
+            let scale = ratio.max(1.0);
+            let reach = support * scale;
+            let start = ((center - reach).floor().max(0.0) as usize).min(len - 1);
+            let end = ((center + reach).ceil() as usize).clamp(start + 1, len);
+            let mut w: Vec<f32> = (start..end)
+                .map(|k| kernel((k as f32 + 0.5 - center) / scale))
+                .collect();
+            let sum: f32 = w.iter().sum();
+            if sum != 0.0 {
+                w.iter_mut().for_each(|w| *w /= sum);
+            }
+            (start, w)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imagewrapper::GreyImageWrapper;
+
+    fn wrapper(mat: Mat<f32>) -> GreyImageWrapper {
+        let (width, height) = (mat.ncols(), mat.nrows());
+        GreyImageWrapper { mat, width, height }
+    }
+
+    #[test]
+    fn resize_keeps_values_outside_0_255() {
+        let img = wrapper(Mat::from_fn(
+            8,
+            6,
+            |i, _| if i < 4 { 1000.0 } else { -50.0 },
+        ));
+        let nearest = img.resize(3, 2, FilterType::Nearest);
+        assert_eq!((nearest.mat.nrows(), nearest.mat.ncols()), (2, 3));
+        for j in 0..3 {
+            assert_eq!(
+                (nearest.mat.read(0, j), nearest.mat.read(1, j)),
+                (1000.0, -50.0)
+            );
+        }
+        // Upsampling interpolates between the two, reaching both at the outer rows
+        let two = wrapper(Mat::from_fn(
+            2,
+            2,
+            |i, _| if i == 0 { 1000.0 } else { -50.0 },
+        ));
+        let upsampled = two.resize(2, 4, FilterType::Triangle);
+        assert_eq!(upsampled.mat.read(0, 0), 1000.0);
+        assert_eq!(upsampled.mat.read(3, 0), -50.0);
+        assert!((upsampled.mat.read(1, 0) - 737.5).abs() < 1e-2);
+    }
+
+    #[test]
+    fn resize_keeps_constant_planes_constant() {
+        let img = wrapper(Mat::from_fn(7, 5, |_, _| 3000.0));
+        for filter in [FilterType::CatmullRom, FilterType::Lanczos3] {
+            let resized = img.resize(11, 3, filter);
+            for j in 0..11 {
+                for i in 0..3 {
+                    assert!((resized.mat.read(i, j) - 3000.0).abs() < 1e-2);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use crate::imagewrapper::{Planes, RgbImageWrapper};
+use faer_core::Mat;
+
+// Nine evenly spaced samples of each matplotlib colormap, interpolated linearly in between;
+// both rise steadily in lightness, so brighter always means a larger error
+const VIRIDIS: [[u8; 3]; 9] = [
+    [0x44, 0x01, 0x54],
+    [0x47, 0x2d, 0x7b],
+    [0x3b, 0x52, 0x8b],
+    [0x2c, 0x72, 0x8e],
+    [0x21, 0x90, 0x8c],
+    [0x27, 0xad, 0x81],
+    [0x5d, 0xc8, 0x63],
+    [0xaa, 0xdc, 0x32],
+    [0xfd, 0xe7, 0x25],
+];
+const INFERNO: [[u8; 3]; 9] = [
+    [0x00, 0x00, 0x04],
+    [0x1b, 0x0c, 0x41],
+    [0x4a, 0x0c, 0x6b],
+    [0x78, 0x1c, 0x6d],
+    [0xa5, 0x2c, 0x60],
+    [0xcf, 0x44, 0x46],
+    [0xed, 0x69, 0x25],
+    [0xfb, 0x9b, 0x06],
+    [0xfc, 0xff, 0xa4],
+];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Colormap {
+    #[default]
+    Viridis,
+    Inferno,
+    Grey,
+}
+
+impl Colormap {
+    // Color of `t`, clamped to 0-1
+    pub fn color(self, t: f32) -> [f32; 3] {
+        let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+        let stops = match self {
+            Colormap::Viridis => &VIRIDIS,
+            Colormap::Inferno => &INFERNO,
+            Colormap::Grey => return [255.0 * t; 3],
+        };
+        let x = t * (stops.len() - 1) as f32;
+        let k = (x as usize).min(stops.len() - 2);
+        let frac = x - k as f32;
+        std::array::from_fn(|c| {
+            let (a, b) = (stops[k][c] as f32, stops[k + 1][c] as f32);
+            a + frac * (b - a)
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ErrorMapOptions {
+    pub colormap: Colormap,
+    // Error drawn at the top of the colormap, larger ones being clamped to it; `None` uses the
+    // largest error of the pair, while a fixed value keeps maps of different images comparable
+    pub max_error: Option<f32>,
+}
+
+pub trait ErrorMap: Planes {
+    // Root mean square over channels of the difference at each pixel
+    fn error_magnitudes(&self, other: &Self) -> Mat<f32> {
+        let (a, b) = (self.planes(), other.planes());
+        assert!(
+            a.len() == b.len() && a[0].nrows() == b[0].nrows() && a[0].ncols() == b[0].ncols(),
+            "wrappers must have the same shape"
+        );
+        let channels = a.len() as f32;
+        Mat::from_fn(a[0].nrows(), a[0].ncols(), |i, j| {
+            let sum: f32 = a
+                .iter()
+                .zip(b)
+                .map(|(a, b)| (a.read(i, j) - b.read(i, j)).powi(2))
+                .sum();
+            (sum / channels).sqrt()
+        })
+    }
+
+    // `error_magnitudes` through a colormap, to see at a glance where compression hurts most;
+    // save it like any other wrapper. Panics if the shapes differ.
+    fn error_map(&self, other: &Self, options: &ErrorMapOptions) -> RgbImageWrapper {
+        let errors = self.error_magnitudes(other);
+        let (m, n) = (errors.nrows(), errors.ncols());
+        let max = options.max_error.unwrap_or_else(|| {
+            (0..n)
+                .flat_map(|j| (0..m).map(move |i| (i, j)))
+                .map(|(i, j)| errors.read(i, j))
+                .fold(0.0, f32::max)
+        });
+        // Identical images map to the bottom of the colormap
+        let scale = if max > 0.0 { 1.0 / max } else { 0.0 };
+
+        // Column-major, like the planes
+        let colors: Vec<[f32; 3]> = (0..n)
+            .flat_map(|j| (0..m).map(move |i| (i, j)))
+            .map(|(i, j)| options.colormap.color(errors.read(i, j) * scale))
+            .collect();
+        let channel = |c: usize| Mat::from_fn(m, n, |i, j| colors[j * m + i][c].round());
+        RgbImageWrapper {
+            mats: [channel(0), channel(1), channel(2)],
+            width: n,
+            height: m,
+        }
+    }
+}
+
+impl<W: Planes> ErrorMap for W {}
@@ -0,0 +1,176 @@
+use crate::compress::{Compressible, SvdApproxError};
+use crate::imagewrapper::{ImageWrapper, Planes};
+use image::{ImageFormat, ImageResult};
+use std::fs::File;
+use std::io::{BufReader, Cursor, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Keyword of the PNG text chunk `Tracked::save_png` embeds the history in
+const PNG_KEYWORD: &str = "svdimagecompress:history";
+
+// One step applied to an image, with its parameters as display strings
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Operation {
+    pub name: String,
+    pub params: Vec<(String, String)>,
+    // Seconds since the Unix epoch
+    pub time: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct History {
+    pub operations: Vec<Operation>,
+}
+
+// Escapes everything outside printable ASCII, so the output is also valid Latin-1 for PNG
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            ' '..='~' => out.push(c),
+            _ => {
+                let mut units = [0u16; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    out.push_str(&format!("\\u{:04x}", unit));
+                }
+            }
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl History {
+    pub fn record(&mut self, name: &str, params: &[(&str, String)]) {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        self.operations.push(Operation {
+            name: name.to_string(),
+            params: params
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.clone()))
+                .collect(),
+            time,
+        });
+    }
+
+    // `{"operations": [{"name": ..., "time": ..., "params": {...}}, ...]}` on one line
+    pub fn to_json(&self) -> String {
+        let operations: Vec<String> = self
+            .operations
+            .iter()
+            .map(|op| {
+                let params: Vec<String> = op
+                    .params
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", json_string(key), json_string(value)))
+                    .collect();
+                format!(
+                    "{{\"name\": {}, \"time\": {}, \"params\": {{{}}}}}",
+                    json_string(&op.name),
+                    op.time,
+                    params.join(", ")
+                )
+            })
+            .collect();
+        format!("{{\"operations\": [{}]}}", operations.join(", "))
+    }
+}
+
+impl std::fmt::Display for History {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (k, op) in self.operations.iter().enumerate() {
+            write!(f, "{}. {}", k + 1, op.name)?;
+            for (key, value) in &op.params {
+                write!(f, " {}={}", key, value)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+// A wrapper that remembers what was done to it. Operations made through `apply` or `record` are
+// logged; ones made on `wrapper` directly are not.
+#[derive(Clone, Debug)]
+pub struct Tracked<W> {
+    pub wrapper: W,
+    pub history: History,
+}
+
+impl<W> Tracked<W> {
+    // Starts a history at an image from `source`, e.g. a path or URL
+    pub fn new(wrapper: W, source: &str) -> Self {
+        let mut history = History::default();
+        history.record("load", &[("source", source.to_string())]);
+        Tracked { wrapper, history }
+    }
+
+    pub fn record(&mut self, name: &str, params: &[(&str, String)]) {
+        self.history.record(name, params);
+    }
+
+    // Replaces the wrapper by `f`'s result and records it as `name`; on error, nothing changes
+    pub fn apply<E>(
+        self,
+        name: &str,
+        params: &[(&str, String)],
+        f: impl FnOnce(&W) -> Result<W, E>,
+    ) -> Result<Self, E> {
+        let Tracked {
+            wrapper,
+            mut history,
+        } = self;
+        let wrapper = f(&wrapper)?;
+        history.record(name, params);
+        Ok(Tracked { wrapper, history })
+    }
+}
+
+impl<W: ImageWrapper> Tracked<W> {
+    pub fn open(path: impl AsRef<Path>) -> ImageResult<Self> {
+        let path = path.as_ref();
+        let wrapper = W::load(BufReader::new(File::open(path)?))?;
+        Ok(Tracked::new(wrapper, &path.display().to_string()))
+    }
+
+    // Saves as PNG with the history as JSON in a `tEXt` chunk right after the header, where
+    // tools like `exiftool` and `pngcheck -t` show it
+    pub fn save_png<Wr: Write>(&self, mut writer: Wr) -> ImageResult<()> {
+        let mut buf = Cursor::new(Vec::new());
+        self.wrapper.save(&mut buf, ImageFormat::Png)?;
+        let png = buf.into_inner();
+
+        let mut data = PNG_KEYWORD.as_bytes().to_vec();
+        data.push(0);
+        data.extend_from_slice(self.history.to_json().as_bytes());
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(b"tEXt");
+        hasher.update(&data);
+
+        // Signature (8 bytes), then IHDR: length, type, 13 bytes of data and CRC
+        let (head, tail) = png.split_at(8 + 4 + 4 + 13 + 4);
+        writer.write_all(head)?;
+        writer.write_all(&(data.len() as u32).to_be_bytes())?;
+        writer.write_all(b"tEXt")?;
+        writer.write_all(&data)?;
+        writer.write_all(&hasher.finalize().to_be_bytes())?;
+        writer.write_all(tail)?;
+        Ok(())
+    }
+}
+
+impl<W: Compressible<Error = SvdApproxError> + Planes> Tracked<W> {
+    pub fn compress(self, rank: usize) -> Result<Self, SvdApproxError> {
+        self.apply("compress", &[("rank", rank.to_string())], |w| {
+            w.compress(rank)
+        })
+    }
+}
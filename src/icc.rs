@@ -0,0 +1,366 @@
+use crate::imagewrapper::Planes;
+use faer_core::Mat;
+use image::{ImageDecoder, ImageReader, ImageResult};
+use std::io::{BufRead, Seek};
+
+// Samples of the inverse of a tabulated or parametric curve, interpolated linearly
+const INVERSE_SAMPLES: usize = 4096;
+
+#[derive(Debug)]
+pub enum IccError {
+    InvalidProfile(String),
+    // Valid, but not a matrix/TRC profile, e.g. one built on lookup tables (`A2B0`)
+    Unsupported(String),
+    // The image's color channels don't match the profile's color space
+    ChannelMismatch(usize, usize),
+}
+
+impl std::fmt::Display for IccError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            IccError::InvalidProfile(msg) => write!(f, "Invalid ICC profile: {}.", msg),
+            IccError::Unsupported(msg) => write!(f, "Unsupported ICC profile: {}.", msg),
+            IccError::ChannelMismatch(expected, got) => write!(
+                f,
+                "Profile expects {} color channels, got {}.",
+                expected, got
+            ),
+        }
+    }
+}
+
+// Tone reproduction curve, from encoded values to linear light, both nominally in [0, 1]. It is
+// extended beyond that range point-symmetrically below 0 and, for tables, linearly above 1, so
+// colors outside a gamut survive a conversion there and back.
+#[derive(Clone, Debug, PartialEq)]
+enum Curve {
+    Gamma(f32),
+    Table(Vec<f32>),
+    // ICC parametric curve of type 4, which the other types are special cases of:
+    // `(a x + b)^g + e` from `d` on, `c x + f` below
+    Parametric([f32; 7]),
+}
+
+impl Curve {
+    fn eval(&self, x: f32) -> f32 {
+        if x < 0.0 {
+            return -self.eval(-x);
+        }
+        match self {
+            Curve::Gamma(g) => x.powf(*g),
+            Curve::Table(table) => {
+                let pos = x * (table.len() - 1) as f32;
+                let k = (pos.floor() as usize).min(table.len() - 2);
+                let t = pos - k as f32;
+                table[k] * (1.0 - t) + table[k + 1] * t
+            }
+            Curve::Parametric([g, a, b, c, d, e, f]) => {
+                if x >= *d {
+                    (a * x + b).max(0.0).powf(*g) + e
+                } else {
+                    c * x + f
+                }
+            }
+        }
+    }
+
+    // Tables and parametric curves are inverted through a dense table of the curve itself,
+    // which works for any monotone curve
+    fn inverse(&self) -> Curve {
+        if let Curve::Gamma(g) = self {
+            return Curve::Gamma(1.0 / g);
+        }
+        let forward: Vec<f32> = (0..INVERSE_SAMPLES)
+            .map(|k| self.eval(k as f32 / (INVERSE_SAMPLES - 1) as f32))
+            .collect();
+        let table = (0..INVERSE_SAMPLES)
+            .map(|k| {
+                let y = k as f32 / (INVERSE_SAMPLES - 1) as f32;
+                let above = forward.partition_point(|&v| v < y);
+                match above {
+                    0 => 0.0,
+                    _ if above == INVERSE_SAMPLES => 1.0,
+                    _ => {
+                        let (lo, hi) = (forward[above - 1], forward[above]);
+                        let t = if hi > lo { (y - lo) / (hi - lo) } else { 0.0 };
+                        (above - 1) as f32 / (INVERSE_SAMPLES - 1) as f32
+                            + t / (INVERSE_SAMPLES - 1) as f32
+                    }
+                }
+            })
+            .collect();
+        Curve::Table(table)
+    }
+}
+
+type Matrix = [[f32; 3]; 3];
+
+fn invert(m: &Matrix) -> Option<Matrix> {
+    let det = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let mut inv = [[0.0; 3]; 3];
+    for (i, row) in inv.iter_mut().enumerate() {
+        for (j, x) in row.iter_mut().enumerate() {
+            let (r0, r1) = ((j + 1) % 3, (j + 2) % 3);
+            let (c0, c1) = ((i + 1) % 3, (i + 2) % 3);
+            *x = (m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]) / det;
+        }
+    }
+    Some(inv)
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut out = [[0.0; 3]; 3];
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, x) in row.iter_mut().enumerate() {
+            *x = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    out
+}
+
+// A matrix/TRC ICC profile: per-channel curves to linear light and, for RGB, the matrix from
+// linear RGB to the D50 XYZ connection space. This covers the display and working-space
+// profiles (sRGB, Adobe RGB, Display P3, ProPhoto and most camera and monitor profiles), but
+// not the lookup-table profiles of printers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IccProfile {
+    curves: Vec<Curve>,
+    matrix: Option<Matrix>,
+}
+
+fn s15_fixed16(data: &[u8], at: usize) -> Option<f32> {
+    let bytes = data.get(at..at + 4)?;
+    Some(i32::from_be_bytes(bytes.try_into().unwrap()) as f32 / 65536.0)
+}
+
+fn u16_at(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        data.get(at..at + 2)?.try_into().unwrap(),
+    ))
+}
+
+fn u32_at(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(at..at + 4)?.try_into().unwrap(),
+    ))
+}
+
+fn parse_curve(tag: &[u8]) -> Option<Curve> {
+    match tag.get(..4)? {
+        b"curv" => match u32_at(tag, 8)? {
+            0 => Some(Curve::Gamma(1.0)),
+            1 => Some(Curve::Gamma(u16_at(tag, 12)? as f32 / 256.0)),
+            count => (0..count as usize)
+                .map(|k| Some(u16_at(tag, 12 + 2 * k)? as f32 / 65535.0))
+                .collect::<Option<Vec<_>>>()
+                .map(Curve::Table),
+        },
+        b"para" => {
+            let count = [1, 3, 4, 5, 7].get(u16_at(tag, 8)? as usize).copied()?;
+            let p: Vec<f32> = (0..count)
+                .map(|k| s15_fixed16(tag, 12 + 4 * k))
+                .collect::<Option<_>>()?;
+            let g = p[0];
+            Some(Curve::Parametric(match *p.as_slice() {
+                [_] => [g, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+                [_, a, b] => [g, a, b, 0.0, -b / a, 0.0, 0.0],
+                [_, a, b, c] => [g, a, b, 0.0, -b / a, c, c],
+                [_, a, b, c, d] => [g, a, b, c, d, 0.0, 0.0],
+                [_, a, b, c, d, e, f] => [g, a, b, c, d, e, f],
+                _ => return None,
+            }))
+        }
+        _ => None,
+    }
+}
+
+impl IccProfile {
+    pub fn parse(data: &[u8]) -> Result<Self, IccError> {
+        let invalid = |msg: &str| IccError::InvalidProfile(msg.to_string());
+        if data.get(36..40) != Some(b"acsp") {
+            return Err(invalid("missing `acsp` signature"));
+        }
+        let space = data
+            .get(16..20)
+            .ok_or_else(|| invalid("truncated header"))?;
+        if data.get(20..24) != Some(b"XYZ ") {
+            return Err(IccError::Unsupported(
+                "matrix/TRC profiles connect through XYZ, not Lab".to_string(),
+            ));
+        }
+
+        // The count comes from the file, so it is capped at the entries the data can hold
+        let count = u32_at(data, 128).ok_or_else(|| invalid("truncated tag table"))? as usize;
+        let count = count.min(data.len().saturating_sub(132) / 12);
+        let tag = |signature: &[u8; 4]| -> Option<&[u8]> {
+            (0..count).find_map(|k| {
+                let entry = 132 + 12 * k;
+                if data.get(entry..entry + 4)? != signature {
+                    return None;
+                }
+                let offset = u32_at(data, entry + 4)? as usize;
+                let size = u32_at(data, entry + 8)? as usize;
+                data.get(offset..offset.checked_add(size)?)
+            })
+        };
+        let curve = |signature: &[u8; 4]| -> Result<Curve, IccError> {
+            let name = String::from_utf8_lossy(signature);
+            let tag = tag(signature).ok_or_else(|| {
+                IccError::Unsupported(format!("no `{}` tag, so not a matrix/TRC profile", name))
+            })?;
+            parse_curve(tag).ok_or_else(|| invalid(&format!("malformed `{}` tag", name)))
+        };
+
+        match space {
+            b"GRAY" => Ok(IccProfile {
+                curves: vec![curve(b"kTRC")?],
+                matrix: None,
+            }),
+            b"RGB " => {
+                let mut matrix = [[0.0; 3]; 3];
+                for (j, signature) in [b"rXYZ", b"gXYZ", b"bXYZ"].into_iter().enumerate() {
+                    let name = String::from_utf8_lossy(signature);
+                    let tag = tag(signature)
+                        .filter(|tag| tag.get(..4) == Some(b"XYZ "))
+                        .ok_or_else(|| {
+                            IccError::Unsupported(format!("no `{}` colorant tag", name))
+                        })?;
+                    for (i, row) in matrix.iter_mut().enumerate() {
+                        row[j] = s15_fixed16(tag, 8 + 4 * i)
+                            .ok_or_else(|| invalid(&format!("malformed `{}` tag", name)))?;
+                    }
+                }
+                Ok(IccProfile {
+                    curves: vec![curve(b"rTRC")?, curve(b"gTRC")?, curve(b"bTRC")?],
+                    matrix: Some(matrix),
+                })
+            }
+            _ => Err(IccError::Unsupported(format!(
+                "color space `{}`",
+                String::from_utf8_lossy(space).trim_end()
+            ))),
+        }
+    }
+
+    // sRGB, with the D50-adapted colorants of the standard sRGB profile, as a working space
+    pub fn srgb() -> Self {
+        let curve = Curve::Parametric([
+            2.4,
+            1.0 / 1.055,
+            0.055 / 1.055,
+            1.0 / 12.92,
+            0.04045,
+            0.0,
+            0.0,
+        ]);
+        IccProfile {
+            curves: vec![curve; 3],
+            matrix: Some([
+                [0.436066, 0.385147, 0.143066],
+                [0.222488, 0.716873, 0.060608],
+                [0.013916, 0.097076, 0.714096],
+            ]),
+        }
+    }
+
+    // Grey with the sRGB curve, the working space for grey images
+    pub fn srgb_grey() -> Self {
+        IccProfile {
+            curves: vec![IccProfile::srgb().curves[0].clone()],
+            matrix: None,
+        }
+    }
+
+    pub fn channels(&self) -> usize {
+        self.curves.len()
+    }
+}
+
+// Embedded profile of an encoded image, if its format and decoder support one
+pub fn read_icc_profile<R: BufRead + Seek>(reader: R) -> ImageResult<Option<Vec<u8>>> {
+    ImageReader::new(reader)
+        .with_guessed_format()?
+        .into_decoder()?
+        .icc_profile()
+}
+
+pub trait ColorManage: Planes {
+    // Converts the color channels from profile `from` to profile `to` (alpha is left alone):
+    // e.g. from a camera's profile into `IccProfile::srgb()` before compressing, and back
+    // afterwards so the output matches the original's profile. Both must have as many
+    // channels as the image has color channels. Colors outside the gamut of `to` come out
+    // below 0 or above 255 rather than clipped, until saved.
+    fn convert_profile(&self, from: &IccProfile, to: &IccProfile) -> Result<Self, IccError>
+    where
+        Self: Sized,
+    {
+        let alpha = self.alpha();
+        let colors: Vec<usize> = (0..self.planes().len())
+            .filter(|&k| Some(k) != alpha)
+            .collect();
+        for profile in [from, to] {
+            if profile.channels() != colors.len() {
+                return Err(IccError::ChannelMismatch(profile.channels(), colors.len()));
+            }
+        }
+
+        // Linear RGB in `from` to linear RGB in `to`, through XYZ
+        let matrix = match (from.matrix, to.matrix) {
+            (Some(src), Some(dst)) => Some(multiply(
+                &invert(&dst).ok_or_else(|| {
+                    IccError::InvalidProfile("singular colorant matrix".to_string())
+                })?,
+                &src,
+            )),
+            _ => None,
+        };
+        let inverse: Vec<Curve> = to.curves.iter().map(Curve::inverse).collect();
+
+        let planes = self.planes();
+        let (m, n) = (planes[0].nrows(), planes[0].ncols());
+        let mut out: Vec<Mat<f32>> = planes.to_vec();
+        let mut linear = vec![0.0f32; colors.len()];
+        for j in 0..n {
+            for i in 0..m {
+                for (c, &k) in colors.iter().enumerate() {
+                    linear[c] = from.curves[c].eval(planes[k].read(i, j) / 255.0);
+                }
+                if let Some(matrix) = &matrix {
+                    let rgb = [linear[0], linear[1], linear[2]];
+                    for (c, row) in matrix.iter().enumerate() {
+                        linear[c] = row.iter().zip(rgb).map(|(a, x)| a * x).sum();
+                    }
+                }
+                for (c, &k) in colors.iter().enumerate() {
+                    out[k].write(i, j, 255.0 * inverse[c].eval(linear[c]));
+                }
+            }
+        }
+        Ok(self.rebuild(out))
+    }
+}
+
+impl<W: Planes> ColorManage for W {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn huge_tag_counts_fail_fast() {
+        let mut data = vec![0; 132];
+        data[16..20].copy_from_slice(b"RGB ");
+        data[20..24].copy_from_slice(b"XYZ ");
+        data[36..40].copy_from_slice(b"acsp");
+        data[128..132].copy_from_slice(&u32::MAX.to_be_bytes());
+
+        let start = std::time::Instant::now();
+        assert!(IccProfile::parse(&data).is_err());
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+    }
+}
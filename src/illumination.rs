@@ -0,0 +1,144 @@
+use crate::compress::{SvdApproxError, SvdBackend, SvdFactors, svd};
+use crate::imagewrapper::Planes;
+use crate::stats::channel_stats;
+use faer_core::{Mat, MatRef};
+use rayon::prelude::*;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Background {
+    // Dark subjects on a lit field, e.g. brightfield microscopy or scans: the background is
+    // sampled from block maxima and divided out, as in flat-field correction
+    #[default]
+    Bright,
+    // Bright subjects on a dark field, e.g. fluorescence: the background is sampled from
+    // block minima and subtracted, since dividing by a near-zero field amplifies noise
+    Dark,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BackgroundOptions {
+    // Rank of the illumination model; lighting varies smoothly, so 1 to 3 capture it without
+    // picking up the subject
+    pub rank: usize,
+    // Side of the blocks sampled for the background; must exceed the largest subject features
+    pub block: usize,
+    pub background: Background,
+}
+
+impl BackgroundOptions {
+    pub fn new(rank: usize) -> Self {
+        BackgroundOptions {
+            rank,
+            block: 16,
+            background: Background::default(),
+        }
+    }
+}
+
+// Most background-like value of each `block x block` block
+fn block_extremes(mat: MatRef<f32>, block: usize, background: Background) -> Mat<f32> {
+    let (m, n) = (mat.nrows(), mat.ncols());
+    Mat::from_fn(m.div_ceil(block), n.div_ceil(block), |bi, bj| {
+        let values = (bj * block..((bj + 1) * block).min(n))
+            .flat_map(|j| (bi * block..((bi + 1) * block).min(m)).map(move |i| mat.read(i, j)));
+        match background {
+            Background::Bright => values.fold(f32::NEG_INFINITY, f32::max),
+            Background::Dark => values.fold(f32::INFINITY, f32::min),
+        }
+    })
+}
+
+// Linearly interpolates the rows of a factor of the block samples back to `rows` rows. A sample
+// stands for the middle of its block, and the field extends linearly past the outer ones, since
+// edges and corners are where vignetting is strongest.
+fn upsample_rows(factor: &Mat<f32>, rows: usize, block: usize) -> Mat<f32> {
+    let last = factor.nrows() - 1;
+
+    Mat::from_fn(rows, factor.ncols(), |i, j| {
+        if last == 0 {
+            return factor.read(0, j);
+        }
+        let t = (i as f32 - (block as f32 - 1.0) / 2.0) / block as f32;
+        let lo = (t.floor().max(0.0) as usize).min(last - 1);
+        let frac = t - lo as f32;
+        (1.0 - frac) * factor.read(lo, j) + frac * factor.read(lo + 1, j)
+    })
+}
+
+// Illumination field at full size: a low-rank fit to the block samples, upscaled through its
+// factors as `Compressible::compress_preview` does
+pub(crate) fn background(
+    mat: MatRef<f32>,
+    options: &BackgroundOptions,
+) -> Result<Mat<f32>, SvdApproxError> {
+    let block = options.block.max(1);
+    let samples = block_extremes(mat, block, options.background);
+    let factors = svd(samples.as_ref(), SvdBackend::default())?.truncate(options.rank, false)?;
+
+    Ok(SvdFactors {
+        u: upsample_rows(&factors.u, mat.nrows(), block),
+        v: upsample_rows(&factors.v, mat.ncols(), block),
+        ..factors
+    }
+    .reconstruct())
+}
+
+fn correct(mat: MatRef<f32>, options: &BackgroundOptions) -> Result<Mat<f32>, SvdApproxError> {
+    let field = background(mat, options)?;
+    // Rescaled to the field's mean, so the overall level stays where it was
+    let mean = channel_stats(field.as_ref()).mean;
+
+    Ok(Mat::from_fn(mat.nrows(), mat.ncols(), |i, j| {
+        let (x, f) = (mat.read(i, j), field.read(i, j));
+        match options.background {
+            Background::Bright => x * mean / f.max(f32::EPSILON),
+            Background::Dark => x - f + mean,
+        }
+    }))
+}
+
+// Uneven lighting, vignetting and sensor shading, modeled per channel as a low-rank surface
+pub trait Illumination: Planes {
+    fn estimate_background(&self, rank: usize) -> Result<Self, SvdApproxError>
+    where
+        Self: Sized,
+    {
+        self.estimate_background_with(&BackgroundOptions::new(rank))
+    }
+
+    fn estimate_background_with(&self, options: &BackgroundOptions) -> Result<Self, SvdApproxError>
+    where
+        Self: Sized,
+    {
+        let mats = self
+            .planes()
+            .par_iter()
+            .map(|mat| background(mat.as_ref(), options))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(self.rebuild(mats))
+    }
+
+    // The image with `estimate_background` divided out (or subtracted, for `Background::Dark`)
+    fn correct_illumination(&self, rank: usize) -> Result<Self, SvdApproxError>
+    where
+        Self: Sized,
+    {
+        self.correct_illumination_with(&BackgroundOptions::new(rank))
+    }
+
+    fn correct_illumination_with(&self, options: &BackgroundOptions) -> Result<Self, SvdApproxError>
+    where
+        Self: Sized,
+    {
+        let mats = self
+            .planes()
+            .par_iter()
+            .map(|mat| correct(mat.as_ref(), options))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(self.rebuild(mats))
+    }
+}
+
+impl<W: Planes> Illumination for W {}
@@ -1,7 +1,11 @@
-use faer_core::Mat;
+use crate::diagnostics::{check_downcast, downcast_error};
+use crate::instrument::span;
+use faer_core::{Mat, MatRef};
 use image::*;
 use std::array;
-use std::io::{BufReader, Read, Seek, Write};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Cursor, Read, Seek, Write};
+use std::path::{Path, PathBuf};
 
 pub trait ImageWrapper {
     fn load<R: Read + Seek>(reader: R) -> ImageResult<Self>
@@ -9,86 +13,634 @@ pub trait ImageWrapper {
         Self: Sized;
 
     fn save<W: Write + Seek>(&self, writer: W, format: ImageFormat) -> ImageResult<()>;
+
+    // For pipes, sockets and the like: some encoders (e.g. TIFF) seek back to patch offsets, so
+    // the image is encoded in memory first
+    fn save_stream<W: Write>(&self, mut writer: W, format: ImageFormat) -> ImageResult<()> {
+        let mut buf = Cursor::new(Vec::new());
+        self.save(&mut buf, format)?;
+        writer.write_all(buf.get_ref())?;
+        Ok(())
+    }
+}
+
+// Uniform access to the per-channel matrices of a wrapper, whatever its channel count
+pub trait Planes {
+    fn planes(&self) -> &[Mat<f32>];
+
+    fn planes_mut(&mut self) -> &mut [Mat<f32>];
+
+    fn map_planes<F>(&self, f: F) -> Self
+    where
+        F: Fn(MatRef<f32>) -> Mat<f32> + Sync,
+        Self: Sized;
+
+    // Builds a wrapper of the same kind from new planes; panics if the channel count differs
+    fn rebuild(&self, planes: Vec<Mat<f32>>) -> Self
+    where
+        Self: Sized;
+
+    // Index of the plane holding alpha, if any
+    fn alpha(&self) -> Option<usize> {
+        None
+    }
+
+    // Largest rank the SVD of a plane can have, so valid ranks for compression are
+    // `0..=max_rank()`; rank 0 gives each channel's mean
+    fn max_rank(&self) -> usize {
+        let mat = &self.planes()[0];
+        mat.nrows().min(mat.ncols())
+    }
+}
+
+// Shared by the `Display` impls of all wrappers
+pub(crate) fn describe(
+    f: &mut std::fmt::Formatter,
+    kind: &str,
+    width: usize,
+    height: usize,
+    channels: usize,
+) -> std::fmt::Result {
+    write!(
+        f,
+        "{} image {}x{}, {} channel{}, max rank {}",
+        kind,
+        width,
+        height,
+        channels,
+        if channels == 1 { "" } else { "s" },
+        width.min(height)
+    )
+}
+
+pub(crate) fn decode<R: Read + Seek>(reader: R) -> ImageResult<DynamicImage> {
+    let mut reader = BufReader::new(reader);
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+    let format = guess_format(&buf)?;
+
+    let span = span("decode");
+    let img = load_from_memory_with_format(&buf, format)?;
+    span.finish(img.height() as usize, img.width() as usize);
+
+    // Every wrapper decoded here holds 8 bits per sample
+    check_downcast(img.color()).map_err(downcast_error)?;
+    Ok(img)
+}
+
+// Splits an 8-bit image buffer into one `height x width` matrix per channel. The mirror image
+// of `from_mats`: a block of columns is read row by row from the interleaved buffer, then each
+// block column is written out contiguously into its column-major matrix.
+pub(crate) fn to_mats<P, const N: usize>(img: &ImageBuffer<P, Vec<u8>>) -> [Mat<f32>; N]
+where
+    P: Pixel<Subpixel = u8>,
+{
+    const BLOCK: usize = 16;
+
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    let buf = img.as_raw();
+    let mut mats: [Mat<f32>; N] = array::from_fn(|_| Mat::zeros(height, width));
+    let mut block = vec![0.0f32; BLOCK * height];
+
+    for x0 in (0..width).step_by(BLOCK) {
+        let cols = BLOCK.min(width - x0);
+
+        for (k, mat) in mats.iter_mut().enumerate() {
+            for y in 0..height {
+                let row = &buf[(y * width + x0) * N..];
+                for b in 0..cols {
+                    block[b * height + y] = row[b * N + k] as f32;
+                }
+            }
+
+            for b in 0..cols {
+                mat.col_as_slice_mut(x0 + b)
+                    .copy_from_slice(&block[b * height..(b + 1) * height]);
+            }
+        }
+    }
+
+    mats
+}
+
+// Clamps and truncates to u8 over fixed-width chunks, which the compiler vectorizes
+pub(crate) fn quantize(src: &[f32], dst: &mut [u8]) {
+    const LANES: usize = 16;
+
+    let mut src_chunks = src.chunks_exact(LANES);
+    let mut dst_chunks = dst.chunks_exact_mut(LANES);
+
+    for (s, d) in (&mut src_chunks).zip(&mut dst_chunks) {
+        for i in 0..LANES {
+            d[i] = s[i].clamp(0.0, 255.0) as u8;
+        }
+    }
+
+    for (s, d) in src_chunks
+        .remainder()
+        .iter()
+        .zip(dst_chunks.into_remainder())
+    {
+        *d = s.clamp(0.0, 255.0) as u8;
+    }
+}
+
+// Clamps and quantizes one matrix per channel back into an 8-bit image buffer
+pub(crate) fn from_mats<P, const N: usize>(
+    mats: &[Mat<f32>; N],
+    width: usize,
+    height: usize,
+) -> ImageBuffer<P, Vec<u8>>
+where
+    P: Pixel<Subpixel = u8>,
+{
+    // faer matrices are column-major, so quantize a block of contiguous columns at a time and
+    // then interleave the block into the row-major pixel buffer
+    const BLOCK: usize = 16;
+
+    let mut buf = vec![0u8; width * height * N];
+    let mut block = vec![0u8; BLOCK * height];
+
+    for (k, mat) in mats.iter().enumerate() {
+        for x0 in (0..width).step_by(BLOCK) {
+            let cols = BLOCK.min(width - x0);
+
+            for b in 0..cols {
+                quantize(
+                    mat.col_as_slice(x0 + b),
+                    &mut block[b * height..(b + 1) * height],
+                );
+            }
+
+            for y in 0..height {
+                let row = &mut buf[(y * width + x0) * N..];
+                for b in 0..cols {
+                    row[b * N + k] = block[b * height + y];
+                }
+            }
+        }
+    }
+
+    ImageBuffer::from_raw(width as u32, height as u32, buf).unwrap()
+}
+
+// Writes `<stem>_<name>.<ext>` for each plane as a grey image and returns the paths written
+fn save_planes(
+    mats: &[Mat<f32>],
+    names: &[&str],
+    width: usize,
+    height: usize,
+    stem: &Path,
+    format: ImageFormat,
+) -> ImageResult<Vec<PathBuf>> {
+    let ext = format.extensions_str().first().copied().unwrap_or("img");
+
+    mats.iter()
+        .zip(names)
+        .map(|(mat, name)| {
+            let mut file_name = stem.file_name().unwrap_or_default().to_os_string();
+            file_name.push(format!("_{}.{}", name, ext));
+            let path = stem.with_file_name(file_name);
+
+            let grey = GreyImageWrapper {
+                mat: mat.clone(),
+                width,
+                height,
+            };
+            let mut writer = BufWriter::new(File::create(&path)?);
+            grey.save(&mut writer, format)?;
+            writer.flush()?;
+            Ok(path)
+        })
+        .collect()
+}
+
+// The common `(width, height)` of `channels`, checked against the matrices as well as the fields
+fn same_size(channels: &[GreyImageWrapper]) -> Option<(usize, usize)> {
+    let (width, height) = (channels[0].width, channels[0].height);
+    channels
+        .iter()
+        .all(|c| {
+            (c.width, c.height, c.mat.ncols(), c.mat.nrows()) == (width, height, width, height)
+        })
+        .then_some((width, height))
 }
 
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GreyImageWrapper {
     pub mat: Mat<f32>,
     pub width: usize,
     pub height: usize,
 }
 
+impl GreyImageWrapper {
+    pub(crate) fn from_image(img: &GrayImage) -> Self {
+        let [mat] = to_mats(img);
+        let (width, height) = img.dimensions();
+
+        Self {
+            mat,
+            width: width as usize,
+            height: height as usize,
+        }
+    }
+
+    pub(crate) fn to_image(&self) -> GrayImage {
+        from_mats(array::from_ref(&self.mat), self.width, self.height)
+    }
+}
+
+impl Planes for GreyImageWrapper {
+    fn planes(&self) -> &[Mat<f32>] {
+        array::from_ref(&self.mat)
+    }
+
+    fn planes_mut(&mut self) -> &mut [Mat<f32>] {
+        array::from_mut(&mut self.mat)
+    }
+
+    fn map_planes<F>(&self, f: F) -> Self
+    where
+        F: Fn(MatRef<f32>) -> Mat<f32> + Sync,
+    {
+        let mat = f(self.mat.as_ref());
+        let (height, width) = (mat.nrows(), mat.ncols());
+        Self { mat, width, height }
+    }
+
+    fn rebuild(&self, planes: Vec<Mat<f32>>) -> Self {
+        let [mat]: [Mat<f32>; 1] = planes.try_into().expect("expected 1 plane");
+        let (height, width) = (mat.nrows(), mat.ncols());
+        Self { mat, width, height }
+    }
+}
+
+impl std::fmt::Display for GreyImageWrapper {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        describe(f, "Grey", self.width, self.height, 1)
+    }
+}
+
 impl ImageWrapper for GreyImageWrapper {
     fn load<R: Read + Seek>(reader: R) -> ImageResult<Self> {
-        let mut reader = BufReader::new(reader);
-        let mut buf = Vec::new();
-        reader.read_to_end(&mut buf)?;
-        let format = guess_format(&buf)?;
+        Ok(Self::from_image(&decode(reader)?.into_luma8()))
+    }
 
-        let dyn_img = load_from_memory_with_format(&buf, format)?.into_luma8();
-        let (width, height) = dyn_img.dimensions();
-        let width = width as usize;
-        let height = height as usize;
+    fn save<W: Write + Seek>(&self, mut writer: W, format: ImageFormat) -> ImageResult<()> {
+        let span = span("encode");
+        self.to_image().write_to(&mut writer, format)?;
+        span.finish(self.height, self.width);
+        Ok(())
+    }
+}
 
-        let mat = Mat::from_fn(height, width, |i, j| {
-            let pixel = dyn_img.get_pixel(j as u32, i as u32);
-            pixel[0] as f32
-        });
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GreyAlphaImageWrapper {
+    pub mats: [Mat<f32>; 2],
+    pub width: usize,
+    pub height: usize,
+}
+
+impl GreyAlphaImageWrapper {
+    pub(crate) fn from_image(img: &GrayAlphaImage) -> Self {
+        let (width, height) = img.dimensions();
 
-        Ok(Self { mat, width, height })
+        Self {
+            mats: to_mats(img),
+            width: width as usize,
+            height: height as usize,
+        }
     }
 
-    fn save<W: Write + Seek>(&self, mut writer: W, format: ImageFormat) -> ImageResult<()> {
-        let img = GrayImage::from_fn(self.width as u32, self.height as u32, |i, j| {
-            let pixel_value = self.mat.get(j as usize, i as usize).clamp(0.0, 255.0) as u8;
-            Luma([pixel_value])
-        });
+    pub(crate) fn to_image(&self) -> GrayAlphaImage {
+        from_mats(&self.mats, self.width, self.height)
+    }
+}
+
+impl Planes for GreyAlphaImageWrapper {
+    fn planes(&self) -> &[Mat<f32>] {
+        &self.mats
+    }
+
+    fn planes_mut(&mut self) -> &mut [Mat<f32>] {
+        &mut self.mats
+    }
+
+    fn alpha(&self) -> Option<usize> {
+        Some(1)
+    }
 
-        img.write_to(&mut writer, format)?;
+    fn map_planes<F>(&self, f: F) -> Self
+    where
+        F: Fn(MatRef<f32>) -> Mat<f32> + Sync,
+    {
+        let mats = self.mats.each_ref().map(|mat| f(mat.as_ref()));
+        let (height, width) = (mats[0].nrows(), mats[0].ncols());
+
+        Self {
+            mats,
+            width,
+            height,
+        }
+    }
+
+    fn rebuild(&self, planes: Vec<Mat<f32>>) -> Self {
+        let mats: [Mat<f32>; 2] = planes.try_into().expect("expected 2 planes");
+        let (height, width) = (mats[0].nrows(), mats[0].ncols());
+
+        Self {
+            mats,
+            width,
+            height,
+        }
+    }
+}
+
+impl std::fmt::Display for GreyAlphaImageWrapper {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        describe(f, "Grey+alpha", self.width, self.height, 2)
+    }
+}
+
+impl ImageWrapper for GreyAlphaImageWrapper {
+    fn load<R: Read + Seek>(reader: R) -> ImageResult<Self> {
+        Ok(Self::from_image(&decode(reader)?.into_luma_alpha8()))
+    }
+
+    fn save<W: Write + Seek>(&self, mut writer: W, format: ImageFormat) -> ImageResult<()> {
+        let span = span("encode");
+        self.to_image().write_to(&mut writer, format)?;
+        span.finish(self.height, self.width);
         Ok(())
     }
 }
 
+// Weights of R, G and B in a luma value
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LumaWeights {
+    // SD video and JPEG, as used by `image`'s own grey conversion
+    #[default]
+    Rec601,
+    // HD video and sRGB
+    Rec709,
+    Custom([f32; 3]),
+}
+
+impl LumaWeights {
+    pub fn coefficients(self) -> [f32; 3] {
+        match self {
+            LumaWeights::Rec601 => [0.299, 0.587, 0.114],
+            LumaWeights::Rec709 => [0.2126, 0.7152, 0.0722],
+            LumaWeights::Custom(weights) => weights,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RgbImageWrapper {
     pub mats: [Mat<f32>; 3],
     pub width: usize,
     pub height: usize,
 }
 
+impl RgbImageWrapper {
+    pub(crate) fn from_image(img: &RgbImage) -> Self {
+        let (width, height) = img.dimensions();
+
+        Self {
+            mats: to_mats(img),
+            width: width as usize,
+            height: height as usize,
+        }
+    }
+
+    pub(crate) fn to_image(&self) -> RgbImage {
+        from_mats(&self.mats, self.width, self.height)
+    }
+
+    // Saves each channel as a grey image, e.g. `out/photo_r.png`, `_g` and `_b` for the stem
+    // `out/photo`, to see which channel is hurt most by compression
+    pub fn save_channels(
+        &self,
+        stem: impl AsRef<Path>,
+        format: ImageFormat,
+    ) -> ImageResult<Vec<PathBuf>> {
+        save_planes(
+            &self.mats,
+            &["r", "g", "b"],
+            self.width,
+            self.height,
+            stem.as_ref(),
+            format,
+        )
+    }
+
+    // Panics if `i` is not 0 (red), 1 (green) or 2 (blue)
+    pub fn channel(&self, i: usize) -> GreyImageWrapper {
+        GreyImageWrapper {
+            mat: self.mats[i].clone(),
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    // Weighted sum of the channels in float, without rounding to u8 in between. The values are
+    // applied to gamma-encoded samples as they are, like `image` does.
+    pub fn to_grey(&self, weights: LumaWeights) -> GreyImageWrapper {
+        let [r, g, b] = weights.coefficients();
+        let [red, green, blue] = &self.mats;
+
+        GreyImageWrapper {
+            mat: Mat::from_fn(self.height, self.width, |i, j| {
+                r * red.read(i, j) + g * green.read(i, j) + b * blue.read(i, j)
+            }),
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    // Returns `None` unless all channels have the same dimensions
+    pub fn from_channels(channels: [GreyImageWrapper; 3]) -> Option<Self> {
+        let (width, height) = same_size(&channels)?;
+        Some(Self {
+            mats: channels.map(|channel| channel.mat),
+            width,
+            height,
+        })
+    }
+}
+
+impl Planes for RgbImageWrapper {
+    fn planes(&self) -> &[Mat<f32>] {
+        &self.mats
+    }
+
+    fn planes_mut(&mut self) -> &mut [Mat<f32>] {
+        &mut self.mats
+    }
+
+    fn map_planes<F>(&self, f: F) -> Self
+    where
+        F: Fn(MatRef<f32>) -> Mat<f32> + Sync,
+    {
+        let mats = self.mats.each_ref().map(|mat| f(mat.as_ref()));
+        let (height, width) = (mats[0].nrows(), mats[0].ncols());
+
+        Self {
+            mats,
+            width,
+            height,
+        }
+    }
+
+    fn rebuild(&self, planes: Vec<Mat<f32>>) -> Self {
+        let mats: [Mat<f32>; 3] = planes.try_into().expect("expected 3 planes");
+        let (height, width) = (mats[0].nrows(), mats[0].ncols());
+
+        Self {
+            mats,
+            width,
+            height,
+        }
+    }
+}
+
+impl std::fmt::Display for RgbImageWrapper {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        describe(f, "RGB", self.width, self.height, 3)
+    }
+}
+
 impl ImageWrapper for RgbImageWrapper {
     fn load<R: Read + Seek>(reader: R) -> ImageResult<Self> {
-        let mut reader = BufReader::new(reader);
-        let mut buf = Vec::new();
-        reader.read_to_end(&mut buf)?;
-        let format = guess_format(&buf)?;
-
-        let dyn_img = load_from_memory_with_format(&buf, format)?.into_rgb8();
-        let (width, height) = dyn_img.dimensions();
-        let width = width as usize;
-        let height = height as usize;
-
-        let mats = array::from_fn(|k| {
-            Mat::from_fn(height, width, |j, i| {
-                let pixel = dyn_img.get_pixel(i as u32, j as u32);
-                pixel[k] as f32
-            })
-        });
-
-        Ok(Self {
-            mats,
+        Ok(Self::from_image(&decode(reader)?.into_rgb8()))
+    }
+
+    fn save<W: Write + Seek>(&self, mut writer: W, format: ImageFormat) -> ImageResult<()> {
+        let span = span("encode");
+        self.to_image().write_to(&mut writer, format)?;
+        span.finish(self.height, self.width);
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RgbaImageWrapper {
+    pub mats: [Mat<f32>; 4],
+    pub width: usize,
+    pub height: usize,
+}
+
+impl RgbaImageWrapper {
+    pub(crate) fn from_image(img: &RgbaImage) -> Self {
+        let (width, height) = img.dimensions();
+
+        Self {
+            mats: to_mats(img),
+            width: width as usize,
+            height: height as usize,
+        }
+    }
+
+    pub(crate) fn to_image(&self) -> RgbaImage {
+        from_mats(&self.mats, self.width, self.height)
+    }
+
+    // Like `RgbImageWrapper::save_channels`, with `_a` for alpha
+    pub fn save_channels(
+        &self,
+        stem: impl AsRef<Path>,
+        format: ImageFormat,
+    ) -> ImageResult<Vec<PathBuf>> {
+        save_planes(
+            &self.mats,
+            &["r", "g", "b", "a"],
+            self.width,
+            self.height,
+            stem.as_ref(),
+            format,
+        )
+    }
+
+    // Panics if `i` is not 0 (red), 1 (green), 2 (blue) or 3 (alpha)
+    pub fn channel(&self, i: usize) -> GreyImageWrapper {
+        GreyImageWrapper {
+            mat: self.mats[i].clone(),
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    // Returns `None` unless all channels have the same dimensions
+    pub fn from_channels(channels: [GreyImageWrapper; 4]) -> Option<Self> {
+        let (width, height) = same_size(&channels)?;
+        Some(Self {
+            mats: channels.map(|channel| channel.mat),
             width,
             height,
         })
     }
+}
+
+impl Planes for RgbaImageWrapper {
+    fn planes(&self) -> &[Mat<f32>] {
+        &self.mats
+    }
+
+    fn planes_mut(&mut self) -> &mut [Mat<f32>] {
+        &mut self.mats
+    }
+
+    fn alpha(&self) -> Option<usize> {
+        Some(3)
+    }
+
+    fn map_planes<F>(&self, f: F) -> Self
+    where
+        F: Fn(MatRef<f32>) -> Mat<f32> + Sync,
+    {
+        let mats = self.mats.each_ref().map(|mat| f(mat.as_ref()));
+        let (height, width) = (mats[0].nrows(), mats[0].ncols());
+
+        Self {
+            mats,
+            width,
+            height,
+        }
+    }
+
+    fn rebuild(&self, planes: Vec<Mat<f32>>) -> Self {
+        let mats: [Mat<f32>; 4] = planes.try_into().expect("expected 4 planes");
+        let (height, width) = (mats[0].nrows(), mats[0].ncols());
+
+        Self {
+            mats,
+            width,
+            height,
+        }
+    }
+}
+
+impl std::fmt::Display for RgbaImageWrapper {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        describe(f, "RGBA", self.width, self.height, 4)
+    }
+}
+
+impl ImageWrapper for RgbaImageWrapper {
+    fn load<R: Read + Seek>(reader: R) -> ImageResult<Self> {
+        Ok(Self::from_image(&decode(reader)?.into_rgba8()))
+    }
 
     fn save<W: Write + Seek>(&self, mut writer: W, format: ImageFormat) -> ImageResult<()> {
-        let image = RgbImage::from_fn(self.width as u32, self.height as u32, |i, j| {
-            let pixel_values = array::from_fn(|k| {
-                self.mats[k].get(j as usize, i as usize).clamp(0.0, 255.0) as u8
-            });
-            Rgb(pixel_values)
-        });
-
-        image.write_to(&mut writer, format)?;
+        let span = span("encode");
+        self.to_image().write_to(&mut writer, format)?;
+        span.finish(self.height, self.width);
         Ok(())
     }
 }
@@ -0,0 +1,119 @@
+use std::cell::RefCell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+// Time spent in each pipeline stage. Channels compressed in parallel each add their own time,
+// so `svd` and `reconstruct` can exceed the wall-clock time of the compression.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Timings {
+    pub decode: Duration,
+    pub svd: Duration,
+    pub reconstruct: Duration,
+    pub encode: Duration,
+}
+
+// Nanoseconds per stage, in the order of the fields of `Timings`
+#[derive(Default)]
+pub(crate) struct Collector([AtomicU64; 4]);
+
+impl Collector {
+    fn add(&self, stage: &str, elapsed: Duration) {
+        let index = match stage {
+            "decode" => 0,
+            "svd" => 1,
+            "reconstruct" => 2,
+            "encode" => 3,
+            _ => return,
+        };
+        self.0[index].fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    fn timings(&self) -> Timings {
+        let stage = |k: usize| Duration::from_nanos(self.0[k].load(Ordering::Relaxed));
+        Timings {
+            decode: stage(0),
+            svd: stage(1),
+            reconstruct: stage(2),
+            encode: stage(3),
+        }
+    }
+}
+
+thread_local! {
+    static COLLECTOR: RefCell<Option<Arc<Collector>>> = const { RefCell::new(None) };
+}
+
+// The collector spans on this thread report to, for handing on to worker threads with `enter`
+pub(crate) fn current() -> Option<Arc<Collector>> {
+    COLLECTOR.with(|collector| collector.borrow().clone())
+}
+
+// Restores the thread's previous collector when dropped
+pub(crate) struct Guard(Option<Arc<Collector>>);
+
+pub(crate) fn enter(collector: Option<Arc<Collector>>) -> Guard {
+    Guard(COLLECTOR.with(|current| current.replace(collector)))
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        COLLECTOR.with(|current| *current.borrow_mut() = self.0.take());
+    }
+}
+
+// Runs `f`, returning how long the decoding, SVDs, reconstructions and encoding it did on this
+// thread took, including the channels `compress_with` and its variants fan out to the pool.
+// Work `f` hands to other threads itself, such as a spawned job, is not counted.
+pub fn timed<T>(f: impl FnOnce() -> T) -> (T, Timings) {
+    let collector = Arc::new(Collector::default());
+    let result = {
+        let _guard = enter(Some(Arc::clone(&collector)));
+        f()
+    };
+    (result, collector.timings())
+}
+
+// Timing spans for the main pipeline stages, logged at debug level together with the dimensions
+// of the matrix or image involved, and added to the thread's collector inside `timed`. With
+// neither, they cost a thread-local lookup.
+pub(crate) struct Span {
+    stage: &'static str,
+    start: Option<Instant>,
+    collector: Option<Arc<Collector>>,
+}
+
+pub(crate) fn span(stage: &'static str) -> Span {
+    let collector = current();
+    Span {
+        stage,
+        start: (cfg!(feature = "log") || collector.is_some()).then(Instant::now),
+        collector,
+    }
+}
+
+impl Span {
+    pub(crate) fn finish(self, rows: usize, cols: usize) {
+        let Some(start) = self.start else {
+            return;
+        };
+        let elapsed = start.elapsed();
+
+        #[cfg(feature = "log")]
+        log::debug!(
+            target: "svdimagecompress",
+            "{} rows={} cols={} elapsed={:?}",
+            self.stage,
+            rows,
+            cols,
+            elapsed
+        );
+        #[cfg(not(feature = "log"))]
+        let _ = (rows, cols);
+
+        if let Some(collector) = &self.collector {
+            collector.add(self.stage, elapsed);
+        }
+    }
+}
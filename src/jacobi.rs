@@ -0,0 +1,96 @@
+use crate::compress::{SvdFactors, SvdTolerance};
+use faer_core::{Mat, MatRef};
+
+// Sweeps after which the rotations are taken to have converged as far as they will
+const MAX_SWEEPS: usize = 60;
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+// Replaces `(a, b)` by `(c * a - s * b, s * a + c * b)`
+fn rotate(a: &mut [f64], b: &mut [f64], c: f64, s: f64) {
+    for (x, y) in a.iter_mut().zip(b.iter_mut()) {
+        let (p, q) = (*x, *y);
+        *x = c * p - s * q;
+        *y = s * p + c * q;
+    }
+}
+
+// One-sided (Hestenes) Jacobi SVD in f64. Rotating pairs of columns until all are mutually
+// orthogonal leaves `A V = U S`, with every singular value accurate to `epsilon` relative to
+// itself rather than to the largest one, as bidiagonalization gives. Each sweep costs
+// `O(m n min(m, n))`, several times a bidiagonal SVD, and takes around ten to converge.
+pub(crate) fn jacobi_svd(mat: MatRef<f32>, tolerance: SvdTolerance) -> SvdFactors {
+    // Rotate the columns of whichever of `A` and its transpose has fewer of them
+    let transposed = mat.nrows() < mat.ncols();
+    let a = if transposed { mat.transpose() } else { mat };
+    let (m, n) = (a.nrows(), a.ncols());
+
+    let mut w: Vec<Vec<f64>> = (0..n)
+        .map(|j| (0..m).map(|i| a.read(i, j) as f64).collect())
+        .collect();
+    let mut v: Vec<Vec<f64>> = (0..n)
+        .map(|j| (0..n).map(|i| if i == j { 1.0 } else { 0.0 }).collect())
+        .collect();
+    let mut norms: Vec<f64> = w.iter().map(|col| dot(col, col)).collect();
+
+    let epsilon = tolerance.epsilon as f64;
+    let zero = tolerance.zero_threshold as f64;
+
+    for _ in 0..MAX_SWEEPS {
+        let mut rotated = false;
+
+        for p in 0..n {
+            for q in p + 1..n {
+                let (alpha, beta) = (norms[p], norms[q]);
+                if alpha <= zero || beta <= zero {
+                    continue;
+                }
+                let gamma = dot(&w[p], &w[q]);
+                if gamma.abs() <= epsilon * (alpha * beta).sqrt() {
+                    continue;
+                }
+                rotated = true;
+
+                // The rotation annihilating the off-diagonal entry of `[alpha gamma; gamma beta]`
+                let zeta = (beta - alpha) / (2.0 * gamma);
+                let t = zeta.signum() / (zeta.abs() + (1.0 + zeta * zeta).sqrt());
+                let c = 1.0 / (1.0 + t * t).sqrt();
+                let s = c * t;
+
+                let (left, right) = w.split_at_mut(q);
+                rotate(&mut left[p], &mut right[0], c, s);
+                let (left, right) = v.split_at_mut(q);
+                rotate(&mut left[p], &mut right[0], c, s);
+                norms[p] = alpha - t * gamma;
+                norms[q] = beta + t * gamma;
+            }
+        }
+
+        // The running norms drift with each update, so start every sweep from exact ones
+        norms = w.iter().map(|col| dot(col, col)).collect();
+        if !rotated {
+            break;
+        }
+    }
+
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|&i, &j| norms[j].total_cmp(&norms[i]));
+    let sigma: Vec<f64> = order.iter().map(|&j| norms[j].sqrt()).collect();
+
+    // Columns of `A V` normalized are U; a vanishing one has no direction and is left zero
+    let us = Mat::from_fn(m, n, |i, k| {
+        if sigma[k] > zero {
+            (w[order[k]][i] / sigma[k]) as f32
+        } else {
+            0.0
+        }
+    });
+    let vs = Mat::from_fn(n, n, |i, k| v[order[k]][i] as f32);
+    let s: Vec<f32> = sigma.iter().map(|&x| x as f32).collect();
+    let energy = s.iter().map(|x| x * x).sum();
+
+    let (u, v) = if transposed { (vs, us) } else { (us, vs) };
+    SvdFactors { u, s, v, energy }
+}
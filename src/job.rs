@@ -0,0 +1,226 @@
+use crate::compress::SvdApproxError;
+use std::cell::Cell;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+// How long a yielding job sleeps when there is no other work it could run meanwhile
+const YIELD_SLEEP: Duration = Duration::from_millis(1);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Priority {
+    Background,
+    #[default]
+    Normal,
+    // For previews a user is waiting on
+    Interactive,
+}
+
+impl Priority {
+    const ALL: [Priority; 3] = [
+        Priority::Background,
+        Priority::Normal,
+        Priority::Interactive,
+    ];
+}
+
+// Spawned jobs not yet finished, by priority
+static ACTIVE: [AtomicUsize; 3] = [const { AtomicUsize::new(0) }; 3];
+
+fn higher_active(priority: Priority) -> bool {
+    Priority::ALL[priority as usize + 1..]
+        .iter()
+        .any(|&p| ACTIVE[p as usize].load(Ordering::Acquire) > 0)
+}
+
+thread_local! {
+    // Jobs whose work is running on this thread
+    static RUNNING: Cell<usize> = const { Cell::new(0) };
+}
+
+struct Running;
+
+impl Running {
+    fn enter() -> Self {
+        RUNNING.with(|running| running.set(running.get() + 1));
+        Running
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        RUNNING.with(|running| running.set(running.get() - 1));
+    }
+}
+
+// Shared between a running job and its handle: channels finished out of `total`, and whether
+// the caller asked the job to stop
+pub(crate) struct Progress {
+    done: AtomicUsize,
+    total: usize,
+    cancelled: AtomicBool,
+    priority: Priority,
+}
+
+impl Progress {
+    pub(crate) fn check(&self) -> Result<(), SvdApproxError> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            Err(SvdApproxError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    // Called before the job's work starts. While a job of higher priority is unfinished, this
+    // one waits: instead of blocking its worker thread, which the other job may need, it runs
+    // other queued pool work until the way is clear. A thread already inside a job's work (a
+    // pool worker can pick up this job while waiting on its own channels) never waits, since
+    // that job, which may be the very one of higher priority, can't finish until this one does.
+    fn wait_turn(&self) -> Result<(), SvdApproxError> {
+        self.check()?;
+        if RUNNING.with(|running| running.get() > 0) {
+            return Ok(());
+        }
+        while higher_active(self.priority) {
+            if rayon::yield_now() != Some(rayon::Yield::Executed) {
+                std::thread::sleep(YIELD_SLEEP);
+            }
+            self.check()?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn channel_done(&self) {
+        self.done.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+struct Slot<T> {
+    // A panic inside the job is caught here and resumed in whoever takes the result
+    result: Option<std::thread::Result<Result<T, SvdApproxError>>>,
+    finished: bool,
+    waker: Option<Waker>,
+}
+
+struct Shared<T> {
+    progress: Progress,
+    slot: Mutex<Slot<T>>,
+    finished: Condvar,
+}
+
+// A compression running on rayon's global pool. Block on it with `wait`, poll it with
+// `try_wait`, or `.await` it: it implements `Future` without needing any particular runtime.
+// Dropping the handle detaches the job rather than cancelling it.
+pub struct JobHandle<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> JobHandle<T> {
+    // Fraction of channels finished, in [0, 1]
+    pub fn progress(&self) -> f32 {
+        let progress = &self.shared.progress;
+        if progress.total == 0 {
+            return if self.is_finished() { 1.0 } else { 0.0 };
+        }
+        progress.done.load(Ordering::Relaxed) as f32 / progress.total as f32
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.shared.slot.lock().unwrap().finished
+    }
+
+    // Asks the job to stop; channels not yet started are skipped, though one already inside
+    // its SVD runs to the end. The job then finishes with `SvdApproxError::Cancelled`.
+    pub fn cancel(&self) {
+        self.shared
+            .progress
+            .cancelled
+            .store(true, Ordering::Relaxed);
+    }
+
+    // Blocks until the job finishes
+    pub fn wait(self) -> Result<T, SvdApproxError> {
+        let mut slot = self.shared.slot.lock().unwrap();
+        while !slot.finished {
+            slot = self.shared.finished.wait(slot).unwrap();
+        }
+        take(&mut slot)
+    }
+
+    // The result if the job has finished, without blocking. It can only be taken once, by this,
+    // `wait` or the future; taking it again panics.
+    pub fn try_wait(&self) -> Option<Result<T, SvdApproxError>> {
+        let mut slot = self.shared.slot.lock().unwrap();
+        slot.finished.then(|| take(&mut slot))
+    }
+}
+
+fn take<T>(slot: &mut Slot<T>) -> Result<T, SvdApproxError> {
+    match slot.result.take() {
+        Some(Ok(result)) => result,
+        Some(Err(payload)) => panic::resume_unwind(payload),
+        None => panic!("job result was already taken"),
+    }
+}
+
+impl<T> Future for JobHandle<T> {
+    type Output = Result<T, SvdApproxError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mut slot = self.shared.slot.lock().unwrap();
+        if slot.finished {
+            Poll::Ready(take(&mut slot))
+        } else {
+            slot.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+// Runs `work` on rayon's global pool, reporting progress over `total` channels. Jobs of lower
+// priority that have not started yet wait for this one from the moment it is queued.
+pub(crate) fn spawn<T, F>(total: usize, priority: Priority, work: F) -> JobHandle<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&Progress) -> Result<T, SvdApproxError> + Send + 'static,
+{
+    let shared = Arc::new(Shared {
+        progress: Progress {
+            done: AtomicUsize::new(0),
+            total,
+            cancelled: AtomicBool::new(false),
+            priority,
+        },
+        slot: Mutex::new(Slot {
+            result: None,
+            finished: false,
+            waker: None,
+        }),
+        finished: Condvar::new(),
+    });
+
+    ACTIVE[priority as usize].fetch_add(1, Ordering::AcqRel);
+    let worker = Arc::clone(&shared);
+    rayon::spawn(move || {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            worker.progress.wait_turn()?;
+            let _running = Running::enter();
+            work(&worker.progress)
+        }));
+        ACTIVE[priority as usize].fetch_sub(1, Ordering::AcqRel);
+        let mut slot = worker.slot.lock().unwrap();
+        slot.result = Some(result);
+        slot.finished = true;
+        if let Some(waker) = slot.waker.take() {
+            waker.wake();
+        }
+        worker.finished.notify_all();
+    });
+
+    JobHandle { shared }
+}
@@ -1,4 +1,153 @@
+#[cfg(feature = "animation")]
+mod animation;
+mod anisotropic;
+mod annotate;
+mod arena;
+mod batch;
+#[cfg(feature = "bench")]
+pub mod bench;
+#[cfg(feature = "cmyk")]
+mod cmyk;
 mod compress;
+mod contact;
+#[cfg(feature = "container")]
+mod container;
+mod denoise;
+mod diagnostics;
+#[cfg(feature = "dicom")]
+mod dicom;
+mod document;
+mod dyncompress;
+mod dynwrapper;
+mod effects;
+mod encode;
+#[cfg(feature = "fits")]
+mod fits;
+mod fixedpoint;
+#[cfg(feature = "half")]
+mod float16;
+mod font;
+mod geometry;
+mod heatmap;
+#[cfg(feature = "history")]
+mod history;
+#[cfg(feature = "icc")]
+mod icc;
+mod illumination;
 mod imagewrapper;
+mod instrument;
+mod jacobi;
+mod job;
+#[cfg(feature = "matfile")]
+pub mod matfile;
+mod memory;
+mod metrics;
+mod morph;
+#[cfg(feature = "multipage")]
+mod multipage;
+mod notch;
+#[cfg(feature = "npy")]
+pub mod npy;
+mod ops;
+mod overlay;
+#[cfg(feature = "palette")]
+mod palette;
+mod pipeline;
+#[cfg(feature = "plot")]
+mod plot;
+mod preset;
+#[cfg(feature = "preview")]
+mod preview;
+mod saliency;
+mod spectrum;
+mod stats;
+#[cfg(feature = "streaming")]
+mod streaming;
+// Also built for the crate's own tests, which use the fixtures
+#[cfg(any(test, feature = "testutils"))]
+pub mod testutils;
+mod texture;
+mod tiling;
+mod untrusted;
+#[cfg(feature = "video")]
+mod video;
 
-pub use imagewrapper::{GreyImageWrapper, RgbImageWrapper};
+#[cfg(feature = "animation")]
+pub use animation::{AnimationError, AnimationOptions, save_apng, save_webp_animation};
+pub use annotate::{Annotate, Annotation, Corner};
+pub use arena::arena_size;
+pub use batch::{
+    BatchError, BatchJob, BatchOptions, BatchResult, BatchStats, Dedup, DedupAction, FileReport,
+    PERCENTILES, Summary, run_batch,
+};
+#[cfg(feature = "cmyk")]
+pub use cmyk::CmykImageWrapper;
+pub use compress::{
+    AlphaRank, ChannelError, CompressOptions, Compressible, Factorizable, QualityTarget,
+    RankWeighting, Resize, Rung, Salvaged, SvdApproxError, SvdBackend, SvdFactors, SvdTolerance,
+    estimate_peak_memory, factors_to_image,
+};
+pub use contact::{ContactSheetOptions, contact_sheets, save_contact_sheets};
+#[cfg(feature = "container")]
+pub use container::{
+    ColorSpace, Container, ContainerError, ContainerInfo, ContainerMeta, inspect_container,
+    load_container, read_container, write_container, write_lossless,
+};
+pub use denoise::{Denoise, DenoiseOptions, Denoised, Threshold};
+pub use diagnostics::{
+    Diagnostic, DowncastPolicy, clear_diagnostics_handler, downcast_policy,
+    set_diagnostics_handler, set_downcast_policy,
+};
+#[cfg(feature = "dicom")]
+pub use dicom::{DicomImageWrapper, Window};
+pub use document::{Binarize, DocumentOptions, clean_document};
+pub use dyncompress::{DynCompress, WriteSeek};
+pub use dynwrapper::DynWrapper;
+pub use effects::{Effect, Glitch};
+pub use encode::{Encoding, SaveWith};
+#[cfg(feature = "fits")]
+pub use fits::FitsImageWrapper;
+pub use fixedpoint::QuantizedFactors;
+#[cfg(feature = "half")]
+pub use float16::{HalfFactors, HalfMat};
+pub use geometry::{Geometry, Rect};
+pub use heatmap::{Colormap, ErrorMap, ErrorMapOptions};
+#[cfg(feature = "history")]
+pub use history::{History, Operation, Tracked};
+#[cfg(feature = "icc")]
+pub use icc::{ColorManage, IccError, IccProfile, read_icc_profile};
+pub use illumination::{Background, BackgroundOptions, Illumination};
+pub use imagewrapper::{
+    GreyAlphaImageWrapper, GreyImageWrapper, ImageWrapper, LumaWeights, Planes, RgbImageWrapper,
+    RgbaImageWrapper,
+};
+pub use instrument::{Timings, timed};
+pub use job::{JobHandle, Priority};
+pub use memory::{MemoryBudget, OnExhausted, memory_budget, memory_in_use, set_memory_budget};
+pub use metrics::{Comparison, Metrics};
+pub use morph::lerp_factors;
+#[cfg(feature = "multipage")]
+pub use multipage::{PagesError, TiffPages, compress_pages, save_pages};
+pub use notch::{Axis, Notch, NotchOptions, PeriodicNoise};
+pub use ops::{ApproxEq, Blend};
+pub use overlay::{HybridCompress, HybridOptions, Ink, TextLayer};
+#[cfg(feature = "palette")]
+pub use palette::{Palette, Quantize, QuantizeOptions, Quantizer, SaveIndexed, png_palette_size};
+pub use pipeline::{Delinearize, Dither, Linearize, Pipeline, PipelineError, Stage, StageError};
+#[cfg(feature = "plot")]
+pub use plot::{Plot, Series, curve_plot, spectrum_plot};
+pub use preset::Preset;
+#[cfg(feature = "preview")]
+pub use preview::Preview;
+pub use saliency::{GradientSaliency, SaliencyProvider, WeightedCompress, WeightedOptions};
+pub use spectrum::{CurvePoint, Spectral, SpectralComparison, write_curve_csv};
+pub use stats::{ChannelStats, Histogram, Statistics};
+#[cfg(feature = "streaming")]
+pub use streaming::{
+    StreamError, StreamOptions, compress_png_streaming, save_factors_png, stream_factors,
+};
+pub use texture::{Decay, Resynthesize, TextureOptions, synthesize_texture};
+pub use tiling::{TileSplit, Tiling};
+pub use untrusted::{UntrustedError, UntrustedLimits, load_untrusted};
+#[cfg(feature = "video")]
+pub use video::{FrameRate, VideoError, VideoReader, VideoWriter, compress_video};
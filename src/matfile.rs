@@ -0,0 +1,104 @@
+use crate::compress::SvdFactors;
+use faer_core::{Mat, MatRef};
+use std::io::{self, Write};
+
+const MI_INT8: u32 = 1;
+const MI_INT32: u32 = 5;
+const MI_UINT32: u32 = 6;
+const MI_SINGLE: u32 = 7;
+const MI_MATRIX: u32 = 14;
+const MX_SINGLE_CLASS: u32 = 7;
+
+// Appends a tagged data element, padding its data to the 8-byte boundary MAT-files require
+fn push_element(buf: &mut Vec<u8>, data_type: u32, data: &[u8]) {
+    buf.extend_from_slice(&data_type.to_le_bytes());
+    buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    buf.extend_from_slice(data);
+    buf.resize(buf.len().next_multiple_of(8), 0);
+}
+
+fn words_to_bytes(words: &[u32]) -> Vec<u8> {
+    words.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+fn matrix_element(name: &str, mat: MatRef<f32>) -> Vec<u8> {
+    let mut body = Vec::new();
+
+    let flags = [MX_SINGLE_CLASS, 0];
+    push_element(&mut body, MI_UINT32, &words_to_bytes(&flags));
+
+    let dims = [mat.nrows() as u32, mat.ncols() as u32];
+    push_element(&mut body, MI_INT32, &words_to_bytes(&dims));
+
+    push_element(&mut body, MI_INT8, name.as_bytes());
+
+    // MATLAB arrays are column-major, like faer's
+    let mut real = Vec::with_capacity(4 * mat.nrows() * mat.ncols());
+    for j in 0..mat.ncols() {
+        for i in 0..mat.nrows() {
+            real.extend_from_slice(&mat.read(i, j).to_le_bytes());
+        }
+    }
+    push_element(&mut body, MI_SINGLE, &real);
+
+    let mut element = Vec::with_capacity(8 + body.len());
+    push_element(&mut element, MI_MATRIX, &body);
+    element
+}
+
+// Writes a Level 5 MAT-file with one single-precision variable per matrix
+pub fn write_mat<W: Write>(mut writer: W, arrays: &[(&str, MatRef<f32>)]) -> io::Result<()> {
+    let mut header = format!(
+        "MATLAB 5.0 MAT-file, Platform: {}, Created by: svdimagecompress {}",
+        std::env::consts::OS,
+        env!("CARGO_PKG_VERSION")
+    )
+    .into_bytes();
+    header.resize(116, b' ');
+    header.extend_from_slice(&[0; 8]); // subsystem data offset
+    header.extend_from_slice(&0x0100u16.to_le_bytes());
+    header.extend_from_slice(b"IM");
+    writer.write_all(&header)?;
+
+    for (name, mat) in arrays {
+        writer.write_all(&matrix_element(name, *mat))?;
+    }
+
+    Ok(())
+}
+
+// Saves per-channel matrices as `channel_0`, `channel_1`, ...
+pub fn write_mats_mat<W: Write>(writer: W, mats: &[Mat<f32>]) -> io::Result<()> {
+    let names: Vec<String> = (0..mats.len()).map(|k| format!("channel_{}", k)).collect();
+    let arrays: Vec<_> = names
+        .iter()
+        .zip(mats)
+        .map(|(name, mat)| (name.as_str(), mat.as_ref()))
+        .collect();
+
+    write_mat(writer, &arrays)
+}
+
+// Saves per-channel factors as `u_0`, `s_0`, `v_0`, `u_1`, ..., with each `s` a column vector
+pub fn write_factors_mat<W: Write>(writer: W, factors: &[SvdFactors]) -> io::Result<()> {
+    let s_cols: Vec<Mat<f32>> = factors
+        .iter()
+        .map(|f| Mat::from_fn(f.rank(), 1, |i, _| f.s[i]))
+        .collect();
+    let names: Vec<[String; 3]> = (0..factors.len())
+        .map(|k| [format!("u_{}", k), format!("s_{}", k), format!("v_{}", k)])
+        .collect();
+    let arrays: Vec<_> = names
+        .iter()
+        .zip(factors.iter().zip(&s_cols))
+        .flat_map(|([u, s, v], (f, s_col))| {
+            [
+                (u.as_str(), f.u.as_ref()),
+                (s.as_str(), s_col.as_ref()),
+                (v.as_str(), f.v.as_ref()),
+            ]
+        })
+        .collect();
+
+    write_mat(writer, &arrays)
+}
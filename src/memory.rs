@@ -0,0 +1,102 @@
+use crate::compress::SvdApproxError;
+use std::cell::Cell;
+use std::sync::{Condvar, Mutex};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OnExhausted {
+    // Wait for other compressions to release enough
+    #[default]
+    Block,
+    // Fail with `SvdApproxError::MemoryPoolExhausted`
+    Fail,
+}
+
+// A cap in bytes on the scratch memory of all compressions in the process together. Each
+// channel reserves its SVD workspace, factors and reconstruction (see `estimate_peak_memory`)
+// before starting and releases them when done, so concurrent jobs queue for memory instead of
+// exhausting it. A channel needing more than the whole cap fails either way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MemoryBudget {
+    pub limit: usize,
+    pub on_exhausted: OnExhausted,
+}
+
+impl MemoryBudget {
+    pub fn new(limit: usize) -> Self {
+        MemoryBudget {
+            limit,
+            on_exhausted: OnExhausted::default(),
+        }
+    }
+}
+
+struct Pool {
+    budget: Option<MemoryBudget>,
+    used: usize,
+}
+
+static POOL: Mutex<Pool> = Mutex::new(Pool {
+    budget: None,
+    used: 0,
+});
+static RELEASED: Condvar = Condvar::new();
+
+thread_local! {
+    // Reservations held by this thread
+    static HELD: Cell<usize> = const { Cell::new(0) };
+}
+
+// `None` lifts the cap; reservations already granted are kept
+pub fn set_memory_budget(budget: Option<MemoryBudget>) {
+    POOL.lock().unwrap().budget = budget;
+    RELEASED.notify_all();
+}
+
+pub fn memory_budget() -> Option<MemoryBudget> {
+    POOL.lock().unwrap().budget
+}
+
+// Bytes currently reserved by running compressions
+pub fn memory_in_use() -> usize {
+    POOL.lock().unwrap().used
+}
+
+pub(crate) struct Reservation(usize);
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        POOL.lock().unwrap().used -= self.0;
+        HELD.with(|held| held.set(held.get() - 1));
+        RELEASED.notify_all();
+    }
+}
+
+// Reserves `bytes` from the pool, waiting or failing as the budget says when they don't fit.
+// A thread that already holds a reservation is never made to wait: a pool worker can pick up
+// another channel while inside one, and the outer channel can't finish until the inner does.
+pub(crate) fn reserve(bytes: usize) -> Result<Reservation, SvdApproxError> {
+    let nested = HELD.with(|held| held.get() > 0);
+    let mut pool = POOL.lock().unwrap();
+
+    while let Some(budget) = pool.budget {
+        if bytes > budget.limit {
+            return Err(SvdApproxError::MemoryBudgetExceeded(bytes, budget.limit));
+        }
+        let available = budget.limit.saturating_sub(pool.used);
+        if nested || bytes <= available {
+            break;
+        }
+        match budget.on_exhausted {
+            OnExhausted::Block => pool = RELEASED.wait(pool).unwrap(),
+            OnExhausted::Fail => {
+                return Err(SvdApproxError::MemoryPoolExhausted(bytes, available));
+            }
+        }
+    }
+
+    pool.used += bytes;
+    HELD.with(|held| held.set(held.get() + 1));
+    Ok(Reservation(bytes))
+}
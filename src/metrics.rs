@@ -0,0 +1,221 @@
+use crate::imagewrapper::Planes;
+use faer_core::{Mat, MatRef};
+
+const PEAK: f64 = 255.0;
+// Gaussian window of Wang et al. (2004): 11 taps, sigma = 1.5
+const WINDOW_RADIUS: usize = 5;
+const WINDOW_SIGMA: f32 = 1.5;
+// Stabilizer of the gradient similarity, the constant of GMSD (Xue et al., 2014) for 0-255 images
+const EDGE_C: f64 = 170.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Comparison {
+    pub mse: f32,
+    // In dB against a peak of 255; infinite for identical images
+    pub psnr: f32,
+    pub ssim: f32,
+    // `Metrics::edge_similarity`
+    pub edges: f32,
+    pub max_error: f32,
+}
+
+fn check_shapes(a: &[Mat<f32>], b: &[Mat<f32>]) {
+    assert_eq!(
+        a.len(),
+        b.len(),
+        "wrappers must have the same number of channels"
+    );
+    assert!(
+        a[0].nrows() == b[0].nrows() && a[0].ncols() == b[0].ncols(),
+        "wrappers must have the same dimensions, got {}x{} and {}x{}",
+        a[0].ncols(),
+        a[0].nrows(),
+        b[0].ncols(),
+        b[0].nrows(),
+    );
+}
+
+fn gaussian_window() -> [f32; 2 * WINDOW_RADIUS + 1] {
+    let mut window = std::array::from_fn(|k| {
+        let x = k as f32 - WINDOW_RADIUS as f32;
+        (-x * x / (2.0 * WINDOW_SIGMA * WINDOW_SIGMA)).exp()
+    });
+    let sum: f32 = window.iter().sum();
+    window.iter_mut().for_each(|w| *w /= sum);
+    window
+}
+
+// Separable Gaussian blur, clamping at the borders so small images still get a full window
+fn blur(mat: MatRef<f32>, window: &[f32]) -> Mat<f32> {
+    let (m, n) = (mat.nrows(), mat.ncols());
+    let tap = |x: usize, len: usize, k: usize| (x + k).saturating_sub(WINDOW_RADIUS).min(len - 1);
+
+    let rows = Mat::from_fn(m, n, |i, j| {
+        window
+            .iter()
+            .enumerate()
+            .map(|(k, w)| w * mat.read(i, tap(j, n, k)))
+            .sum::<f32>()
+    });
+    Mat::from_fn(m, n, |i, j| {
+        window
+            .iter()
+            .enumerate()
+            .map(|(k, w)| w * rows.read(tap(i, m, k), j))
+            .sum::<f32>()
+    })
+}
+
+fn channel_ssim(a: MatRef<f32>, b: MatRef<f32>, window: &[f32]) -> f64 {
+    let c1 = (0.01 * PEAK) * (0.01 * PEAK);
+    let c2 = (0.03 * PEAK) * (0.03 * PEAK);
+    let product = |x: MatRef<f32>, y: MatRef<f32>| {
+        Mat::from_fn(x.nrows(), x.ncols(), |i, j| x.read(i, j) * y.read(i, j))
+    };
+
+    let (mu_a, mu_b) = (blur(a, window), blur(b, window));
+    let aa = blur(product(a, a).as_ref(), window);
+    let bb = blur(product(b, b).as_ref(), window);
+    let ab = blur(product(a, b).as_ref(), window);
+
+    let mut sum = 0.0f64;
+    for j in 0..a.ncols() {
+        for i in 0..a.nrows() {
+            let (mu_a, mu_b) = (mu_a.read(i, j) as f64, mu_b.read(i, j) as f64);
+            let var_a = aa.read(i, j) as f64 - mu_a * mu_a;
+            let var_b = bb.read(i, j) as f64 - mu_b * mu_b;
+            let cov = ab.read(i, j) as f64 - mu_a * mu_b;
+
+            sum += ((2.0 * mu_a * mu_b + c1) * (2.0 * cov + c2))
+                / ((mu_a * mu_a + mu_b * mu_b + c1) * (var_a + var_b + c2));
+        }
+    }
+
+    sum / (a.nrows() * a.ncols()) as f64
+}
+
+// Sobel gradient magnitude, scaled to the change per pixel and clamping at the borders
+fn gradient_magnitude(mat: MatRef<f32>) -> Mat<f32> {
+    let (m, n) = (mat.nrows(), mat.ncols());
+    let at = |i: isize, j: isize| {
+        mat.read(
+            i.clamp(0, m as isize - 1) as usize,
+            j.clamp(0, n as isize - 1) as usize,
+        )
+    };
+    Mat::from_fn(m, n, |i, j| {
+        let (i, j) = (i as isize, j as isize);
+        let gx = (at(i - 1, j + 1) + 2.0 * at(i, j + 1) + at(i + 1, j + 1))
+            - (at(i - 1, j - 1) + 2.0 * at(i, j - 1) + at(i + 1, j - 1));
+        let gy = (at(i + 1, j - 1) + 2.0 * at(i + 1, j) + at(i + 1, j + 1))
+            - (at(i - 1, j - 1) + 2.0 * at(i - 1, j) + at(i - 1, j + 1));
+        (gx * gx + gy * gy).sqrt() / 8.0
+    })
+}
+
+// Sum of the gradient similarities of a channel weighted by edge strength, and the weights
+fn channel_edges(a: MatRef<f32>, b: MatRef<f32>) -> (f64, f64) {
+    let (ga, gb) = (gradient_magnitude(a), gradient_magnitude(b));
+    let (mut sum, mut weight) = (0.0f64, 0.0f64);
+    for j in 0..a.ncols() {
+        for i in 0..a.nrows() {
+            let (x, y) = (ga.read(i, j) as f64, gb.read(i, j) as f64);
+            let w = x.max(y);
+            sum += w * (2.0 * x * y + EDGE_C) / (x * x + y * y + EDGE_C);
+            weight += w;
+        }
+    }
+    (sum, weight)
+}
+
+// Full-reference quality metrics on the 0-255 scale of the planes, averaged over channels; all
+// methods panic if the channel counts or dimensions differ
+pub trait Metrics: Planes {
+    fn mse(&self, other: &Self) -> f32 {
+        let (a, b) = (self.planes(), other.planes());
+        check_shapes(a, b);
+
+        let mut sum = 0.0f64;
+        for (a, b) in a.iter().zip(b) {
+            for j in 0..a.ncols() {
+                for i in 0..a.nrows() {
+                    let diff = (a.read(i, j) - b.read(i, j)) as f64;
+                    sum += diff * diff;
+                }
+            }
+        }
+
+        (sum / (a.len() * a[0].nrows() * a[0].ncols()) as f64) as f32
+    }
+
+    fn psnr(&self, other: &Self) -> f32 {
+        let mse = self.mse(other) as f64;
+        if mse == 0.0 {
+            f32::INFINITY
+        } else {
+            (10.0 * (PEAK * PEAK / mse).log10()) as f32
+        }
+    }
+
+    fn ssim(&self, other: &Self) -> f32 {
+        let (a, b) = (self.planes(), other.planes());
+        check_shapes(a, b);
+
+        let window = gaussian_window();
+        let sum: f64 = a
+            .iter()
+            .zip(b)
+            .map(|(a, b)| channel_ssim(a.as_ref(), b.as_ref(), &window))
+            .sum();
+
+        (sum / a.len() as f64) as f32
+    }
+
+    // How well edges survive, from 0 to 1 for identical gradients: the similarity of the
+    // gradient magnitudes at each pixel, as in GMSD, averaged with weights given by the stronger
+    // of the two so that smeared edges and new ones such as ringing dominate, not flat areas.
+    // Channels are pooled by their edge weight, so flat ones like an opaque alpha don't count.
+    fn edge_similarity(&self, other: &Self) -> f32 {
+        let (a, b) = (self.planes(), other.planes());
+        check_shapes(a, b);
+
+        let (sum, weight) = a
+            .iter()
+            .zip(b)
+            .map(|(a, b)| channel_edges(a.as_ref(), b.as_ref()))
+            .fold((0.0, 0.0), |(s, w), (ds, dw)| (s + ds, w + dw));
+
+        if weight > 0.0 {
+            (sum / weight) as f32
+        } else {
+            1.0
+        }
+    }
+
+    fn max_error(&self, other: &Self) -> f32 {
+        let (a, b) = (self.planes(), other.planes());
+        check_shapes(a, b);
+
+        a.iter()
+            .zip(b)
+            .flat_map(|(a, b)| {
+                (0..a.ncols()).flat_map(move |j| {
+                    (0..a.nrows()).map(move |i| (a.read(i, j) - b.read(i, j)).abs())
+                })
+            })
+            .fold(0.0, f32::max)
+    }
+
+    fn compare(&self, other: &Self) -> Comparison {
+        Comparison {
+            mse: self.mse(other),
+            psnr: self.psnr(other),
+            ssim: self.ssim(other),
+            edges: self.edge_similarity(other),
+            max_error: self.max_error(other),
+        }
+    }
+}
+
+impl<W: Planes> Metrics for W {}
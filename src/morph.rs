@@ -0,0 +1,60 @@
+use crate::compress::{SvdApproxError, SvdFactors};
+use faer_core::{Mat, MatRef};
+
+fn dot(a: MatRef<f32>, b: MatRef<f32>, j: usize) -> f32 {
+    (0..a.nrows()).map(|i| a.read(i, j) * b.read(i, j)).sum()
+}
+
+fn lerp_vectors(a: MatRef<f32>, b: MatRef<f32>, signs: &[f32], t: f32) -> Mat<f32> {
+    let mut mat = Mat::from_fn(a.nrows(), signs.len(), |i, j| {
+        (1.0 - t) * a.read(i, j) + t * signs[j] * b.read(i, j)
+    });
+
+    // Renormalize so the interpolated vectors stay unit-length (they are only nearly orthogonal)
+    for j in 0..mat.ncols() {
+        let norm = dot(mat.as_ref(), mat.as_ref(), j).sqrt();
+        if norm > 0.0 {
+            for i in 0..mat.nrows() {
+                mat.write(i, j, mat.read(i, j) / norm);
+            }
+        }
+    }
+
+    mat
+}
+
+// Interpolates the matched singular triplets of two same-sized decompositions, with `t = 0`
+// giving `a` and `t = 1` giving `b`. Only the leading `min(a.rank(), b.rank())` triplets are used.
+pub fn lerp_factors(a: &SvdFactors, b: &SvdFactors, t: f32) -> Result<SvdFactors, SvdApproxError> {
+    let (m, n) = (a.u.nrows(), a.v.nrows());
+
+    if (m, n) != (b.u.nrows(), b.v.nrows()) {
+        return Err(SvdApproxError::ShapeMismatch(
+            (m, n),
+            (b.u.nrows(), b.v.nrows()),
+        ));
+    }
+
+    let rank = a.rank().min(b.rank());
+    let (a_u, a_v) = (a.u.as_ref().subcols(0, rank), a.v.as_ref().subcols(0, rank));
+    let (b_u, b_v) = (b.u.as_ref().subcols(0, rank), b.v.as_ref().subcols(0, rank));
+
+    // Singular vectors are only defined up to a joint sign flip of `u_i` and `v_i`, so align `b`'s
+    // triplets with `a`'s before interpolating to avoid collapsing through zero
+    let signs: Vec<f32> = (0..rank)
+        .map(|j| {
+            if dot(a_u, b_u, j) + dot(a_v, b_v, j) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            }
+        })
+        .collect();
+
+    Ok(SvdFactors {
+        u: lerp_vectors(a_u, b_u, &signs, t),
+        s: (0..rank).map(|i| (1.0 - t) * a.s[i] + t * b.s[i]).collect(),
+        v: lerp_vectors(a_v, b_v, &signs, t),
+        energy: (1.0 - t) * a.energy + t * b.energy,
+    })
+}
@@ -0,0 +1,169 @@
+use crate::compress::{CompressOptions, Compressible, SvdApproxError};
+use crate::dynwrapper::DynWrapper;
+use image::error::{DecodingError, ImageFormatHint, UnsupportedError, UnsupportedErrorKind};
+use image::*;
+use std::io::{Read, Seek, Write};
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::encoder::TiffEncoder;
+use tiff::encoder::colortype::{Gray8, RGB8, RGBA8};
+
+#[derive(Debug)]
+pub enum PagesError {
+    Image(ImageError),
+    Svd(SvdApproxError),
+}
+
+impl std::fmt::Display for PagesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PagesError::Image(err) => write!(f, "Image error: {}", err),
+            PagesError::Svd(err) => write!(f, "SVD error: {}", err),
+        }
+    }
+}
+
+impl From<ImageError> for PagesError {
+    fn from(err: ImageError) -> Self {
+        PagesError::Image(err)
+    }
+}
+
+impl From<SvdApproxError> for PagesError {
+    fn from(err: SvdApproxError) -> Self {
+        PagesError::Svd(err)
+    }
+}
+
+fn tiff_error(err: tiff::TiffError) -> ImageError {
+    ImageError::Decoding(DecodingError::new(
+        ImageFormatHint::Exact(ImageFormat::Tiff),
+        err,
+    ))
+}
+
+fn encoding_error(err: tiff::TiffError) -> ImageError {
+    ImageError::IoError(std::io::Error::other(err))
+}
+
+fn unsupported(color: tiff::ColorType) -> ImageError {
+    ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+        ImageFormatHint::Exact(ImageFormat::Tiff),
+        UnsupportedErrorKind::GenericFeature(format!("{:?} pages", color)),
+    ))
+}
+
+// Iterates the pages (IFDs) of a multi-page TIFF, each loaded into the matching wrapper. The
+// `image` decoder only ever sees the first page, hence going through `tiff` directly.
+pub struct TiffPages<R: Read + Seek> {
+    decoder: Decoder<R>,
+    done: bool,
+}
+
+impl<R: Read + Seek> TiffPages<R> {
+    pub fn new(reader: R) -> ImageResult<Self> {
+        Ok(TiffPages {
+            decoder: Decoder::new(reader).map_err(tiff_error)?,
+            done: false,
+        })
+    }
+
+    fn read_page(&mut self) -> ImageResult<DynWrapper> {
+        let (width, height) = self.decoder.dimensions().map_err(tiff_error)?;
+        let color = self.decoder.colortype().map_err(tiff_error)?;
+        let data = self.decoder.read_image().map_err(tiff_error)?;
+
+        let img = match (color, data) {
+            (tiff::ColorType::Gray(8), DecodingResult::U8(data)) => {
+                ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLuma8)
+            }
+            (tiff::ColorType::GrayA(8), DecodingResult::U8(data)) => {
+                ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLumaA8)
+            }
+            (tiff::ColorType::RGB(8), DecodingResult::U8(data)) => {
+                ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb8)
+            }
+            (tiff::ColorType::RGBA(8), DecodingResult::U8(data)) => {
+                ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgba8)
+            }
+            (tiff::ColorType::Gray(16), DecodingResult::U16(data)) => {
+                ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLuma16)
+            }
+            (tiff::ColorType::RGB(16), DecodingResult::U16(data)) => {
+                ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb16)
+            }
+            (tiff::ColorType::RGBA(16), DecodingResult::U16(data)) => {
+                ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgba16)
+            }
+            _ => return Err(unsupported(color)),
+        };
+
+        // `from_raw` only fails if the decoder returned fewer samples than the page claims
+        img.map(DynWrapper::from_dynamic)
+            .ok_or_else(|| tiff_error(tiff::TiffError::LimitsExceeded))
+    }
+}
+
+impl<R: Read + Seek> Iterator for TiffPages<R> {
+    type Item = ImageResult<DynWrapper>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let page = self.read_page();
+
+        if page.is_err() || !self.decoder.more_images() {
+            self.done = true;
+        } else if let Err(err) = self.decoder.next_image() {
+            self.done = true;
+            return Some(Err(tiff_error(err)));
+        }
+
+        Some(page)
+    }
+}
+
+// Writes one TIFF page per wrapper; grey + alpha pages are widened to RGBA, which (unlike
+// grey + alpha) every TIFF reader handles
+pub fn save_pages<W: Write + Seek>(writer: W, pages: &[DynWrapper]) -> ImageResult<()> {
+    let mut encoder = TiffEncoder::new(writer).map_err(encoding_error)?;
+
+    for page in pages {
+        let (width, height) = (page.width() as u32, page.height() as u32);
+
+        match page {
+            DynWrapper::Grey(wrapper) => {
+                encoder.write_image::<Gray8>(width, height, wrapper.to_image().as_raw())
+            }
+            DynWrapper::Rgb(wrapper) => {
+                encoder.write_image::<RGB8>(width, height, wrapper.to_image().as_raw())
+            }
+            DynWrapper::GreyAlpha(_) | DynWrapper::Rgba(_) => {
+                encoder.write_image::<RGBA8>(width, height, page.to_dynamic().to_rgba8().as_raw())
+            }
+        }
+        .map_err(encoding_error)?;
+    }
+
+    Ok(())
+}
+
+// Compresses every page of a multi-page TIFF with the same options and writes the result as a
+// multi-page TIFF, returning the number of pages
+pub fn compress_pages<R, W>(
+    reader: R,
+    writer: W,
+    options: &CompressOptions,
+) -> Result<usize, PagesError>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+{
+    let pages = TiffPages::new(reader)?
+        .map(|page| Ok(page?.compress_with(options)?))
+        .collect::<Result<Vec<_>, PagesError>>()?;
+
+    save_pages(writer, &pages)?;
+    Ok(pages.len())
+}
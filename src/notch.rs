@@ -0,0 +1,230 @@
+use crate::compress::{SvdApproxError, SvdBackend, svd};
+use crate::imagewrapper::Planes;
+use faer_core::Mat;
+use rayon::prelude::*;
+
+// Share of a singular vector's power a tone needs to be worth removing; a faint pattern can be
+// spread thinly over the leading vectors, so this only screens out round-off
+const MIN_TONE_POWER: f32 = 1e-4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NotchOptions {
+    // Leading singular pairs inspected per channel
+    pub components: usize,
+    // Fraction of a singular vector's power at periods up to `max_period` that must sit at a
+    // single frequency (and its immediate neighbors) for it to count as a repeating pattern
+    pub peak: f32,
+    // Periods longer than this many pixels are taken to be image content rather than noise
+    pub max_period: f32,
+}
+
+impl Default for NotchOptions {
+    fn default() -> Self {
+        NotchOptions {
+            components: 32,
+            peak: 0.5,
+            max_period: 32.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Axis {
+    // The pattern repeats down the image, like horizontal banding
+    Vertical,
+    // The pattern repeats across the image, like vertical stripes
+    Horizontal,
+}
+
+// A tone removed from one singular vector by `PeriodicNoise::remove_periodic_noise`
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Notch {
+    pub channel: usize,
+    // Position in the channel's spectrum, and the singular value there
+    pub index: usize,
+    pub singular_value: f32,
+    // Share of the channel's energy removed with the tone
+    pub energy: f32,
+    pub axis: Axis,
+    // In pixels
+    pub period: f32,
+}
+
+// In-place iterative radix-2 FFT; `re.len()` must be a power of two
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * std::f32::consts::PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+// The strongest tone of `x` with a period of at most `max_period`, as `(period, fitted tone)`,
+// if it holds `peak` of the power at such periods and `MIN_TONE_POWER` of the power overall
+fn dominant_tone(x: &[f32], peak: f32, max_period: f32) -> Option<(f32, Vec<f32>)> {
+    // A Hann window keeps the edges of the smooth content from leaking into high frequencies
+    let len = x.len();
+    let n = len.next_power_of_two();
+    let mut re: Vec<f32> = x
+        .iter()
+        .enumerate()
+        .map(|(i, &v)| {
+            let w = (std::f32::consts::PI * (i as f32 + 0.5) / len as f32).sin();
+            v * w * w
+        })
+        .collect();
+    re.resize(n, 0.0);
+    let mut im = vec![0.0; n];
+    fft(&mut re, &mut im);
+
+    let power: Vec<f32> = (0..=n / 2).map(|k| re[k] * re[k] + im[k] * im[k]).collect();
+    let lowest = ((n as f32 / max_period).ceil() as usize).max(1);
+    let high: f32 = power.get(lowest..)?.iter().sum();
+    if high <= 0.0 {
+        return None;
+    }
+
+    // Windowing and zero padding spread a tone over neighboring bins, so they count towards
+    // the peak
+    let band = |k: usize| power[k - 1..(k + 2).min(power.len())].iter().sum::<f32>();
+    let k = (lowest.max(2)..power.len()).max_by(|&a, &b| band(a).total_cmp(&band(b)))?;
+    if band(k) < peak * high {
+        return None;
+    }
+
+    // Least-squares fit of a sinusoid at the best frequency near bin `k`
+    let fit = |freq: f32| {
+        let omega = 2.0 * std::f32::consts::PI * freq;
+        let (mut cc, mut ss, mut cs, mut xc, mut xs) = (0.0, 0.0, 0.0, 0.0, 0.0);
+        for (i, &v) in x.iter().enumerate() {
+            let (sin, cos) = (omega * i as f32).sin_cos();
+            cc += cos * cos;
+            ss += sin * sin;
+            cs += cos * sin;
+            xc += v * cos;
+            xs += v * sin;
+        }
+        let det = cc * ss - cs * cs;
+        let (a, b) = ((xc * ss - xs * cs) / det, (xs * cc - xc * cs) / det);
+        let tone: Vec<f32> = (0..x.len())
+            .map(|i| {
+                let (sin, cos) = (omega * i as f32).sin_cos();
+                a * cos + b * sin
+            })
+            .collect();
+        (freq, tone)
+    };
+    let norm = |tone: &[f32]| tone.iter().map(|t| t * t).sum::<f32>();
+    let (freq, tone) = [-0.5, -0.25, 0.0, 0.25, 0.5]
+        .into_iter()
+        .map(|offset| (k as f32 + offset) / n as f32)
+        .filter(|&freq| freq * max_period >= 1.0)
+        .map(fit)
+        .max_by(|a, b| norm(&a.1).total_cmp(&norm(&b.1)))?;
+
+    let total: f32 = x.iter().map(|v| v * v).sum();
+    (norm(&tone) >= MIN_TONE_POWER * total).then_some((1.0 / freq, tone))
+}
+
+// Scanner banding, halftone screens and moire put sharp lines into the spectra of the singular
+// vectors: a stripe pattern is a sinusoid in U or V, a 2-D ripple one in both. Fitting and
+// subtracting just those tones from the leading vectors notches the pattern out while leaving
+// everything else in them, which matters because the SVD mixes a weak pattern into whichever
+// components of the image share its other vector.
+pub trait PeriodicNoise: Planes {
+    fn remove_periodic_noise(
+        &self,
+        options: &NotchOptions,
+    ) -> Result<(Self, Vec<Notch>), SvdApproxError>
+    where
+        Self: Sized,
+    {
+        let results = self
+            .planes()
+            .par_iter()
+            .enumerate()
+            .map(|(channel, mat)| {
+                let factors = svd(mat.as_ref(), SvdBackend::default())?;
+                let mut out = mat.clone();
+                let mut notches = Vec::new();
+
+                for index in 0..options.components.min(factors.rank()) {
+                    let s = factors.s[index];
+                    // The rest of the spectrum is round-off
+                    if s <= f32::EPSILON * factors.s[0] {
+                        break;
+                    }
+                    let mut u = factors.u.col_as_slice(index).to_vec();
+                    let mut v = factors.v.col_as_slice(index).to_vec();
+
+                    for (axis, x) in [(Axis::Vertical, &mut u), (Axis::Horizontal, &mut v)] {
+                        let Some((period, tone)) =
+                            dominant_tone(x, options.peak, options.max_period)
+                        else {
+                            continue;
+                        };
+                        let power: f32 = tone.iter().map(|t| t * t).sum();
+                        x.iter_mut().zip(&tone).for_each(|(x, t)| *x -= t);
+                        notches.push(Notch {
+                            channel,
+                            index,
+                            singular_value: s,
+                            energy: if factors.energy > 0.0 {
+                                s * s * power / factors.energy
+                            } else {
+                                0.0
+                            },
+                            axis,
+                            period,
+                        });
+                    }
+
+                    // Swap the component for its notched version
+                    let (u0, v0) = (factors.u.col_as_slice(index), factors.v.col_as_slice(index));
+                    if u.as_slice() != u0 || v.as_slice() != v0 {
+                        out = Mat::from_fn(out.nrows(), out.ncols(), |i, j| {
+                            out.read(i, j) - s * (u0[i] * v0[j] - u[i] * v[j])
+                        });
+                    }
+                }
+
+                Ok((out, notches))
+            })
+            .collect::<Result<Vec<_>, SvdApproxError>>()?;
+
+        let (mats, notches): (Vec<_>, Vec<_>) = results.into_iter().unzip();
+        Ok((self.rebuild(mats), notches.into_iter().flatten().collect()))
+    }
+}
+
+impl<W: Planes> PeriodicNoise for W {}
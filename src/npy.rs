@@ -0,0 +1,141 @@
+use crate::compress::SvdFactors;
+use faer_core::{Mat, MatRef};
+use std::io::{self, Write};
+
+pub enum NpyArray<'a> {
+    Matrix(MatRef<'a, f32>),
+    Vector(&'a [f32]),
+}
+
+impl NpyArray<'_> {
+    fn shape(&self) -> String {
+        match self {
+            NpyArray::Matrix(mat) => format!("({}, {})", mat.nrows(), mat.ncols()),
+            NpyArray::Vector(vec) => format!("({},)", vec.len()),
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        // Header dict padded so that the data starts on a 64-byte boundary
+        let mut header = format!(
+            "{{'descr': '<f4', 'fortran_order': True, 'shape': {}, }}",
+            self.shape()
+        );
+        let padding = 63 - (10 + header.len()) % 64;
+        header.extend(std::iter::repeat_n(' ', padding));
+        header.push('\n');
+
+        let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+        bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+
+        // faer matrices are column-major, which is exactly `fortran_order: True`
+        match self {
+            NpyArray::Matrix(mat) => {
+                for j in 0..mat.ncols() {
+                    for i in 0..mat.nrows() {
+                        bytes.extend_from_slice(&mat.read(i, j).to_le_bytes());
+                    }
+                }
+            }
+            NpyArray::Vector(vec) => {
+                for x in vec.iter() {
+                    bytes.extend_from_slice(&x.to_le_bytes());
+                }
+            }
+        }
+
+        bytes
+    }
+}
+
+pub fn write_npy<W: Write>(mut writer: W, array: NpyArray) -> io::Result<()> {
+    writer.write_all(&array.to_bytes())
+}
+
+// Writes an uncompressed (stored) zip archive with one `<name>.npy` member per array, which is
+// what `numpy.savez` produces and `numpy.load` expects
+pub fn write_npz<W: Write>(mut writer: W, arrays: &[(&str, NpyArray)]) -> io::Result<()> {
+    let mut central_dir = Vec::new();
+    let mut offset = 0u32;
+
+    for (name, array) in arrays {
+        let name = format!("{}.npy", name);
+        let data = array.to_bytes();
+        let crc = crc32fast::hash(&data);
+        let size = data.len() as u32;
+
+        let mut local = Vec::with_capacity(30 + name.len());
+        local.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        local.extend_from_slice(&20u16.to_le_bytes()); // version needed to extract
+        local.extend_from_slice(&0u16.to_le_bytes()); // flags
+        local.extend_from_slice(&0u16.to_le_bytes()); // method: stored
+        local.extend_from_slice(&0u16.to_le_bytes()); // mod time
+        local.extend_from_slice(&0x21u16.to_le_bytes()); // mod date: 1980-01-01
+        local.extend_from_slice(&crc.to_le_bytes());
+        local.extend_from_slice(&size.to_le_bytes());
+        local.extend_from_slice(&size.to_le_bytes());
+        local.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        local.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+        local.extend_from_slice(name.as_bytes());
+
+        central_dir.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        central_dir.extend_from_slice(&20u16.to_le_bytes()); // version made by
+        central_dir.extend_from_slice(&local[4..30]);
+        central_dir.extend_from_slice(&0u16.to_le_bytes()); // comment length
+        central_dir.extend_from_slice(&0u16.to_le_bytes()); // disk number
+        central_dir.extend_from_slice(&0u16.to_le_bytes()); // internal attributes
+        central_dir.extend_from_slice(&0u32.to_le_bytes()); // external attributes
+        central_dir.extend_from_slice(&offset.to_le_bytes());
+        central_dir.extend_from_slice(name.as_bytes());
+
+        writer.write_all(&local)?;
+        writer.write_all(&data)?;
+        offset += local.len() as u32 + size;
+    }
+
+    let mut end = Vec::with_capacity(22);
+    end.extend_from_slice(&0x06054b50u32.to_le_bytes());
+    end.extend_from_slice(&0u16.to_le_bytes()); // this disk
+    end.extend_from_slice(&0u16.to_le_bytes()); // disk with central directory
+    end.extend_from_slice(&(arrays.len() as u16).to_le_bytes());
+    end.extend_from_slice(&(arrays.len() as u16).to_le_bytes());
+    end.extend_from_slice(&(central_dir.len() as u32).to_le_bytes());
+    end.extend_from_slice(&offset.to_le_bytes());
+    end.extend_from_slice(&0u16.to_le_bytes()); // comment length
+
+    writer.write_all(&central_dir)?;
+    writer.write_all(&end)
+}
+
+// Saves per-channel matrices as `channel_0`, `channel_1`, ...
+pub fn write_mats_npz<W: Write>(writer: W, mats: &[Mat<f32>]) -> io::Result<()> {
+    let names: Vec<String> = (0..mats.len()).map(|k| format!("channel_{}", k)).collect();
+    let arrays: Vec<_> = names
+        .iter()
+        .zip(mats)
+        .map(|(name, mat)| (name.as_str(), NpyArray::Matrix(mat.as_ref())))
+        .collect();
+
+    write_npz(writer, &arrays)
+}
+
+// Saves per-channel factors as `u_0`, `s_0`, `v_0`, `u_1`, ...
+pub fn write_factors_npz<W: Write>(writer: W, factors: &[SvdFactors]) -> io::Result<()> {
+    let names: Vec<[String; 3]> = (0..factors.len())
+        .map(|k| [format!("u_{}", k), format!("s_{}", k), format!("v_{}", k)])
+        .collect();
+    let arrays: Vec<_> = names
+        .iter()
+        .zip(factors)
+        .flat_map(|([u, s, v], f)| {
+            [
+                (u.as_str(), NpyArray::Matrix(f.u.as_ref())),
+                (s.as_str(), NpyArray::Vector(&f.s)),
+                (v.as_str(), NpyArray::Matrix(f.v.as_ref())),
+            ]
+        })
+        .collect();
+
+    write_npz(writer, &arrays)
+}
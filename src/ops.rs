@@ -0,0 +1,133 @@
+#[cfg(feature = "cmyk")]
+use crate::cmyk::CmykImageWrapper;
+use crate::dynwrapper::DynWrapper;
+use crate::imagewrapper::{
+    GreyAlphaImageWrapper, GreyImageWrapper, Planes, RgbImageWrapper, RgbaImageWrapper,
+};
+use faer_core::{Mat, MatRef};
+use std::ops::{Add, Mul, Sub};
+
+fn zip_planes<W, F>(a: &W, b: &W, f: F) -> W
+where
+    W: Planes,
+    F: Fn(MatRef<f32>, MatRef<f32>) -> Mat<f32>,
+{
+    let (a_planes, b_planes) = (a.planes(), b.planes());
+    assert_eq!(
+        a_planes.len(),
+        b_planes.len(),
+        "wrappers must have the same number of channels"
+    );
+    assert!(
+        a_planes[0].nrows() == b_planes[0].nrows() && a_planes[0].ncols() == b_planes[0].ncols(),
+        "wrappers must have the same dimensions, got {}x{} and {}x{}",
+        a_planes[0].ncols(),
+        a_planes[0].nrows(),
+        b_planes[0].ncols(),
+        b_planes[0].nrows(),
+    );
+
+    let mut out = a.map_planes(|mat| mat.to_owned());
+    for (dst, src) in out.planes_mut().iter_mut().zip(b_planes) {
+        *dst = f(dst.as_ref(), src.as_ref());
+    }
+    out
+}
+
+fn scale(mat: MatRef<f32>, factor: f32) -> Mat<f32> {
+    Mat::from_fn(mat.nrows(), mat.ncols(), |i, j| mat.read(i, j) * factor)
+}
+
+pub trait Blend: Planes + Sized {
+    // Linear mix `(1 - alpha) * self + alpha * other`; panics if the dimensions differ
+    fn blend(&self, other: &Self, alpha: f32) -> Self {
+        zip_planes(self, other, |a, b| {
+            Mat::from_fn(a.nrows(), a.ncols(), |i, j| {
+                (1.0 - alpha) * a.read(i, j) + alpha * b.read(i, j)
+            })
+        })
+    }
+}
+
+impl<W: Planes> Blend for W {}
+
+pub trait ApproxEq: Planes {
+    // True if both have the same channels and dimensions and no entry differs by more than `tol`
+    fn approx_eq(&self, other: &Self, tol: f32) -> bool {
+        let (a_planes, b_planes) = (self.planes(), other.planes());
+
+        a_planes.len() == b_planes.len()
+            && a_planes.iter().zip(b_planes).all(|(a, b)| {
+                a.nrows() == b.nrows()
+                    && a.ncols() == b.ncols()
+                    && (0..a.ncols())
+                        .all(|j| (0..a.nrows()).all(|i| (a.read(i, j) - b.read(i, j)).abs() <= tol))
+            })
+    }
+}
+
+impl<W: Planes> ApproxEq for W {}
+
+// Element-wise `+`/`-` (panicking on mismatched dimensions) and scalar `*` for every wrapper
+macro_rules! impl_ops {
+    ($($wrapper:ty),*) => {$(
+        impl Add<&$wrapper> for &$wrapper {
+            type Output = $wrapper;
+
+            fn add(self, rhs: &$wrapper) -> $wrapper {
+                zip_planes(self, rhs, |a, b| a + b)
+            }
+        }
+
+        impl Add for $wrapper {
+            type Output = $wrapper;
+
+            fn add(self, rhs: $wrapper) -> $wrapper {
+                &self + &rhs
+            }
+        }
+
+        impl Sub<&$wrapper> for &$wrapper {
+            type Output = $wrapper;
+
+            fn sub(self, rhs: &$wrapper) -> $wrapper {
+                zip_planes(self, rhs, |a, b| a - b)
+            }
+        }
+
+        impl Sub for $wrapper {
+            type Output = $wrapper;
+
+            fn sub(self, rhs: $wrapper) -> $wrapper {
+                &self - &rhs
+            }
+        }
+
+        impl Mul<f32> for &$wrapper {
+            type Output = $wrapper;
+
+            fn mul(self, rhs: f32) -> $wrapper {
+                self.map_planes(|mat| scale(mat, rhs))
+            }
+        }
+
+        impl Mul<f32> for $wrapper {
+            type Output = $wrapper;
+
+            fn mul(self, rhs: f32) -> $wrapper {
+                &self * rhs
+            }
+        }
+    )*};
+}
+
+impl_ops!(
+    GreyImageWrapper,
+    GreyAlphaImageWrapper,
+    RgbImageWrapper,
+    RgbaImageWrapper,
+    DynWrapper
+);
+
+#[cfg(feature = "cmyk")]
+impl_ops!(CmykImageWrapper);
@@ -0,0 +1,192 @@
+use crate::compress::{Compressible, SvdApproxError};
+use crate::imagewrapper::{LumaWeights, Planes};
+use crate::saliency::box_blur;
+use faer_core::Mat;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Ink {
+    // Whichever of dark and light marks covers more pixels
+    #[default]
+    Auto,
+    Dark,
+    Light,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HybridOptions {
+    // Rank of the photo layer
+    pub rank: usize,
+    // How far (in 0-255 luma) a pixel must stand out from its surroundings to count as ink
+    pub threshold: f32,
+    // Radius of the surroundings. Marks much wider than this blend into them and stay in the
+    // photo layer, so it bounds the stroke width detected.
+    pub radius: usize,
+    pub ink: Ink,
+}
+
+impl HybridOptions {
+    pub fn new(rank: usize) -> Self {
+        HybridOptions {
+            rank,
+            threshold: 48.0,
+            radius: 3,
+            ink: Ink::default(),
+        }
+    }
+}
+
+// The 1-bit layer of `HybridCompress::compress_hybrid`: which pixels are ink, packed eight to a
+// byte in row-major order with the first pixel in the most significant bit, and the ink's value
+// in each channel
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TextLayer {
+    pub width: usize,
+    pub height: usize,
+    pub bits: Vec<u8>,
+    pub ink: Vec<f32>,
+}
+
+impl TextLayer {
+    fn from_mask(mask: &[bool], width: usize, height: usize, ink: Vec<f32>) -> Self {
+        let mut bits = vec![0u8; mask.len().div_ceil(8)];
+        for (p, &set) in mask.iter().enumerate() {
+            if set {
+                bits[p / 8] |= 0x80 >> (p % 8);
+            }
+        }
+        TextLayer {
+            width,
+            height,
+            bits,
+            ink,
+        }
+    }
+
+    pub fn get(&self, i: usize, j: usize) -> bool {
+        let p = i * self.width + j;
+        self.bits[p / 8] & (0x80 >> (p % 8)) != 0
+    }
+
+    // Number of ink pixels
+    pub fn count(&self) -> usize {
+        self.bits.iter().map(|b| b.count_ones() as usize).sum()
+    }
+
+    // Paints the ink over `base`, which must have the layer's size and channel count
+    pub fn composite<W: Planes>(&self, base: &W) -> W {
+        let mats = base
+            .planes()
+            .iter()
+            .zip(&self.ink)
+            .map(|(mat, &ink)| {
+                Mat::from_fn(mat.nrows(), mat.ncols(), |i, j| {
+                    if self.get(i, j) { ink } else { mat.read(i, j) }
+                })
+            })
+            .collect();
+        base.rebuild(mats)
+    }
+}
+
+// Luma of the color planes; alpha and other extra planes are ignored
+fn luma(planes: &[Mat<f32>]) -> Mat<f32> {
+    let (m, n) = (planes[0].nrows(), planes[0].ncols());
+    if planes.len() < 3 {
+        return planes[0].clone();
+    }
+    let [r, g, b] = LumaWeights::Rec601.coefficients();
+    Mat::from_fn(m, n, |i, j| {
+        r * planes[0].read(i, j) + g * planes[1].read(i, j) + b * planes[2].read(i, j)
+    })
+}
+
+// `mat` with the pixels under `mask` replaced by the mean of the unmasked ones around them
+// (normalized convolution), falling back to the mean of the whole plane where there are none
+fn fill(mat: &Mat<f32>, mask: &[bool], radius: usize) -> Mat<f32> {
+    let (m, n) = (mat.nrows(), mat.ncols());
+    let keep = |i: usize, j: usize| !mask[i * n + j];
+    let weights = Mat::from_fn(m, n, |i, j| if keep(i, j) { 1.0 } else { 0.0 });
+    let values = Mat::from_fn(m, n, |i, j| if keep(i, j) { mat.read(i, j) } else { 0.0 });
+    let (weights, values) = (box_blur(&weights, radius), box_blur(&values, radius));
+
+    let (sum, count) = (0..m * n)
+        .filter(|&p| !mask[p])
+        .fold((0.0f64, 0usize), |(sum, count), p| {
+            (sum + mat.read(p / n, p % n) as f64, count + 1)
+        });
+    let mean = if count > 0 {
+        (sum / count as f64) as f32
+    } else {
+        0.0
+    };
+
+    Mat::from_fn(m, n, |i, j| match (keep(i, j), weights.read(i, j)) {
+        (true, _) => mat.read(i, j),
+        (false, w) if w > 0.0 => values.read(i, j) / w,
+        _ => mean,
+    })
+}
+
+fn dilate(mask: &[bool], m: usize, n: usize) -> Vec<bool> {
+    (0..m * n)
+        .map(|p| {
+            let (i, j) = (p / n, p % n);
+            (i.saturating_sub(1)..(i + 2).min(m))
+                .any(|r| (j.saturating_sub(1)..(j + 2).min(n)).any(|c| mask[r * n + c]))
+        })
+        .collect()
+}
+
+pub trait HybridCompress: Planes {
+    // Separates thin high-contrast marks (text, line art) into a lossless 1-bit `TextLayer`,
+    // compresses the rest at `options.rank` with the marks filled in from their surroundings so
+    // they cost the SVD nothing, and paints them back on top. Returns the composite and the
+    // layer. Anti-aliased fringes are filled too rather than kept, so strokes stay crisp.
+    fn compress_hybrid(&self, options: &HybridOptions) -> Result<(Self, TextLayer), SvdApproxError>
+    where
+        Self: Compressible<Error = SvdApproxError> + Sized,
+    {
+        let planes = self.planes();
+        let (m, n) = (planes[0].nrows(), planes[0].ncols());
+        let luma = luma(planes);
+        let surroundings = box_blur(&luma, options.radius);
+        let contrast = |p: usize| luma.read(p / n, p % n) - surroundings.read(p / n, p % n);
+
+        let dark: Vec<bool> = (0..m * n)
+            .map(|p| contrast(p) < -options.threshold)
+            .collect();
+        let light: Vec<bool> = (0..m * n)
+            .map(|p| contrast(p) > options.threshold)
+            .collect();
+        let count = |mask: &[bool]| mask.iter().filter(|&&set| set).count();
+        let mask = match options.ink {
+            Ink::Dark => dark,
+            Ink::Light => light,
+            Ink::Auto if count(&dark) >= count(&light) => dark,
+            Ink::Auto => light,
+        };
+
+        let marked = count(&mask).max(1) as f64;
+        let ink = planes
+            .iter()
+            .map(|mat| {
+                let sum: f64 = (0..m * n)
+                    .filter(|&p| mask[p])
+                    .map(|p| mat.read(p / n, p % n) as f64)
+                    .sum();
+                (sum / marked) as f32
+            })
+            .collect();
+        let layer = TextLayer::from_mask(&mask, n, m, ink);
+
+        let fringe = dilate(&mask, m, n);
+        let photo = self.map_planes(|mat| fill(&mat.to_owned(), &fringe, options.radius));
+        let compressed = photo.compress(options.rank)?;
+        Ok((layer.composite(&compressed), layer))
+    }
+}
+
+impl<W: Planes> HybridCompress for W {}
@@ -0,0 +1,332 @@
+use crate::dynwrapper::DynWrapper;
+use crate::imagewrapper::{
+    GreyAlphaImageWrapper, GreyImageWrapper, Planes, RgbImageWrapper, RgbaImageWrapper,
+};
+use color_quant::NeuQuant;
+use faer_core::Mat;
+use image::error::{EncodingError, ImageFormatHint, ParameterError, ParameterErrorKind};
+use image::{ImageError, ImageFormat, ImageResult, RgbaImage};
+use rayon::prelude::*;
+use std::io::{self, Read, Write};
+
+// NeuQuant sampling factor: 1 is slowest/best, 30 fastest; 10 is the usual default
+const SAMPLE_FACTOR: i32 = 10;
+
+// Size of the palette of an indexed-color PNG, or `None` for any other kind of image. Indexed
+// PNGs load fine through the regular wrappers (the palette is expanded to RGB(A)); this lets
+// callers decide whether to re-quantize on save.
+pub fn png_palette_size<R: Read>(reader: R) -> Option<usize> {
+    let reader = png::Decoder::new(reader).read_info().ok()?;
+    let info = reader.info();
+
+    match info.color_type {
+        png::ColorType::Indexed => info.palette.as_ref().map(|palette| palette.len() / 3),
+        _ => None,
+    }
+}
+
+fn encoding_error(err: png::EncodingError) -> ImageError {
+    ImageError::Encoding(EncodingError::new(
+        ImageFormatHint::Exact(ImageFormat::Png),
+        err,
+    ))
+}
+
+fn check_colors(colors: usize, min: usize) -> ImageResult<()> {
+    if !(min..=256).contains(&colors) {
+        return Err(ImageError::Parameter(ParameterError::from_kind(
+            ParameterErrorKind::Generic(format!(
+                "palette size must be between {} and 256, got {}",
+                min, colors
+            )),
+        )));
+    }
+    Ok(())
+}
+
+fn write_indexed<W: Write>(
+    img: &RgbaImage,
+    alpha: bool,
+    writer: W,
+    colors: usize,
+) -> ImageResult<()> {
+    check_colors(colors, 2)?;
+
+    let quant = NeuQuant::new(SAMPLE_FACTOR, colors, img.as_raw());
+    let indices: Vec<u8> = img
+        .pixels()
+        .map(|pixel| quant.index_of(&pixel.0) as u8)
+        .collect();
+
+    let mut encoder = png::Encoder::new(writer, img.width(), img.height());
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(quant.color_map_rgb());
+    if alpha {
+        let trns: Vec<u8> = quant
+            .color_map_rgba()
+            .chunks_exact(4)
+            .map(|c| c[3])
+            .collect();
+        encoder.set_trns(trns);
+    }
+
+    let mut writer = encoder.write_header().map_err(encoding_error)?;
+    writer.write_image_data(&indices).map_err(encoding_error)
+}
+
+// Re-quantizes the (compressed) colors to a palette of `colors` entries and saves an indexed PNG
+pub trait SaveIndexed {
+    fn save_indexed<W: Write>(&self, writer: W, colors: usize) -> ImageResult<()>;
+}
+
+impl SaveIndexed for GreyImageWrapper {
+    fn save_indexed<W: Write>(&self, writer: W, colors: usize) -> ImageResult<()> {
+        let img = image::DynamicImage::ImageLuma8(self.to_image()).into_rgba8();
+        write_indexed(&img, false, writer, colors)
+    }
+}
+
+impl SaveIndexed for RgbImageWrapper {
+    fn save_indexed<W: Write>(&self, writer: W, colors: usize) -> ImageResult<()> {
+        let img = image::DynamicImage::ImageRgb8(self.to_image()).into_rgba8();
+        write_indexed(&img, false, writer, colors)
+    }
+}
+
+impl SaveIndexed for RgbaImageWrapper {
+    fn save_indexed<W: Write>(&self, writer: W, colors: usize) -> ImageResult<()> {
+        write_indexed(&self.to_image(), true, writer, colors)
+    }
+}
+
+impl SaveIndexed for DynWrapper {
+    fn save_indexed<W: Write>(&self, writer: W, colors: usize) -> ImageResult<()> {
+        let dynamic = self.to_dynamic();
+        let alpha = dynamic.color().has_alpha();
+        write_indexed(&dynamic.into_rgba8(), alpha, writer, colors)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Quantizer {
+    // Heckbert's median cut: repeatedly halves the box of colors with the widest channel range
+    #[default]
+    MedianCut,
+    // Lloyd's k-means, started from the median-cut palette; each iteration visits every pixel
+    // once per palette entry, and lowers the mean squared error further
+    KMeans {
+        iterations: usize,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QuantizeOptions {
+    pub colors: usize,
+    pub quantizer: Quantizer,
+}
+
+impl QuantizeOptions {
+    pub fn new(colors: usize) -> Self {
+        QuantizeOptions {
+            colors,
+            quantizer: Quantizer::default(),
+        }
+    }
+}
+
+// Colors of a quantized image as RGBA, grey expanded to RGB and missing alpha opaque
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Palette {
+    pub colors: Vec<[u8; 4]>,
+}
+
+impl Palette {
+    // GIMP palette (.gpl), which GIMP, Krita, Inkscape and Aseprite all import; alpha is dropped
+    pub fn write_gpl<W: Write>(&self, mut writer: W, name: &str) -> io::Result<()> {
+        writeln!(writer, "GIMP Palette")?;
+        writeln!(writer, "Name: {}", name)?;
+        writeln!(writer, "Columns: 16")?;
+        writeln!(writer, "#")?;
+        for [r, g, b, _] in &self.colors {
+            writeln!(
+                writer,
+                "{:3} {:3} {:3}\t#{:02x}{:02x}{:02x}",
+                r, g, b, r, g, b
+            )?;
+        }
+        Ok(())
+    }
+
+    // Flat RGB triples, as for a PNG PLTE chunk or a GIF color table
+    pub fn to_rgb(&self) -> Vec<u8> {
+        self.colors
+            .iter()
+            .flat_map(|c| [c[0], c[1], c[2]])
+            .collect()
+    }
+}
+
+// Pixels `pixels[i * c..(i + 1) * c]` split into at most `colors` boxes by median cut, as one
+// list of pixel indices per box
+fn median_cut(pixels: &[f32], c: usize, colors: usize) -> Vec<Vec<usize>> {
+    let range = |b: &[usize], k: usize| {
+        let (lo, hi) = b.iter().fold((f32::MAX, f32::MIN), |(lo, hi), &i| {
+            let x = pixels[i * c + k];
+            (lo.min(x), hi.max(x))
+        });
+        hi - lo
+    };
+    let widest = |b: &[usize]| {
+        (0..c)
+            .map(|k| (k, range(b, k)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap_or((0, 0.0))
+    };
+
+    let mut boxes = vec![(0..pixels.len() / c).collect::<Vec<usize>>()];
+    while boxes.len() < colors {
+        let Some((index, (k, _))) = boxes
+            .iter()
+            .map(|b| widest(b))
+            .enumerate()
+            .filter(|(_, (_, range))| *range > 0.0)
+            .max_by(|a, b| a.1.1.total_cmp(&b.1.1))
+        else {
+            // Every box holds a single color
+            break;
+        };
+
+        let mut b = boxes.swap_remove(index);
+        b.sort_unstable_by(|&i, &j| pixels[i * c + k].total_cmp(&pixels[j * c + k]));
+        let upper = b.split_off(b.len() / 2);
+        boxes.push(b);
+        boxes.push(upper);
+    }
+    boxes
+}
+
+fn nearest(pixel: &[f32], palette: &[Vec<f32>]) -> usize {
+    let distance = |color: &Vec<f32>| -> f32 {
+        pixel
+            .iter()
+            .zip(color)
+            .map(|(x, y)| (x - y) * (x - y))
+            .sum()
+    };
+    (0..palette.len())
+        .min_by(|&a, &b| distance(&palette[a]).total_cmp(&distance(&palette[b])))
+        .unwrap_or(0)
+}
+
+fn quantize<T: Planes>(wrapper: &T, options: &QuantizeOptions) -> ImageResult<(T, Palette)> {
+    check_colors(options.colors, 1)?;
+    let planes = wrapper.planes();
+    let c = planes.len();
+    let (m, n) = (planes[0].nrows(), planes[0].ncols());
+
+    // Colors the output could actually be saved with, pixel by pixel
+    let pixels: Vec<f32> = (0..m * n)
+        .flat_map(|p| planes.iter().map(move |mat| mat.read(p / n, p % n)))
+        .map(|x| x.clamp(0.0, 255.0).round())
+        .collect();
+    let mean = |members: &[usize]| -> Vec<f32> {
+        (0..c)
+            .map(|k| {
+                let sum: f64 = members.iter().map(|&i| pixels[i * c + k] as f64).sum();
+                (sum / members.len().max(1) as f64) as f32
+            })
+            .collect()
+    };
+
+    let mut palette: Vec<Vec<f32>> = median_cut(&pixels, c, options.colors)
+        .iter()
+        .filter(|b| !b.is_empty())
+        .map(|b| mean(b))
+        .collect();
+    let assign = |palette: &[Vec<f32>]| -> Vec<usize> {
+        pixels
+            .par_chunks_exact(c)
+            .map(|pixel| nearest(pixel, palette))
+            .collect()
+    };
+
+    let mut labels = assign(&palette);
+    if let Quantizer::KMeans { iterations } = options.quantizer {
+        for _ in 0..iterations {
+            let mut members = vec![Vec::new(); palette.len()];
+            for (i, &label) in labels.iter().enumerate() {
+                members[label].push(i);
+            }
+            // An entry that lost all its pixels keeps its color
+            for (color, members) in palette.iter_mut().zip(&members) {
+                if !members.is_empty() {
+                    *color = mean(members);
+                }
+            }
+            labels = assign(&palette);
+        }
+    }
+
+    let palette: Vec<Vec<f32>> = palette
+        .iter()
+        .map(|color| color.iter().map(|x| x.round()).collect())
+        .collect();
+    let mats = (0..c)
+        .map(|k| Mat::from_fn(m, n, |i, j| palette[labels[i * n + j]][k]))
+        .collect();
+
+    let colors = palette
+        .iter()
+        .map(|color| {
+            let x: Vec<u8> = color.iter().map(|&x| x as u8).collect();
+            match *x.as_slice() {
+                [g] => [g, g, g, 255],
+                [g, a] => [g, g, g, a],
+                [r, g, b] => [r, g, b, 255],
+                [r, g, b, a, ..] => [r, g, b, a],
+                [] => [0, 0, 0, 255],
+            }
+        })
+        .collect();
+    Ok((wrapper.rebuild(mats), Palette { colors }))
+}
+
+// Reduces the (compressed) image to a palette of at most `options.colors` colors, returning it
+// still as a wrapper of the same kind, along with the palette for export
+pub trait Quantize: Sized {
+    fn quantize(&self, options: &QuantizeOptions) -> ImageResult<(Self, Palette)>;
+}
+
+impl Quantize for GreyImageWrapper {
+    fn quantize(&self, options: &QuantizeOptions) -> ImageResult<(Self, Palette)> {
+        quantize(self, options)
+    }
+}
+
+impl Quantize for GreyAlphaImageWrapper {
+    fn quantize(&self, options: &QuantizeOptions) -> ImageResult<(Self, Palette)> {
+        quantize(self, options)
+    }
+}
+
+impl Quantize for RgbImageWrapper {
+    fn quantize(&self, options: &QuantizeOptions) -> ImageResult<(Self, Palette)> {
+        quantize(self, options)
+    }
+}
+
+impl Quantize for RgbaImageWrapper {
+    fn quantize(&self, options: &QuantizeOptions) -> ImageResult<(Self, Palette)> {
+        quantize(self, options)
+    }
+}
+
+impl Quantize for DynWrapper {
+    fn quantize(&self, options: &QuantizeOptions) -> ImageResult<(Self, Palette)> {
+        quantize(self, options)
+    }
+}
@@ -0,0 +1,269 @@
+use crate::compress::{CompressOptions, Compressible, Resize, SvdApproxError};
+use crate::encode::{Encoding, SaveWith};
+use crate::geometry::Geometry;
+use crate::imagewrapper::{ImageWrapper, Planes};
+use faer_core::Mat;
+use image::{ImageError, ImageFormat};
+use std::io::Write;
+
+#[derive(Debug)]
+pub enum StageError {
+    Svd(SvdApproxError),
+    Image(ImageError),
+    Other(String),
+}
+
+impl std::fmt::Display for StageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StageError::Svd(err) => write!(f, "SVD error: {}", err),
+            StageError::Image(err) => write!(f, "Image error: {}", err),
+            StageError::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl From<SvdApproxError> for StageError {
+    fn from(err: SvdApproxError) -> Self {
+        StageError::Svd(err)
+    }
+}
+
+impl From<ImageError> for StageError {
+    fn from(err: ImageError) -> Self {
+        StageError::Image(err)
+    }
+}
+
+// A stage's error, with the stage's position and name
+#[derive(Debug)]
+pub struct PipelineError {
+    pub stage: usize,
+    pub name: String,
+    pub error: StageError,
+}
+
+impl std::fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Stage {} ({}) failed: {}",
+            self.stage, self.name, self.error
+        )
+    }
+}
+
+// One step of a `Pipeline`. Stages are shared between threads, so that one pipeline can serve a
+// whole batch or many requests.
+pub trait Stage<W>: Send + Sync {
+    fn name(&self) -> String;
+
+    fn apply(&self, wrapper: W) -> Result<W, StageError>;
+}
+
+impl<W: Planes + Sized> Stage<W> for Resize {
+    fn name(&self) -> String {
+        format!("resize {}x{}", self.width, self.height)
+    }
+
+    fn apply(&self, wrapper: W) -> Result<W, StageError> {
+        Ok(wrapper.resize(self.width, self.height, self.filter))
+    }
+}
+
+impl<W: Compressible<Error = SvdApproxError> + Planes> Stage<W> for CompressOptions {
+    fn name(&self) -> String {
+        format!("compress rank {}", self.rank)
+    }
+
+    fn apply(&self, wrapper: W) -> Result<W, StageError> {
+        Ok(wrapper.compress_with(self)?)
+    }
+}
+
+// Applies `f` to every color sample, leaving alpha as it is
+fn map_colors<W: Planes>(wrapper: W, f: impl Fn(f32) -> f32) -> W {
+    let alpha = wrapper.alpha();
+    let mats = wrapper
+        .planes()
+        .iter()
+        .enumerate()
+        .map(|(k, mat)| {
+            if Some(k) == alpha {
+                return mat.clone();
+            }
+            Mat::from_fn(mat.nrows(), mat.ncols(), |i, j| f(mat.read(i, j)))
+        })
+        .collect();
+    wrapper.rebuild(mats)
+}
+
+// Decodes the sRGB transfer curve, so that later stages work on light intensities rather than
+// gamma-encoded values; pair it with `Delinearize` before saving. Values stay in 0-255.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Linearize;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Delinearize;
+
+impl<W: Planes> Stage<W> for Linearize {
+    fn name(&self) -> String {
+        "linearize".to_string()
+    }
+
+    fn apply(&self, wrapper: W) -> Result<W, StageError> {
+        Ok(map_colors(wrapper, |x| {
+            let c = (x / 255.0).clamp(0.0, 1.0);
+            let linear = if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            };
+            255.0 * linear
+        }))
+    }
+}
+
+impl<W: Planes> Stage<W> for Delinearize {
+    fn name(&self) -> String {
+        "delinearize".to_string()
+    }
+
+    fn apply(&self, wrapper: W) -> Result<W, StageError> {
+        Ok(map_colors(wrapper, |x| {
+            let c = (x / 255.0).clamp(0.0, 1.0);
+            let encoded = if c <= 0.0031308 {
+                12.92 * c
+            } else {
+                1.055 * c.powf(1.0 / 2.4) - 0.055
+            };
+            255.0 * encoded
+        }))
+    }
+}
+
+// Ordered (4x4 Bayer) dithering: saving truncates samples to 8 bits, and the offsets added here
+// turn the banding that causes in smooth gradients into a fine regular pattern
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Dither;
+
+const BAYER: [[f32; 4]; 4] = [
+    [0.0, 8.0, 2.0, 10.0],
+    [12.0, 4.0, 14.0, 6.0],
+    [3.0, 11.0, 1.0, 9.0],
+    [15.0, 7.0, 13.0, 5.0],
+];
+
+impl<W: Planes> Stage<W> for Dither {
+    fn name(&self) -> String {
+        "dither".to_string()
+    }
+
+    fn apply(&self, wrapper: W) -> Result<W, StageError> {
+        let mats = wrapper
+            .planes()
+            .iter()
+            .map(|mat| {
+                Mat::from_fn(mat.nrows(), mat.ncols(), |i, j| {
+                    mat.read(i, j) + (BAYER[i % 4][j % 4] + 0.5) / 16.0
+                })
+            })
+            .collect();
+        Ok(wrapper.rebuild(mats))
+    }
+}
+
+struct FnStage<F> {
+    name: String,
+    f: F,
+}
+
+impl<W, F> Stage<W> for FnStage<F>
+where
+    F: Fn(W) -> Result<W, StageError> + Send + Sync,
+{
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn apply(&self, wrapper: W) -> Result<W, StageError> {
+        (self.f)(wrapper)
+    }
+}
+
+// Ordered stages run one after another, e.g.
+// `Pipeline::new().then(Linearize).then(CompressOptions::new(40)).then(Delinearize)`, with an
+// optional encoding for `run_to`
+pub struct Pipeline<W> {
+    stages: Vec<Box<dyn Stage<W>>>,
+    encoding: Option<Encoding>,
+}
+
+impl<W> Default for Pipeline<W> {
+    fn default() -> Self {
+        Pipeline {
+            stages: Vec::new(),
+            encoding: None,
+        }
+    }
+}
+
+impl<W> Pipeline<W> {
+    pub fn new() -> Self {
+        Pipeline::default()
+    }
+
+    pub fn then(mut self, stage: impl Stage<W> + 'static) -> Self {
+        self.stages.push(Box::new(stage));
+        self
+    }
+
+    // A closure as a stage
+    pub fn then_fn<F>(self, name: &str, f: F) -> Self
+    where
+        F: Fn(W) -> Result<W, StageError> + Send + Sync + 'static,
+        W: 'static,
+    {
+        self.then(FnStage {
+            name: name.to_string(),
+            f,
+        })
+    }
+
+    // The encoder `run_to` finishes with; PNG without one
+    pub fn encode(mut self, encoding: Encoding) -> Self {
+        self.encoding = Some(encoding);
+        self
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.stages.iter().map(|stage| stage.name()).collect()
+    }
+
+    pub fn run(&self, mut wrapper: W) -> Result<W, PipelineError> {
+        for (k, stage) in self.stages.iter().enumerate() {
+            wrapper = stage.apply(wrapper).map_err(|error| PipelineError {
+                stage: k,
+                name: stage.name(),
+                error,
+            })?;
+        }
+        Ok(wrapper)
+    }
+}
+
+impl<W: ImageWrapper + SaveWith> Pipeline<W> {
+    // Runs the stages and writes the result with the pipeline's encoding
+    pub fn run_to<Wr: Write>(&self, wrapper: W, writer: Wr) -> Result<(), PipelineError> {
+        let output = self.run(wrapper)?;
+        let result = match self.encoding {
+            Some(encoding) => output.save_with(writer, encoding),
+            None => output.save_stream(writer, ImageFormat::Png),
+        };
+        result.map_err(|err| PipelineError {
+            stage: self.stages.len(),
+            name: "encode".to_string(),
+            error: err.into(),
+        })
+    }
+}
@@ -0,0 +1,322 @@
+use crate::font::{GLYPH_HEIGHT, draw_text, text_width};
+use crate::imagewrapper::{ImageWrapper, RgbImageWrapper};
+use crate::spectrum::CurvePoint;
+use faer_core::Mat;
+use image::{ImageFormat, ImageResult};
+use std::io::{Seek, Write};
+
+// Margins around the plotting area, in pixels, leaving room for the title, ticks and labels
+const LEFT: usize = 64;
+const RIGHT: usize = 16;
+const TOP: usize = 36;
+const BOTTOM: usize = 40;
+// Roughly how many ticks each axis gets
+const TICKS: f64 = 6.0;
+
+const WHITE: [u8; 3] = [255, 255, 255];
+const BLACK: [u8; 3] = [0, 0, 0];
+const GRID: [u8; 3] = [225, 225, 225];
+// Series colors, in order; single channels are drawn in black
+const COLORS: [[u8; 3]; 6] = [
+    [214, 39, 40],
+    [44, 160, 44],
+    [31, 119, 180],
+    [127, 127, 127],
+    [255, 127, 14],
+    [148, 103, 189],
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Series {
+    pub label: String,
+    pub color: [u8; 3],
+    pub points: Vec<(f32, f32)>,
+}
+
+// A line chart, rendered without any plotting dependency. Non-finite points, and non-positive
+// ones on a logarithmic axis, are left out.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Plot {
+    pub title: String,
+    pub x_label: String,
+    pub y_label: String,
+    pub log_y: bool,
+    pub series: Vec<Series>,
+}
+
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<[u8; 3]>,
+}
+
+impl Canvas {
+    fn put(&mut self, x: usize, y: usize, color: [u8; 3]) {
+        if x < self.width && y < self.height {
+            self.pixels[y * self.width + x] = color;
+        }
+    }
+
+    fn text(&mut self, text: &str, x: usize, y: usize, scale: usize, color: [u8; 3]) {
+        draw_text(text, x, y, scale, |x, y| self.put(x, y, color));
+    }
+
+    // Two pixels thick, stepping along the longer axis
+    fn line(&mut self, (x0, y0): (f64, f64), (x1, y1): (f64, f64), color: [u8; 3]) {
+        let steps = (x1 - x0).abs().max((y1 - y0).abs()).ceil().max(1.0) as usize;
+        for k in 0..=steps {
+            let t = k as f64 / steps as f64;
+            let (x, y) = (x0 + t * (x1 - x0), y0 + t * (y1 - y0));
+            if x < 0.0 || y < 0.0 {
+                continue;
+            }
+            let (x, y) = (x.round() as usize, y.round() as usize);
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                self.put(x + dx, y + dy, color);
+            }
+        }
+    }
+
+    fn into_wrapper(self) -> RgbImageWrapper {
+        let (width, height, pixels) = (self.width, self.height, self.pixels);
+        let mats = std::array::from_fn(|c| {
+            Mat::from_fn(height, width, |i, j| pixels[i * width + j][c] as f32)
+        });
+        RgbImageWrapper {
+            mats,
+            width,
+            height,
+        }
+    }
+}
+
+// Tick positions covering `lo..=hi` at a step of 1, 2 or 5 times a power of ten, and the
+// number of decimals their labels need
+fn ticks(lo: f64, hi: f64) -> (Vec<f64>, usize) {
+    let raw = (hi - lo) / TICKS;
+    let magnitude = 10f64.powf(raw.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|k| k * magnitude)
+        .find(|&step| step >= raw)
+        .unwrap_or(10.0 * magnitude);
+    let decimals = (-step.log10().floor()).max(0.0) as usize;
+
+    let first = (lo / step).ceil() as i64;
+    let last = (hi / step).floor() as i64;
+    ((first..=last).map(|k| k as f64 * step).collect(), decimals)
+}
+
+// `lo..hi` widened to a nonempty range
+fn span(lo: f64, hi: f64) -> (f64, f64) {
+    if hi > lo {
+        (lo, hi)
+    } else {
+        (lo - 0.5, hi + 0.5)
+    }
+}
+
+impl Plot {
+    pub fn new(title: &str) -> Self {
+        Plot {
+            title: title.to_string(),
+            ..Plot::default()
+        }
+    }
+
+    pub fn render(&self, width: usize, height: usize) -> RgbImageWrapper {
+        let mut canvas = Canvas {
+            width,
+            height,
+            pixels: vec![WHITE; width * height],
+        };
+        let (x0, x1) = (LEFT as f64, width.saturating_sub(RIGHT) as f64);
+        let (y0, y1) = (TOP as f64, height.saturating_sub(BOTTOM) as f64);
+
+        // Points as drawn, with y already on the axis scale
+        let series: Vec<Vec<(f64, f64)>> = self
+            .series
+            .iter()
+            .map(|series| {
+                series
+                    .points
+                    .iter()
+                    .filter(|(x, y)| x.is_finite() && y.is_finite() && (!self.log_y || *y > 0.0))
+                    .map(|&(x, y)| {
+                        let y = if self.log_y {
+                            (y as f64).log10()
+                        } else {
+                            y as f64
+                        };
+                        (x as f64, y)
+                    })
+                    .collect()
+            })
+            .collect();
+        let bounds = |f: fn(&(f64, f64)) -> f64| {
+            let values = series.iter().flatten().map(f);
+            let lo = values.clone().fold(f64::INFINITY, f64::min);
+            let hi = values.fold(f64::NEG_INFINITY, f64::max);
+            if lo.is_finite() {
+                span(lo, hi)
+            } else {
+                (0.0, 1.0)
+            }
+        };
+        let (x_lo, x_hi) = bounds(|p| p.0);
+        let (y_lo, y_hi) = bounds(|p| p.1);
+        let to_px = |(x, y): (f64, f64)| {
+            (
+                x0 + (x - x_lo) / (x_hi - x_lo) * (x1 - x0),
+                y1 - (y - y_lo) / (y_hi - y_lo) * (y1 - y0),
+            )
+        };
+
+        // Grid and tick labels; logarithmic axes are ticked at whole powers of ten
+        let (x_ticks, x_decimals) = ticks(x_lo, x_hi);
+        for x in x_ticks {
+            let (px, _) = to_px((x, y_lo));
+            for y in y0 as usize..y1 as usize {
+                canvas.put(px as usize, y, GRID);
+            }
+            let label = format!("{:.*}", x_decimals, x);
+            let half = text_width(&label, 1) / 2;
+            canvas.text(
+                &label,
+                (px as usize).saturating_sub(half),
+                y1 as usize + 6,
+                1,
+                BLACK,
+            );
+        }
+        let (y_ticks, y_decimals) = if self.log_y {
+            let (lo, hi) = (y_lo.ceil() as i64, y_hi.floor() as i64);
+            let stride = ((hi - lo) as f64 / TICKS).ceil().max(1.0) as usize;
+            ((lo..=hi).step_by(stride).map(|k| k as f64).collect(), 0)
+        } else {
+            ticks(y_lo, y_hi)
+        };
+        for y in y_ticks {
+            let (_, py) = to_px((x_lo, y));
+            for x in x0 as usize..x1 as usize {
+                canvas.put(x, py as usize, GRID);
+            }
+            let label = if self.log_y {
+                format!("1E{}", y)
+            } else {
+                format!("{:.*}", y_decimals, y)
+            };
+            let x = (x0 as usize).saturating_sub(text_width(&label, 1) + 6);
+            canvas.text(
+                &label,
+                x,
+                (py as usize).saturating_sub(GLYPH_HEIGHT / 2),
+                1,
+                BLACK,
+            );
+        }
+
+        // Axes
+        canvas.line((x0, y1), (x1, y1), BLACK);
+        canvas.line((x0, y0), (x0, y1), BLACK);
+
+        for (points, style) in series.iter().zip(&self.series) {
+            for pair in points.windows(2) {
+                canvas.line(to_px(pair[0]), to_px(pair[1]), style.color);
+            }
+            if let [point] = points.as_slice() {
+                canvas.line(to_px(*point), to_px(*point), style.color);
+            }
+        }
+
+        // Legend in the top right corner of the plotting area
+        for (k, style) in self.series.iter().enumerate() {
+            let y = y0 as usize + 6 + k * (GLYPH_HEIGHT + 5);
+            let x = (x1 as usize).saturating_sub(text_width(&style.label, 1) + 24);
+            for dy in 0..GLYPH_HEIGHT {
+                for dx in 0..10 {
+                    canvas.put(x + dx, y + dy, style.color);
+                }
+            }
+            canvas.text(&style.label, x + 14, y, 1, BLACK);
+        }
+
+        let title_x = (width.saturating_sub(text_width(&self.title, 2))) / 2;
+        canvas.text(&self.title, title_x, 8, 2, BLACK);
+        canvas.text(
+            &self.y_label,
+            8,
+            TOP.saturating_sub(GLYPH_HEIGHT + 4),
+            1,
+            BLACK,
+        );
+        let label_x = (x0 as usize + x1 as usize).saturating_sub(text_width(&self.x_label, 1)) / 2;
+        canvas.text(
+            &self.x_label,
+            label_x,
+            height.saturating_sub(GLYPH_HEIGHT + 6),
+            1,
+            BLACK,
+        );
+
+        canvas.into_wrapper()
+    }
+
+    pub fn save_png<W: Write + Seek>(
+        &self,
+        writer: W,
+        width: usize,
+        height: usize,
+    ) -> ImageResult<()> {
+        self.render(width, height).save(writer, ImageFormat::Png)
+    }
+}
+
+// Singular values against their index, on a logarithmic axis, one series per channel; pass
+// `Spectral::spectra` or the `s` of each channel's factors
+pub fn spectrum_plot(spectra: &[Vec<f32>]) -> Plot {
+    let series = spectra
+        .iter()
+        .enumerate()
+        .map(|(c, s)| Series {
+            label: format!("channel {}", c),
+            color: if spectra.len() == 1 {
+                BLACK
+            } else {
+                COLORS[c % COLORS.len()]
+            },
+            points: s
+                .iter()
+                .enumerate()
+                .map(|(k, &x)| ((k + 1) as f32, x))
+                .collect(),
+        })
+        .collect();
+
+    Plot {
+        title: "Singular values".to_string(),
+        x_label: "index".to_string(),
+        y_label: "singular value".to_string(),
+        log_y: true,
+        series,
+    }
+}
+
+// PSNR against rank, from `Spectral::rank_error_curve`; the full-rank point, at infinite PSNR,
+// is left out
+pub fn curve_plot(curve: &[CurvePoint]) -> Plot {
+    Plot {
+        title: "Quality by rank".to_string(),
+        x_label: "rank".to_string(),
+        y_label: "PSNR (dB)".to_string(),
+        log_y: false,
+        series: vec![Series {
+            label: "PSNR".to_string(),
+            color: BLACK,
+            points: curve
+                .iter()
+                .map(|point| (point.rank as f32, point.psnr))
+                .collect(),
+        }],
+    }
+}
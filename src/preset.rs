@@ -0,0 +1,137 @@
+use crate::compress::{CompressOptions, Compressible, Factorizable, Resize, SvdApproxError};
+use crate::document::{DocumentOptions, clean_document};
+use crate::dynwrapper::DynWrapper;
+use crate::encode::{Encoding, SaveWith};
+use crate::imagewrapper::{ImageWrapper, Planes};
+use image::imageops::FilterType;
+use image::{ImageFormat, ImageResult};
+use std::io::Write;
+
+// Longer side of `Preset::Thumbnail` output
+const THUMBNAIL_DIM: usize = 256;
+
+// Ready-made settings: the rank comes from an energy target measured on the image itself, and
+// the encoder is picked to match. `Lossless` keeps every singular value and writes PNG, so the
+// output is the input exactly. `Document` is scan cleanup rather than compression: see
+// `clean_document`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Preset {
+    Archive,
+    Web,
+    Thumbnail,
+    Lossless,
+    Document,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 5] = [
+        Preset::Archive,
+        Preset::Web,
+        Preset::Thumbnail,
+        Preset::Lossless,
+        Preset::Document,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::Archive => "archive",
+            Preset::Web => "web",
+            Preset::Thumbnail => "thumbnail",
+            Preset::Lossless => "lossless",
+            Preset::Document => "document",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Preset::ALL.into_iter().find(|preset| preset.name() == name)
+    }
+
+    // Fraction of each channel's energy the chosen rank retains
+    pub fn energy(self) -> f32 {
+        match self {
+            Preset::Archive => 0.9999,
+            Preset::Web => 0.995,
+            Preset::Thumbnail => 0.98,
+            Preset::Lossless | Preset::Document => 1.0,
+        }
+    }
+
+    // `None` means lossless PNG
+    pub fn encoding(self) -> Option<Encoding> {
+        match self {
+            Preset::Web => Some(Encoding::Jpeg { quality: 85 }),
+            Preset::Thumbnail => Some(Encoding::Jpeg { quality: 75 }),
+            Preset::Archive | Preset::Lossless | Preset::Document => None,
+        }
+    }
+
+    pub fn format(self) -> ImageFormat {
+        match self.encoding() {
+            Some(Encoding::Jpeg { .. }) => ImageFormat::Jpeg,
+            Some(Encoding::WebPLossless) => ImageFormat::WebP,
+            Some(Encoding::Avif { .. }) => ImageFormat::Avif,
+            None => ImageFormat::Png,
+        }
+    }
+
+    // Runs the SVD once to find the rank meeting `energy()`. Thumbnails are downscaled first,
+    // so their rank is measured on the full image and capped to the smaller size. Lossy presets
+    // premultiply alpha. `Document` leaves the image as it is here; `apply` does its cleanup.
+    pub fn options<W: Factorizable + Planes>(
+        self,
+        wrapper: &W,
+    ) -> Result<CompressOptions, SvdApproxError> {
+        if matches!(self, Preset::Lossless | Preset::Document) {
+            return Ok(CompressOptions::new(wrapper.max_rank()));
+        }
+
+        let mut options = CompressOptions {
+            premultiply: true,
+            ..CompressOptions::new(wrapper.effective_rank(self.energy())?)
+        };
+
+        if self == Preset::Thumbnail {
+            let (height, width) = (wrapper.planes()[0].nrows(), wrapper.planes()[0].ncols());
+            let scale = (THUMBNAIL_DIM as f32 / width.max(height) as f32).min(1.0);
+            let resize = Resize {
+                width: ((width as f32 * scale).round() as usize).max(1),
+                height: ((height as f32 * scale).round() as usize).max(1),
+                filter: FilterType::Lanczos3,
+            };
+            options.rank = options.rank.min(resize.width.min(resize.height));
+            options.resize = Some(resize);
+        }
+
+        Ok(options)
+    }
+
+    // The whole preset: compression with `options()`, or `clean_document` with its defaults
+    pub fn apply(self, wrapper: &DynWrapper) -> Result<DynWrapper, SvdApproxError> {
+        match self {
+            Preset::Document => Ok(DynWrapper::Grey(clean_document(
+                wrapper,
+                &DocumentOptions::default(),
+            )?)),
+            _ => wrapper.compress_with(&self.options(wrapper)?),
+        }
+    }
+
+    // Writes with `encoding()`, or as PNG if that is `None`
+    pub fn save<I, W>(self, image: &I, writer: W) -> ImageResult<()>
+    where
+        I: ImageWrapper + SaveWith,
+        W: Write,
+    {
+        match self.encoding() {
+            Some(encoding) => image.save_with(writer, encoding),
+            None => image.save_stream(writer, ImageFormat::Png),
+        }
+    }
+}
+
+impl std::fmt::Display for Preset {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
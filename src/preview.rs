@@ -0,0 +1,75 @@
+use crate::dynwrapper::DynWrapper;
+use crate::imagewrapper::{
+    GreyAlphaImageWrapper, GreyImageWrapper, RgbImageWrapper, RgbaImageWrapper,
+};
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, Rgba};
+use std::fmt::Write;
+
+// Renders with the upper half block: the foreground color paints the top pixel of each cell
+// and the background the bottom one, so a cell covers a 1x2 (roughly square) pixel area
+fn render(img: DynamicImage, cols: usize) -> String {
+    let cols = cols.clamp(1, img.width().max(1) as usize) as u32;
+    let rows = ((img.height() as u64 * cols as u64) / img.width().max(1) as u64).max(1) as u32;
+    let img = imageops::resize(&img.into_rgba8(), cols, rows, FilterType::Triangle);
+
+    // Transparent pixels fade to black, the most common terminal background
+    let color = |x: u32, y: u32| {
+        let Rgba([r, g, b, a]) = *img.get_pixel(x, y);
+        let fade = |c: u8| c as u16 * a as u16 / 255;
+        format!("{};{};{}", fade(r), fade(g), fade(b))
+    };
+
+    let mut out = String::new();
+    for y in (0..rows).step_by(2) {
+        for x in 0..cols {
+            if y + 1 < rows {
+                let _ = write!(
+                    out,
+                    "\x1b[38;2;{}m\x1b[48;2;{}m\u{2580}",
+                    color(x, y),
+                    color(x, y + 1)
+                );
+            } else {
+                let _ = write!(out, "\x1b[38;2;{}m\x1b[49m\u{2580}", color(x, y));
+            }
+        }
+        out.push_str("\x1b[0m\n");
+    }
+    out
+}
+
+// 24-bit ANSI preview at most `cols` characters wide, for eyeballing results in a terminal
+pub trait Preview {
+    fn preview(&self, cols: usize) -> String;
+}
+
+impl Preview for GreyImageWrapper {
+    fn preview(&self, cols: usize) -> String {
+        render(DynamicImage::ImageLuma8(self.to_image()), cols)
+    }
+}
+
+impl Preview for GreyAlphaImageWrapper {
+    fn preview(&self, cols: usize) -> String {
+        render(DynamicImage::ImageLumaA8(self.to_image()), cols)
+    }
+}
+
+impl Preview for RgbImageWrapper {
+    fn preview(&self, cols: usize) -> String {
+        render(DynamicImage::ImageRgb8(self.to_image()), cols)
+    }
+}
+
+impl Preview for RgbaImageWrapper {
+    fn preview(&self, cols: usize) -> String {
+        render(DynamicImage::ImageRgba8(self.to_image()), cols)
+    }
+}
+
+impl Preview for DynWrapper {
+    fn preview(&self, cols: usize) -> String {
+        render(self.to_dynamic(), cols)
+    }
+}
@@ -0,0 +1,192 @@
+use crate::compress::{SvdApproxError, SvdBackend, svd};
+use crate::imagewrapper::Planes;
+use faer_core::{Mat, MatRef};
+use rayon::prelude::*;
+
+// Source of the importance map steering `WeightedCompress::compress_salient`. Implement it to
+// plug in a face or saliency detector; the planes are the image's channels with values in
+// 0-255, and the map must have the same shape, with values in [0, 1] (1 is most important).
+pub trait SaliencyProvider {
+    fn importance(&self, planes: &[Mat<f32>]) -> Mat<f32>;
+}
+
+// Local gradient energy averaged over a `(2 * radius + 1)` square window, scaled so the busiest
+// region gets 1; every pixel gets at least `floor`, so flat regions still count for something
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GradientSaliency {
+    pub radius: usize,
+    pub floor: f32,
+}
+
+impl Default for GradientSaliency {
+    fn default() -> Self {
+        GradientSaliency {
+            radius: 8,
+            floor: 0.1,
+        }
+    }
+}
+
+// Mean of `mat` over each `(2 * radius + 1)` square window clipped to the image, from a
+// summed-area table
+pub(crate) fn box_blur(mat: &Mat<f32>, radius: usize) -> Mat<f32> {
+    let (m, n) = (mat.nrows(), mat.ncols());
+    let mut table = vec![0.0f64; (m + 1) * (n + 1)];
+    for i in 0..m {
+        for j in 0..n {
+            table[(i + 1) * (n + 1) + j + 1] =
+                mat.read(i, j) as f64 + table[i * (n + 1) + j + 1] + table[(i + 1) * (n + 1) + j]
+                    - table[i * (n + 1) + j];
+        }
+    }
+
+    Mat::from_fn(m, n, |i, j| {
+        let (i0, i1) = (i.saturating_sub(radius), (i + radius + 1).min(m));
+        let (j0, j1) = (j.saturating_sub(radius), (j + radius + 1).min(n));
+        let sum = table[i1 * (n + 1) + j1] - table[i0 * (n + 1) + j1] - table[i1 * (n + 1) + j0]
+            + table[i0 * (n + 1) + j0];
+        (sum / ((i1 - i0) * (j1 - j0)) as f64) as f32
+    })
+}
+
+impl SaliencyProvider for GradientSaliency {
+    fn importance(&self, planes: &[Mat<f32>]) -> Mat<f32> {
+        let (m, n) = planes
+            .first()
+            .map_or((0, 0), |mat| (mat.nrows(), mat.ncols()));
+        let gradient = Mat::from_fn(m, n, |i, j| {
+            planes
+                .iter()
+                .map(|mat| {
+                    let dx = mat.read(i, (j + 1).min(n - 1)) - mat.read(i, j.saturating_sub(1));
+                    let dy = mat.read((i + 1).min(m - 1), j) - mat.read(i.saturating_sub(1), j);
+                    (dx * dx + dy * dy).sqrt()
+                })
+                .sum::<f32>()
+        });
+
+        let energy = box_blur(&gradient, self.radius);
+        let max = (0..n)
+            .flat_map(|j| (0..m).map(move |i| (i, j)))
+            .fold(0.0f32, |max, (i, j)| max.max(energy.read(i, j)));
+        let floor = self.floor.clamp(0.0, 1.0);
+        Mat::from_fn(m, n, |i, j| {
+            let x = if max > 0.0 {
+                energy.read(i, j) / max
+            } else {
+                1.0
+            };
+            floor + (1.0 - floor) * x
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WeightedOptions {
+    pub rank: usize,
+    // Each one costs a full SVD per channel
+    pub iterations: usize,
+}
+
+impl WeightedOptions {
+    pub fn new(rank: usize) -> Self {
+        WeightedOptions {
+            rank,
+            iterations: 8,
+        }
+    }
+}
+
+// Rank-`rank` approximation minimizing the squared error weighted by `weights`, by the EM
+// iteration of Srebro and Jaakkola (2003): fill in the image where weights are low with the
+// current approximation, blended by weight, and truncate the blend's SVD again. Each step can
+// only lower the weighted error, and it starts from the ordinary truncated SVD.
+fn weighted_approx(
+    mat: MatRef<f32>,
+    weights: MatRef<f32>,
+    options: &WeightedOptions,
+) -> Result<Mat<f32>, SvdApproxError> {
+    let (m, n) = (mat.nrows(), mat.ncols());
+
+    // Rank 0 gives the mean, as for unweighted compression, here weighted too
+    if options.rank == 0 {
+        let (mut sum, mut total) = (0.0f64, 0.0f64);
+        for j in 0..n {
+            for i in 0..m {
+                let w = weights.read(i, j).clamp(0.0, 1.0) as f64;
+                sum += w * mat.read(i, j) as f64;
+                total += w;
+            }
+        }
+        let mean = if total > 0.0 {
+            (sum / total) as f32
+        } else {
+            0.0
+        };
+        return Ok(Mat::from_fn(m, n, |_, _| mean));
+    }
+
+    let rank_k = |target: MatRef<f32>| -> Result<Mat<f32>, SvdApproxError> {
+        Ok(svd(target, SvdBackend::default())?
+            .truncate(options.rank, false)?
+            .reconstruct())
+    };
+
+    let mut approx = rank_k(mat)?;
+    for _ in 0..options.iterations {
+        let blend = Mat::from_fn(m, n, |i, j| {
+            let w = weights.read(i, j).clamp(0.0, 1.0);
+            w * mat.read(i, j) + (1.0 - w) * approx.read(i, j)
+        });
+        approx = rank_k(blend.as_ref())?;
+    }
+    Ok(approx)
+}
+
+pub trait WeightedCompress: Planes {
+    // Spends the rank where `weights` (one value in [0, 1] per pixel, shared by all channels) is
+    // high, at the expense of where it is low; a weight of 0 masks a pixel out entirely
+    fn compress_weighted(
+        &self,
+        weights: MatRef<f32>,
+        options: &WeightedOptions,
+    ) -> Result<Self, SvdApproxError>
+    where
+        Self: Sized,
+    {
+        let planes = self.planes();
+        let (m, n) = (planes[0].nrows(), planes[0].ncols());
+        if (weights.nrows(), weights.ncols()) != (m, n) {
+            return Err(SvdApproxError::ShapeMismatch(
+                (m, n),
+                (weights.nrows(), weights.ncols()),
+            ));
+        }
+        if options.rank > m.min(n) {
+            return Err(SvdApproxError::InvalidRank(m.min(n), options.rank));
+        }
+
+        let mats = planes
+            .par_iter()
+            .map(|mat| weighted_approx(mat.as_ref(), weights, options))
+            .collect::<Result<Vec<_>, SvdApproxError>>()?;
+        Ok(self.rebuild(mats))
+    }
+
+    // `compress_weighted` with the importance map `provider` computes for this image
+    fn compress_salient(
+        &self,
+        provider: &dyn SaliencyProvider,
+        options: &WeightedOptions,
+    ) -> Result<Self, SvdApproxError>
+    where
+        Self: Sized,
+    {
+        let weights = provider.importance(self.planes());
+        self.compress_weighted(weights.as_ref(), options)
+    }
+}
+
+impl<W: Planes> WeightedCompress for W {}
@@ -0,0 +1,199 @@
+use crate::compress::{SvdApproxError, dc, effective_rank, singular_values};
+use crate::imagewrapper::Planes;
+use rayon::prelude::*;
+use std::io::{self, Write};
+
+// Normalized singular values below this are taken to be f32 round-off rather than signal, so
+// exactly low-rank images don't send the log distance to infinity
+const FLOOR: f32 = 1e-7;
+// Share of the energy `compressibility_score` asks for
+const SCORE_ENERGY: f32 = 0.95;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpectralComparison {
+    // Mean over channels of `channels`
+    pub distance: f32,
+    // Root mean square difference of the log10 spectra of each channel pair
+    pub channels: Vec<f32>,
+    // Fraction of each image's energy in the lower half of its spectrum, averaged over
+    // channels. Recompressed and synthetic images keep almost none there.
+    pub tail: (f32, f32),
+}
+
+// Spectrum `s` scaled to unit energy and resampled at `len` evenly spaced relative ranks, so
+// that images of different sizes and brightness compare by shape alone
+fn normalized(s: &[f32], len: usize) -> Vec<f32> {
+    let norm = s.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if s.is_empty() || norm == 0.0 {
+        return vec![FLOOR; len];
+    }
+    (0..len)
+        .map(|k| {
+            let x = if len > 1 {
+                k as f32 * (s.len() - 1) as f32 / (len - 1) as f32
+            } else {
+                0.0
+            };
+            let (i, t) = (x as usize, x.fract());
+            let next = s.get(i + 1).copied().unwrap_or(s[i]);
+            ((s[i] + t * (next - s[i])) / norm).max(FLOOR)
+        })
+        .collect()
+}
+
+// One row of `Spectral::rank_error_curve`
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CurvePoint {
+    pub rank: usize,
+    // Frobenius norm of the error over all channels
+    pub frobenius_error: f32,
+    // In dB against a peak of 255; infinite at full rank
+    pub psnr: f32,
+    // Size of the f32 factors, as `write_container` stores them
+    pub bytes: usize,
+}
+
+// Writes `rank,frobenius_error,psnr,bytes` rows under a header
+pub fn write_curve_csv<W: Write>(mut writer: W, curve: &[CurvePoint]) -> io::Result<()> {
+    writeln!(writer, "rank,frobenius_error,psnr,bytes")?;
+    for point in curve {
+        writeln!(
+            writer,
+            "{},{},{},{}",
+            point.rank, point.frobenius_error, point.psnr, point.bytes
+        )?;
+    }
+    Ok(())
+}
+
+fn tail(s: &[f32]) -> f32 {
+    let total: f32 = s.iter().map(|x| x * x).sum();
+    if total == 0.0 {
+        return 0.0;
+    }
+    s[s.len() / 2..].iter().map(|x| x * x).sum::<f32>() / total
+}
+
+fn mean_tail(spectra: &[Vec<f32>]) -> f32 {
+    spectra.iter().map(|s| tail(s)).sum::<f32>() / spectra.len().max(1) as f32
+}
+
+pub trait Spectral: Planes {
+    // Singular values of each channel, in decreasing order
+    fn spectra(&self) -> Result<Vec<Vec<f32>>, SvdApproxError> {
+        self.planes()
+            .par_iter()
+            .map(|mat| Ok(singular_values(mat.as_ref())?.0))
+            .collect()
+    }
+
+    // Compares the shapes of two images' singular spectra channel by channel. The images may
+    // differ in size; panics if they differ in channel count.
+    fn compare_spectra<W: Planes>(&self, other: &W) -> Result<SpectralComparison, SvdApproxError> {
+        let a = self.spectra()?;
+        let b = Spectral::spectra(other)?;
+        assert_eq!(
+            a.len(),
+            b.len(),
+            "wrappers must have the same number of channels"
+        );
+
+        let channels: Vec<f32> = a
+            .iter()
+            .zip(&b)
+            .map(|(a, b)| {
+                let len = a.len().min(b.len());
+                let (a, b) = (normalized(a, len), normalized(b, len));
+                let sum: f32 = a
+                    .iter()
+                    .zip(&b)
+                    .map(|(x, y)| (x.log10() - y.log10()).powi(2))
+                    .sum();
+                (sum / len.max(1) as f32).sqrt()
+            })
+            .collect();
+
+        Ok(SpectralComparison {
+            distance: channels.iter().sum::<f32>() / channels.len().max(1) as f32,
+            channels,
+            tail: (mean_tail(&a), mean_tail(&b)),
+        })
+    }
+
+    // One minus the fraction of the full rank that the hungriest channel needs to keep 95% of
+    // its energy about the mean, which rank 0 already reproduces: near 1 for smooth or
+    // low-rank images that compress well, near 0 for noise. Only singular values are
+    // computed, so this is a cheap first pass for triaging a batch.
+    fn compressibility_score(&self) -> Result<f32, SvdApproxError> {
+        let ranks = self
+            .planes()
+            .par_iter()
+            .map(|mat| {
+                let centered = mat - dc(mat.as_ref());
+                let (s, total) = singular_values(centered.as_ref())?;
+                let rank = effective_rank(&s, total, SCORE_ENERGY, true).unwrap_or(s.len());
+                Ok((rank, s.len()))
+            })
+            .collect::<Result<Vec<_>, SvdApproxError>>()?;
+
+        Ok(ranks
+            .into_iter()
+            .filter(|&(_, len)| len > 0)
+            .map(|(rank, len)| 1.0 - rank as f32 / len as f32)
+            .fold(1.0, f32::min))
+    }
+
+    // Error and storage of `compress` at every `step`-th rank from 0 up to and including full
+    // rank. The errors of a truncated SVD follow from the singular values alone, so this costs
+    // one spectrum per channel rather than a compression per rank; they are those of the float
+    // reconstruction, before rounding to 8 bits.
+    fn rank_error_curve(&self, step: usize) -> Result<Vec<CurvePoint>, SvdApproxError> {
+        let planes = self.planes();
+        let Some(first) = planes.first() else {
+            return Ok(Vec::new());
+        };
+        let (m, n) = (first.nrows(), first.ncols());
+        let k = m.min(n);
+
+        // Squared error left by each channel at every rank, the tail sums of its spectrum;
+        // rank 0 is the mean rather than zero, so its error is that of the centered channel
+        let residuals = planes
+            .par_iter()
+            .map(|mat| {
+                let (s, _) = singular_values(mat.as_ref())?;
+                let mut residual = vec![0.0f64; k + 1];
+                for r in (0..k).rev() {
+                    residual[r] = residual[r + 1] + (s[r] as f64).powi(2);
+                }
+                residual[0] = ((mat - dc(mat.as_ref())).norm_l2() as f64).powi(2);
+                Ok(residual)
+            })
+            .collect::<Result<Vec<_>, SvdApproxError>>()?;
+
+        let mut ranks: Vec<usize> = (0..k).step_by(step.max(1)).collect();
+        ranks.push(k);
+        let pixels = (planes.len() * m * n) as f64;
+
+        Ok(ranks
+            .into_iter()
+            .map(|rank| {
+                let squared: f64 = residuals.iter().map(|residual| residual[rank]).sum();
+                let mse = squared / pixels;
+                CurvePoint {
+                    rank,
+                    frobenius_error: squared.sqrt() as f32,
+                    psnr: if mse == 0.0 {
+                        f32::INFINITY
+                    } else {
+                        (10.0 * (255.0 * 255.0 / mse).log10()) as f32
+                    },
+                    bytes: planes.len() * rank * (m + n + 1) * size_of::<f32>(),
+                }
+            })
+            .collect())
+    }
+}
+
+impl<W: Planes> Spectral for W {}
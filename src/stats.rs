@@ -0,0 +1,100 @@
+use crate::imagewrapper::Planes;
+use faer_core::MatRef;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChannelStats {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    pub variance: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Histogram {
+    pub min: f32,
+    pub max: f32,
+    pub counts: Vec<usize>,
+    // Samples outside `[min, max]`, e.g. the over/undershoot of a low-rank reconstruction
+    pub below: usize,
+    pub above: usize,
+}
+
+pub(crate) fn channel_stats(mat: MatRef<f32>) -> ChannelStats {
+    let mut min = f32::INFINITY;
+    let mut max = f32::NEG_INFINITY;
+    let mut sum = 0.0f64;
+    let mut sum_sq = 0.0f64;
+
+    for j in 0..mat.ncols() {
+        for i in 0..mat.nrows() {
+            let x = mat.read(i, j);
+            min = min.min(x);
+            max = max.max(x);
+            sum += x as f64;
+            sum_sq += (x as f64) * (x as f64);
+        }
+    }
+
+    let count = (mat.nrows() * mat.ncols()) as f64;
+    let mean = sum / count;
+
+    ChannelStats {
+        min,
+        max,
+        mean: mean as f32,
+        variance: (sum_sq / count - mean * mean).max(0.0) as f32,
+    }
+}
+
+fn channel_histogram(mat: MatRef<f32>, bins: usize, min: f32, max: f32) -> Histogram {
+    let mut histogram = Histogram {
+        min,
+        max,
+        counts: vec![0; bins],
+        below: 0,
+        above: 0,
+    };
+    let width = (max - min) / bins as f32;
+
+    for j in 0..mat.ncols() {
+        for i in 0..mat.nrows() {
+            let x = mat.read(i, j);
+            if x < min {
+                histogram.below += 1;
+            } else if x > max {
+                histogram.above += 1;
+            } else {
+                let bin = (((x - min) / width) as usize).min(bins - 1);
+                histogram.counts[bin] += 1;
+            }
+        }
+    }
+
+    histogram
+}
+
+pub trait Statistics: Planes {
+    fn stats(&self) -> Vec<ChannelStats> {
+        self.planes()
+            .iter()
+            .map(|mat| channel_stats(mat.as_ref()))
+            .collect()
+    }
+
+    // One histogram per channel with `bins` equal-width bins spanning `[min, max]`
+    fn histogram(&self, bins: usize, min: f32, max: f32) -> Vec<Histogram> {
+        assert!(
+            bins > 0 && min < max,
+            "need at least one bin and `min < max`"
+        );
+
+        self.planes()
+            .iter()
+            .map(|mat| channel_histogram(mat.as_ref(), bins, min, max))
+            .collect()
+    }
+}
+
+impl<W: Planes> Statistics for W {}
@@ -0,0 +1,359 @@
+use crate::compress::{
+    SvdApproxError, SvdBackend, SvdFactors, mean_factors, orthonormalize, product, svd,
+};
+use crate::diagnostics::check_downcast;
+use faer_core::{Mat, MatRef};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum StreamError {
+    Io(io::Error),
+    Decoding(png::DecodingError),
+    Encoding(png::EncodingError),
+    Svd(SvdApproxError),
+    Unsupported(String),
+}
+
+impl std::fmt::Display for StreamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StreamError::Io(err) => write!(f, "I/O error: {}", err),
+            StreamError::Decoding(err) => write!(f, "PNG decoding error: {}", err),
+            StreamError::Encoding(err) => write!(f, "PNG encoding error: {}", err),
+            StreamError::Svd(err) => write!(f, "SVD error: {}", err),
+            StreamError::Unsupported(msg) => write!(f, "Unsupported input: {}.", msg),
+        }
+    }
+}
+
+impl From<io::Error> for StreamError {
+    fn from(err: io::Error) -> Self {
+        StreamError::Io(err)
+    }
+}
+
+impl From<png::DecodingError> for StreamError {
+    fn from(err: png::DecodingError) -> Self {
+        StreamError::Decoding(err)
+    }
+}
+
+impl From<png::EncodingError> for StreamError {
+    fn from(err: png::EncodingError) -> Self {
+        StreamError::Encoding(err)
+    }
+}
+
+impl From<SvdApproxError> for StreamError {
+    fn from(err: SvdApproxError) -> Self {
+        StreamError::Svd(err)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StreamOptions {
+    pub rank: usize,
+    // Rows held in memory at once
+    pub strip_rows: usize,
+    // Extra sampled directions beyond `rank`, which make the captured subspace more accurate
+    pub oversample: usize,
+    // Each one costs two more passes over the input and sharpens a slowly decaying spectrum
+    pub power_iterations: usize,
+}
+
+impl StreamOptions {
+    pub fn new(rank: usize) -> Self {
+        StreamOptions {
+            rank,
+            strip_rows: 256,
+            oversample: 10,
+            power_iterations: 1,
+        }
+    }
+}
+
+struct Layout {
+    width: usize,
+    height: usize,
+    channels: usize,
+    color: png::ColorType,
+}
+
+fn open(path: &Path) -> Result<(png::Reader<BufReader<File>>, Layout), StreamError> {
+    let file = BufReader::new(File::open(path)?);
+    // Only one row is ever buffered, so the decoder's allocation limit is no concern
+    let mut decoder = png::Decoder::new_with_limits(file, png::Limits { bytes: usize::MAX });
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let reader = decoder.read_info()?;
+
+    if reader.info().interlaced {
+        return Err(StreamError::Unsupported(
+            "interlaced PNGs can't be read row by row".to_string(),
+        ));
+    }
+
+    // `normalize_to_color8` turns 16-bit samples into 8-bit ones
+    let info = reader.info();
+    if info.bit_depth == png::BitDepth::Sixteen {
+        let color = match info.color_type {
+            png::ColorType::Grayscale => image::ColorType::L16,
+            png::ColorType::GrayscaleAlpha => image::ColorType::La16,
+            png::ColorType::Rgba => image::ColorType::Rgba16,
+            _ => image::ColorType::Rgb16,
+        };
+        check_downcast(color).map_err(StreamError::Unsupported)?;
+    }
+
+    let (color, _) = reader.output_color_type();
+    let (width, height) = reader.info().size();
+    let layout = Layout {
+        width: width as usize,
+        height: height as usize,
+        channels: color.samples(),
+        color,
+    };
+    Ok((reader, layout))
+}
+
+// One pass over the input, calling `f` with the first row index and one matrix per channel for
+// every strip of at most `strip_rows` rows
+fn for_each_strip(
+    path: &Path,
+    strip_rows: usize,
+    mut f: impl FnMut(usize, &[Mat<f32>]),
+) -> Result<(), StreamError> {
+    let (mut reader, layout) = open(path)?;
+    let (width, channels) = (layout.width, layout.channels);
+    let mut buf = Vec::with_capacity(strip_rows * width * channels);
+
+    for row0 in (0..layout.height).step_by(strip_rows) {
+        let rows = strip_rows.min(layout.height - row0);
+        buf.clear();
+        for _ in 0..rows {
+            let row = reader
+                .next_row()?
+                .ok_or_else(|| StreamError::Unsupported("truncated image data".to_string()))?;
+            buf.extend_from_slice(row.data());
+        }
+
+        let strip: Vec<Mat<f32>> = (0..channels)
+            .map(|k| {
+                Mat::from_fn(rows, width, |i, j| {
+                    buf[(i * width + j) * channels + k] as f32
+                })
+            })
+            .collect();
+        f(row0, &strip);
+    }
+
+    Ok(())
+}
+
+fn write_rows(dst: &mut Mat<f32>, row0: usize, src: MatRef<f32>) {
+    for j in 0..src.ncols() {
+        for i in 0..src.nrows() {
+            dst.write(row0 + i, j, src.read(i, j));
+        }
+    }
+}
+
+// Deterministic random signs, which work as well as Gaussian test vectors for range finding
+fn test_matrix(rows: usize, cols: usize) -> Mat<f32> {
+    let mut state = 0x9e37_79b9_7f4a_7c15u64;
+    Mat::from_fn(rows, cols, |_, _| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        if state & 1 == 0 { 1.0 } else { -1.0 }
+    })
+}
+
+// Rank-`rank` factors of every channel of a PNG too large to hold in memory, reading it only
+// a strip at a time. This is the randomized SVD of Halko, Martinsson and Tropp (2011): a pass
+// computing `Y = A * Omega` for random test vectors `Omega` captures the dominant column space,
+// two more passes per power iteration refine it, and a final pass projects `A` onto it. Memory
+// is `O((height + width) * (rank + oversample))` floats per channel plus one strip.
+pub fn stream_factors(
+    input: impl AsRef<Path>,
+    options: &StreamOptions,
+) -> Result<Vec<SvdFactors>, StreamError> {
+    let input = input.as_ref();
+    let (_, layout) = open(input)?;
+    let (m, n) = (layout.height, layout.width);
+    let k = m.min(n);
+    if options.rank > k {
+        return Err(SvdApproxError::InvalidRank(k, options.rank).into());
+    }
+
+    let strip_rows = options.strip_rows.max(1);
+    let l = (options.rank + options.oversample).min(k);
+    let omega = test_matrix(n, l);
+    let channels = layout.channels;
+
+    let mut ys = vec![Mat::zeros(m, l); channels];
+    let mut energy = vec![0.0f32; channels];
+    let mut sums = vec![0.0f64; channels];
+    for_each_strip(input, strip_rows, |row0, strip| {
+        for (c, a) in strip.iter().enumerate() {
+            write_rows(
+                &mut ys[c],
+                row0,
+                product(a.as_ref(), omega.as_ref()).as_ref(),
+            );
+            let norm = a.norm_l2();
+            energy[c] += norm * norm;
+            sums[c] += (0..a.ncols())
+                .map(|j| a.col_as_slice(j).iter().map(|&x| x as f64).sum::<f64>())
+                .sum::<f64>();
+        }
+    })?;
+    // Rank 0 keeps the mean, which the sums give exactly where the sketch would only
+    // approximate it
+    if options.rank == 0 {
+        return Ok(sums
+            .into_iter()
+            .zip(energy)
+            .map(|(sum, energy)| mean_factors(m, n, (sum / (m * n) as f64) as f32, energy))
+            .collect());
+    }
+
+    for _ in 0..options.power_iterations {
+        let qs: Vec<Mat<f32>> = ys.into_iter().map(orthonormalize).collect();
+        let mut zs = vec![Mat::zeros(n, l); channels];
+        for_each_strip(input, strip_rows, |row0, strip| {
+            for (c, a) in strip.iter().enumerate() {
+                let q = qs[c].as_ref().subrows(row0, a.nrows());
+                zs[c] += product(a.transpose(), q);
+            }
+        })?;
+
+        let zs: Vec<Mat<f32>> = zs.into_iter().map(orthonormalize).collect();
+        ys = vec![Mat::zeros(m, l); channels];
+        for_each_strip(input, strip_rows, |row0, strip| {
+            for (c, a) in strip.iter().enumerate() {
+                write_rows(
+                    &mut ys[c],
+                    row0,
+                    product(a.as_ref(), zs[c].as_ref()).as_ref(),
+                );
+            }
+        })?;
+    }
+
+    let qs: Vec<Mat<f32>> = ys.into_iter().map(orthonormalize).collect();
+    let mut bs = vec![Mat::zeros(l, n); channels];
+    for_each_strip(input, strip_rows, |row0, strip| {
+        for (c, a) in strip.iter().enumerate() {
+            let q = qs[c].as_ref().subrows(row0, a.nrows());
+            bs[c] += product(q.transpose(), a.as_ref());
+        }
+    })?;
+
+    qs.iter()
+        .zip(&bs)
+        .zip(energy)
+        .map(|((q, b), energy)| {
+            let small = svd(b.as_ref(), SvdBackend::default())?.truncate(options.rank, false)?;
+            Ok(SvdFactors {
+                u: product(q.as_ref(), small.u.as_ref()),
+                energy,
+                ..small
+            })
+        })
+        .collect()
+}
+
+// Compresses a PNG into another PNG of the same color type, never holding more than a strip of
+// pixels: the factors come from `stream_factors`, and the output is reconstructed and written
+// a strip at a time. 16-bit and palette inputs are written as 8-bit.
+pub fn compress_png_streaming(
+    input: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    options: &StreamOptions,
+) -> Result<Vec<SvdFactors>, StreamError> {
+    let factors = stream_factors(&input, options)?;
+    let (_, layout) = open(input.as_ref())?;
+
+    let file = BufWriter::new(File::create(output)?);
+    write_strips(file, &factors, layout.color, options.strip_rows)?;
+    Ok(factors)
+}
+
+// Reconstructs `strip_rows` rows at a time and hands them straight to the encoder
+fn write_strips<W: Write>(
+    writer: W,
+    factors: &[SvdFactors],
+    color: png::ColorType,
+    strip_rows: usize,
+) -> Result<(), StreamError> {
+    let (height, width) = (factors[0].u.nrows(), factors[0].v.nrows());
+    let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
+    encoder.set_color(color);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    let mut stream = writer.stream_writer()?;
+
+    let strip_rows = strip_rows.max(1);
+    let mut buf = Vec::with_capacity(strip_rows * width * factors.len());
+
+    for row0 in (0..height).step_by(strip_rows) {
+        let rows = strip_rows.min(height - row0);
+        let strips: Vec<Mat<f32>> = factors
+            .iter()
+            .map(|f| {
+                let us = Mat::from_fn(rows, f.rank(), |i, j| f.u.read(row0 + i, j) * f.s[j]);
+                product(us.as_ref(), f.v.transpose())
+            })
+            .collect();
+
+        buf.clear();
+        for i in 0..rows {
+            for j in 0..width {
+                buf.extend(
+                    strips
+                        .iter()
+                        .map(|mat| mat.read(i, j).clamp(0.0, 255.0) as u8),
+                );
+            }
+        }
+        stream.write_all(&buf)?;
+    }
+
+    stream.finish()?;
+    Ok(())
+}
+
+// Saves the 8-bit PNG of one to four channels' factors (grey, grey and alpha, RGB or RGBA)
+// without materializing the reconstruction: each band of `strip_rows` rows is reconstructed
+// and fed to a streaming encoder, so memory beyond the factors is one band. Rank-0 factors
+// hold the channel mean (see `SvdFactors::truncate`), so they give it here as `compress` does.
+pub fn save_factors_png<W: Write>(
+    writer: W,
+    factors: &[SvdFactors],
+    strip_rows: usize,
+) -> Result<(), StreamError> {
+    let color = match factors.len() {
+        1 => png::ColorType::Grayscale,
+        2 => png::ColorType::GrayscaleAlpha,
+        3 => png::ColorType::Rgb,
+        4 => png::ColorType::Rgba,
+        channels => {
+            return Err(StreamError::Unsupported(format!(
+                "PNG holds 1 to 4 channels, got {}",
+                channels
+            )));
+        }
+    };
+    let (m, n) = (factors[0].u.nrows(), factors[0].v.nrows());
+    if let Some(f) = factors
+        .iter()
+        .find(|f| f.u.nrows() != m || f.v.nrows() != n)
+    {
+        return Err(SvdApproxError::ShapeMismatch((m, n), (f.u.nrows(), f.v.nrows())).into());
+    }
+
+    write_strips(writer, factors, color, strip_rows)
+}
@@ -0,0 +1,140 @@
+use crate::imagewrapper::{GreyImageWrapper, Planes};
+use crate::metrics::Metrics;
+use crate::notch::Axis;
+use crate::texture::sample;
+use faer_core::Mat;
+
+// Fixtures with known structure, and assertions comparing wrappers, for writing tests against
+// this crate. The fixtures are deterministic, so failures reproduce.
+
+// An image whose singular values are exactly `spectrum` (to f32 precision), so its rank is the
+// number of nonzero entries: random orthonormal factors carry the spectrum, unshifted and
+// unscaled. Its values are centered on 0 rather than in 0-255, and would be clipped if saved.
+pub fn low_rank(width: usize, height: usize, spectrum: &[f32], seed: u64) -> GreyImageWrapper {
+    assert!(
+        spectrum.len() <= width.min(height),
+        "a {}x{} image has at most {} singular values, got {}",
+        width,
+        height,
+        width.min(height),
+        spectrum.len()
+    );
+    GreyImageWrapper {
+        mat: sample(spectrum.to_vec(), height, width, 1, seed),
+        width,
+        height,
+    }
+}
+
+// Alternating black and white `cell x cell` squares, white first; rank 2 whenever both colors
+// appear in more than one row and column
+pub fn checkerboard(width: usize, height: usize, cell: usize) -> GreyImageWrapper {
+    let cell = cell.max(1);
+    GreyImageWrapper {
+        mat: Mat::from_fn(height, width, |i, j| {
+            if (i / cell + j / cell).is_multiple_of(2) {
+                255.0
+            } else {
+                0.0
+            }
+        }),
+        width,
+        height,
+    }
+}
+
+// A linear ramp from 0 to 255 along `axis` (across for `Axis::Horizontal`), constant the other
+// way; rank 1
+pub fn gradient(width: usize, height: usize, axis: Axis) -> GreyImageWrapper {
+    let ramp = |x: usize, len: usize| 255.0 * x as f32 / len.saturating_sub(1).max(1) as f32;
+    GreyImageWrapper {
+        mat: Mat::from_fn(height, width, |i, j| match axis {
+            Axis::Horizontal => ramp(j, width),
+            Axis::Vertical => ramp(i, height),
+        }),
+        width,
+        height,
+    }
+}
+
+#[track_caller]
+fn assert_same_shape<W: Planes>(actual: &W, expected: &W) {
+    let (a, b) = (actual.planes(), expected.planes());
+    assert_eq!(a.len(), b.len(), "images have different channel counts");
+    assert!(
+        a[0].nrows() == b[0].nrows() && a[0].ncols() == b[0].ncols(),
+        "images have different dimensions, {}x{} and {}x{}",
+        a[0].ncols(),
+        a[0].nrows(),
+        b[0].ncols(),
+        b[0].nrows(),
+    );
+}
+
+// Panics unless every sample of `actual` is within `tolerance` of `expected`, naming the worst
+// one
+#[track_caller]
+pub fn assert_images_close<W: Planes>(actual: &W, expected: &W, tolerance: f32) {
+    assert_same_shape(actual, expected);
+
+    let mut worst = (0.0f32, 0, 0, 0);
+    for (c, (a, b)) in actual.planes().iter().zip(expected.planes()).enumerate() {
+        for j in 0..a.ncols() {
+            for i in 0..a.nrows() {
+                let diff = (a.read(i, j) - b.read(i, j)).abs();
+                // NaN counts as the worst possible error
+                if diff > worst.0 || (diff.is_nan() && !worst.0.is_nan()) {
+                    worst = (diff, c, j, i);
+                }
+            }
+        }
+    }
+
+    let (diff, c, x, y) = worst;
+    assert!(
+        diff <= tolerance,
+        "images differ by {} (tolerance {}) in channel {} at ({}, {}): {} vs {}",
+        diff,
+        tolerance,
+        c,
+        x,
+        y,
+        actual.planes()[c].read(y, x),
+        expected.planes()[c].read(y, x),
+    );
+}
+
+#[track_caller]
+pub fn assert_psnr_at_least<W: Planes>(actual: &W, expected: &W, db: f32) {
+    assert_same_shape(actual, expected);
+    let psnr = actual.psnr(expected);
+    assert!(
+        psnr >= db,
+        "PSNR is {:.2} dB, expected at least {:.2} dB",
+        psnr,
+        db
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Compressible, Factorizable};
+
+    #[test]
+    fn low_rank_compresses_exactly_at_its_rank() {
+        let img = low_rank(40, 30, &[900.0, 300.0, 60.0, 8.0], 1);
+        assert_eq!(img.numeric_rank(1e-4).unwrap(), 4);
+        assert_images_close(&img.compress(4).unwrap(), &img, 1e-2);
+        assert_images_close(&img.compress(30).unwrap(), &img, 0.0);
+    }
+
+    #[test]
+    fn fixtures_have_their_stated_ranks() {
+        let board = checkerboard(24, 20, 3);
+        assert_images_close(&board.compress(2).unwrap(), &board, 0.05);
+        let ramp = gradient(24, 20, Axis::Vertical);
+        assert_images_close(&ramp.compress(1).unwrap(), &ramp, 0.05);
+        assert_psnr_at_least(&ramp.compress(1).unwrap(), &ramp, 60.0);
+    }
+}
@@ -0,0 +1,155 @@
+use crate::compress::{SvdApproxError, SvdBackend, SvdFactors, orthonormalize, svd};
+use crate::imagewrapper::{GreyImageWrapper, Planes};
+use crate::stats::channel_stats;
+use faer_core::Mat;
+use rayon::prelude::*;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Decay {
+    // `s_k = (k + 1)^-alpha`: the larger `alpha`, the simpler the texture
+    Power(f32),
+    // `s_k = exp(-k / scale)`: about `scale` components matter
+    Exponential(f32),
+    // Used as given, up to the rank
+    Custom(Vec<f32>),
+}
+
+impl Decay {
+    fn values(&self, rank: usize) -> Vec<f32> {
+        match self {
+            Decay::Power(alpha) => (0..rank).map(|k| ((k + 1) as f32).powf(-alpha)).collect(),
+            Decay::Exponential(scale) => (0..rank).map(|k| (-(k as f32) / scale).exp()).collect(),
+            Decay::Custom(values) => values.iter().copied().take(rank).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TextureOptions {
+    pub width: usize,
+    pub height: usize,
+    pub rank: usize,
+    pub decay: Decay,
+    // Width in pixels of the box filter smoothing the random vectors before they are made
+    // orthonormal: 1 gives white noise, larger values blotchier, more natural textures
+    pub smoothness: usize,
+    pub seed: u64,
+}
+
+impl TextureOptions {
+    pub fn new(width: usize, height: usize, rank: usize) -> Self {
+        TextureOptions {
+            width,
+            height,
+            rank,
+            decay: Decay::Power(1.0),
+            smoothness: 8,
+            seed: 0,
+        }
+    }
+}
+
+// xorshift64*, which is plenty for noise; the seed is mixed so nearby seeds differ
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    pub(crate) fn uniform(&mut self) -> f32 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        let bits = self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 40;
+        (bits as f32 + 0.5) / (1u64 << 24) as f32
+    }
+
+    // Box-Muller
+    fn gaussian(&mut self) -> f32 {
+        let (a, b) = (self.uniform(), self.uniform());
+        (-2.0 * a.ln()).sqrt() * (2.0 * std::f32::consts::PI * b).cos()
+    }
+}
+
+// `cols` random orthonormal columns of length `rows`, correlated over `smoothness` samples
+fn random_factor(rng: &mut Rng, rows: usize, cols: usize, smoothness: usize) -> Mat<f32> {
+    let smoothness = smoothness.max(1);
+    let noise = Mat::from_fn(rows + smoothness - 1, cols, |_, _| rng.gaussian());
+    let smooth = Mat::from_fn(rows, cols, |i, j| {
+        (i..i + smoothness).map(|r| noise.read(r, j)).sum::<f32>()
+    });
+    orthonormalize(smooth)
+}
+
+// Random factors with the given spectrum, reconstructed and stretched to fill 0-255
+pub(crate) fn sample(
+    s: Vec<f32>,
+    rows: usize,
+    cols: usize,
+    smoothness: usize,
+    seed: u64,
+) -> Mat<f32> {
+    let mut rng = Rng::new(seed);
+    let u = random_factor(&mut rng, rows, s.len(), smoothness);
+    let v = random_factor(&mut rng, cols, s.len(), smoothness);
+    let energy = s.iter().map(|x| x * x).sum();
+    SvdFactors { u, s, v, energy }.reconstruct()
+}
+
+fn stretch(mat: Mat<f32>, mean: f32, std: f32) -> Mat<f32> {
+    let stats = channel_stats(mat.as_ref());
+    let scale = if stats.variance > 0.0 {
+        std / stats.variance.sqrt()
+    } else {
+        0.0
+    };
+    Mat::from_fn(mat.nrows(), mat.ncols(), |i, j| {
+        mean + (mat.read(i, j) - stats.mean) * scale
+    })
+}
+
+// A grey texture whose complexity is set by its rank and singular-value decay: random
+// orthonormal factors carrying the spectrum, rescaled to a mean of 128 and a standard
+// deviation of 40. The same options always give the same texture.
+pub fn synthesize_texture(options: &TextureOptions) -> Result<GreyImageWrapper, SvdApproxError> {
+    let (m, n) = (options.height, options.width);
+    if options.rank > m.min(n) {
+        return Err(SvdApproxError::InvalidRank(m.min(n), options.rank));
+    }
+
+    let s = options.decay.values(options.rank);
+    let mat = sample(s, m, n, options.smoothness, options.seed);
+
+    Ok(GreyImageWrapper {
+        mat: stretch(mat, 128.0, 40.0),
+        width: n,
+        height: m,
+    })
+}
+
+pub trait Resynthesize: Planes {
+    // Keeps each channel's singular values but replaces its singular vectors with random ones
+    // (the same for every channel, so colors stay correlated), preserving the channel's mean
+    // and contrast: a texture as complex as the image, with none of its content
+    fn random_vectors(&self, smoothness: usize, seed: u64) -> Result<Self, SvdApproxError>
+    where
+        Self: Sized,
+    {
+        let mats = self
+            .planes()
+            .par_iter()
+            .map(|mat| {
+                let stats = channel_stats(mat.as_ref());
+                let s = svd(mat.as_ref(), SvdBackend::default())?.s;
+                let sample = sample(s, mat.nrows(), mat.ncols(), smoothness, seed);
+                Ok(stretch(sample, stats.mean, stats.variance.sqrt()))
+            })
+            .collect::<Result<Vec<_>, SvdApproxError>>()?;
+        Ok(self.rebuild(mats))
+    }
+}
+
+impl<W: Planes> Resynthesize for W {}
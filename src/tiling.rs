@@ -0,0 +1,169 @@
+use faer_core::{Mat, MatRef};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TileSplit {
+    // Boundaries every `size` pixels
+    #[default]
+    Grid,
+    // Each grid boundary moves up to a quarter of a tile to the row or column with the least
+    // gradient energy nearby, so seams fall in flat regions where they are hardest to see
+    ContentAware,
+}
+
+// Compresses each tile with its own SVD; tiles are rectangles of about `size x size`, shared by
+// all channels. Tiles narrower than the rank are kept whole.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tiling {
+    pub size: usize,
+    pub split: TileSplit,
+    // Pixels each tile extends past its boundaries. Neighboring tiles are cross-faded over the
+    // doubled band so that no hard edge shows between them; 0 butts them together.
+    pub overlap: usize,
+}
+
+impl Tiling {
+    pub fn new(size: usize) -> Self {
+        Tiling {
+            size,
+            split: TileSplit::default(),
+            overlap: 0,
+        }
+    }
+}
+
+// Gradient energy of each row (or, transposed, column) of every plane: the squared differences
+// to its neighbors across the boundary line
+fn line_energy(planes: &[Mat<f32>], rows: bool) -> Vec<f64> {
+    let len = planes
+        .first()
+        .map_or(0, |mat| if rows { mat.nrows() } else { mat.ncols() });
+    let mut energy = vec![0.0f64; len];
+
+    for mat in planes {
+        let mat = if rows { mat.as_ref() } else { mat.transpose() };
+        for j in 0..mat.ncols() {
+            for i in 1..mat.nrows() {
+                let diff = (mat.read(i, j) - mat.read(i - 1, j)) as f64;
+                energy[i - 1] += diff * diff;
+                energy[i] += diff * diff;
+            }
+        }
+    }
+
+    energy
+}
+
+// Start of every tile along a side of length `len`, followed by `len`
+fn cuts(len: usize, size: usize, energy: Option<&[f64]>) -> Vec<usize> {
+    let size = size.max(1);
+    let mut cuts = vec![0];
+    let reach = size / 4;
+
+    for nominal in (size..len).step_by(size) {
+        let cut = match energy {
+            // A cut at `c` separates lines `c - 1` and `c`, which are what a seam would disturb
+            Some(energy) => {
+                let lo = nominal
+                    .saturating_sub(reach)
+                    .max(cuts[cuts.len() - 1] + size / 2)
+                    .max(1);
+                let hi = (nominal + reach).min(len - 1);
+                (lo..=hi)
+                    .min_by(|&a, &b| {
+                        let cost = |c: usize| energy[c - 1] + energy[c];
+                        cost(a).total_cmp(&cost(b))
+                    })
+                    .unwrap_or(nominal)
+            }
+            None => nominal,
+        };
+        if cut < len {
+            cuts.push(cut);
+        }
+    }
+
+    cuts.push(len);
+    cuts.dedup();
+    cuts
+}
+
+impl Tiling {
+    // Row and column cuts for planes sharing one shape
+    pub(crate) fn cuts(&self, planes: &[Mat<f32>]) -> (Vec<usize>, Vec<usize>) {
+        let (m, n) = planes
+            .first()
+            .map_or((0, 0), |mat| (mat.nrows(), mat.ncols()));
+        match self.split {
+            TileSplit::Grid => (cuts(m, self.size, None), cuts(n, self.size, None)),
+            TileSplit::ContentAware => (
+                cuts(m, self.size, Some(&line_energy(planes, true))),
+                cuts(n, self.size, Some(&line_energy(planes, false))),
+            ),
+        }
+    }
+}
+
+// Weight of line `x` of a tile spanning `lo..hi` of `len`: a linear ramp over the `2 * overlap`
+// lines it shares with each neighbor, so the weights of two overlapping tiles sum to one
+fn feather(x: usize, lo: usize, hi: usize, len: usize, overlap: usize) -> f32 {
+    let width = (2 * overlap) as f32;
+    let rise = if lo == 0 {
+        1.0
+    } else {
+        ((x - lo) as f32 + 0.5) / width
+    };
+    let fall = if hi == len {
+        1.0
+    } else {
+        ((hi - x) as f32 - 0.5) / width
+    };
+    rise.min(fall).min(1.0)
+}
+
+// Applies `f` to every tile of `mat` delimited by `rows` and `cols`, each grown by `overlap` on
+// all sides, and assembles the results, feathering where they overlap
+pub(crate) fn map_tiles<E>(
+    mat: MatRef<f32>,
+    rows: &[usize],
+    cols: &[usize],
+    overlap: usize,
+    mut f: impl FnMut(MatRef<f32>) -> Result<Mat<f32>, E>,
+) -> Result<Mat<f32>, E> {
+    let (m, n) = (mat.nrows(), mat.ncols());
+    let mut sum = Mat::zeros(m, n);
+    let mut weights = Mat::<f32>::zeros(m, n);
+
+    for col in cols.windows(2) {
+        for row in rows.windows(2) {
+            let (i0, i1) = (row[0].saturating_sub(overlap), (row[1] + overlap).min(m));
+            let (j0, j1) = (col[0].saturating_sub(overlap), (col[1] + overlap).min(n));
+            let tile = f(mat.submatrix(i0, j0, i1 - i0, j1 - j0))?;
+
+            if overlap == 0 {
+                sum.as_mut()
+                    .submatrix_mut(i0, j0, i1 - i0, j1 - j0)
+                    .copy_from(tile.as_ref());
+                continue;
+            }
+            for j in j0..j1 {
+                let wj = feather(j, j0, j1, n, overlap);
+                for i in i0..i1 {
+                    let w = wj * feather(i, i0, i1, m, overlap);
+                    sum.write(i, j, sum.read(i, j) + w * tile.read(i - i0, j - j0));
+                    weights.write(i, j, weights.read(i, j) + w);
+                }
+            }
+        }
+    }
+
+    if overlap > 0 {
+        for j in 0..n {
+            for i in 0..m {
+                sum.write(i, j, sum.read(i, j) / weights.read(i, j));
+            }
+        }
+    }
+    Ok(sum)
+}
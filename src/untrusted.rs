@@ -0,0 +1,152 @@
+use crate::diagnostics::check_downcast;
+use crate::dynwrapper::DynWrapper;
+use image::{ImageError, ImageReader, Limits, guess_format};
+use std::io::{self, Cursor, Read};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum UntrustedError {
+    Io(io::Error),
+    Image(ImageError),
+    // The input breaks one of the limits; the message says which
+    TooLarge(String),
+    TimedOut(Duration),
+    // The decoder panicked, with this message
+    Panicked(String),
+    Unsupported(String),
+}
+
+impl std::fmt::Display for UntrustedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            UntrustedError::Io(err) => write!(f, "I/O error: {}", err),
+            UntrustedError::Image(err) => write!(f, "Image error: {}", err),
+            UntrustedError::TooLarge(msg) => write!(f, "Input too large: {}.", msg),
+            UntrustedError::TimedOut(limit) => {
+                write!(f, "Decoding took longer than {:.2}s.", limit.as_secs_f64())
+            }
+            UntrustedError::Panicked(msg) => write!(f, "Decoder panicked: {}", msg),
+            UntrustedError::Unsupported(msg) => write!(f, "Unsupported input: {}.", msg),
+        }
+    }
+}
+
+impl From<io::Error> for UntrustedError {
+    fn from(err: io::Error) -> Self {
+        UntrustedError::Io(err)
+    }
+}
+
+impl From<ImageError> for UntrustedError {
+    fn from(err: ImageError) -> Self {
+        UntrustedError::Image(err)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UntrustedLimits {
+    // Encoded bytes read before giving up
+    pub max_input_bytes: u64,
+    pub max_width: u32,
+    pub max_height: u32,
+    // Bytes the decoder may allocate, and separately the float planes of the wrapper may take
+    pub max_alloc: u64,
+    // Wall-clock limit on decoding
+    pub timeout: Duration,
+}
+
+impl Default for UntrustedLimits {
+    fn default() -> Self {
+        UntrustedLimits {
+            max_input_bytes: 64 << 20,
+            max_width: 16384,
+            max_height: 16384,
+            max_alloc: 512 << 20,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|msg| msg.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+fn decode(data: Vec<u8>, limits: &UntrustedLimits) -> Result<DynWrapper, UntrustedError> {
+    let format = guess_format(&data)?;
+    let mut decoder_limits = Limits::default();
+    decoder_limits.max_image_width = Some(limits.max_width);
+    decoder_limits.max_image_height = Some(limits.max_height);
+    decoder_limits.max_alloc = Some(limits.max_alloc);
+
+    // The header alone says how large the planes will be, before anything is allocated for them
+    let mut reader = ImageReader::with_format(Cursor::new(&data), format);
+    reader.limits(decoder_limits.clone());
+    let (width, height) = reader.into_dimensions()?;
+    if width > limits.max_width || height > limits.max_height {
+        return Err(UntrustedError::TooLarge(format!(
+            "{}x{} exceeds {}x{}",
+            width, height, limits.max_width, limits.max_height
+        )));
+    }
+    // Up to four f32 planes
+    let planes = width as u64 * height as u64 * 4 * size_of::<f32>() as u64;
+    if planes > limits.max_alloc {
+        return Err(UntrustedError::TooLarge(format!(
+            "{} bytes of planes exceeds {}",
+            planes, limits.max_alloc
+        )));
+    }
+
+    let mut reader = ImageReader::with_format(Cursor::new(&data), format);
+    reader.limits(decoder_limits);
+    let img = reader.decode()?;
+    check_downcast(img.color()).map_err(UntrustedError::Unsupported)?;
+    Ok(DynWrapper::from_dynamic(img))
+}
+
+// Decodes an image from an untrusted source, such as an upload to a web service, without
+// panicking and within `limits`: oversized inputs are refused from their header, before any
+// pixels are decoded, and a panic in a decoder is returned as an error. Decoding runs on its
+// own thread so that it can be abandoned at the timeout; the thread still runs to completion
+// in the background, bounded by the allocation limit.
+pub fn load_untrusted<R: Read>(
+    reader: R,
+    limits: &UntrustedLimits,
+) -> Result<DynWrapper, UntrustedError> {
+    let mut data = Vec::new();
+    reader
+        .take(limits.max_input_bytes.saturating_add(1))
+        .read_to_end(&mut data)?;
+    if data.len() as u64 > limits.max_input_bytes {
+        return Err(UntrustedError::TooLarge(format!(
+            "more than {} bytes of input",
+            limits.max_input_bytes
+        )));
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    let thread_limits = *limits;
+    std::thread::Builder::new()
+        .name("load_untrusted".to_string())
+        .spawn(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| decode(data, &thread_limits)));
+            // The receiver is gone if the caller already timed out
+            let _ = sender.send(result);
+        })?;
+
+    match receiver.recv_timeout(limits.timeout) {
+        Ok(Ok(result)) => result,
+        Ok(Err(payload)) => Err(UntrustedError::Panicked(panic_message(payload.as_ref()))),
+        Err(mpsc::RecvTimeoutError::Timeout) => Err(UntrustedError::TimedOut(limits.timeout)),
+        Err(mpsc::RecvTimeoutError::Disconnected) => Err(UntrustedError::Panicked(
+            "decoding thread exited without a result".to_string(),
+        )),
+    }
+}
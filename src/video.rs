@@ -0,0 +1,329 @@
+use crate::compress::{CompressOptions, Compressible, SvdApproxError};
+use crate::imagewrapper::RgbImageWrapper;
+use image::RgbImage;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+// Frames go through `ffmpeg` as raw RGB24 over a pipe, so any container and codec it knows
+// works without linking to libav; both tools must be on the `PATH`
+const FFMPEG: &str = "ffmpeg";
+const FFPROBE: &str = "ffprobe";
+
+#[derive(Debug)]
+pub enum VideoError {
+    Io(io::Error),
+    Svd(SvdApproxError),
+    // `ffmpeg` or `ffprobe` failed, with what it printed to stderr
+    Ffmpeg(String),
+    // A frame doesn't match the size of the video
+    FrameSize((usize, usize), (usize, usize)),
+}
+
+impl std::fmt::Display for VideoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            VideoError::Io(err) => write!(f, "I/O error: {}", err),
+            VideoError::Svd(err) => write!(f, "SVD error: {}", err),
+            VideoError::Ffmpeg(msg) => write!(f, "ffmpeg failed: {}", msg),
+            VideoError::FrameSize((w1, h1), (w2, h2)) => {
+                write!(f, "Frames must be {}x{}, got {}x{}.", w1, h1, w2, h2)
+            }
+        }
+    }
+}
+
+impl From<io::Error> for VideoError {
+    fn from(err: io::Error) -> Self {
+        VideoError::Io(err)
+    }
+}
+
+impl From<SvdApproxError> for VideoError {
+    fn from(err: SvdApproxError) -> Self {
+        VideoError::Svd(err)
+    }
+}
+
+// Frame rate as the exact fraction containers store, e.g. 30000/1001 for NTSC
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameRate {
+    pub num: u32,
+    pub den: u32,
+}
+
+impl FrameRate {
+    pub fn new(num: u32, den: u32) -> Self {
+        FrameRate { num, den }
+    }
+
+    pub fn fps(self) -> f64 {
+        self.num as f64 / self.den.max(1) as f64
+    }
+}
+
+impl std::fmt::Display for FrameRate {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}/{}", self.num, self.den)
+    }
+}
+
+fn ffmpeg_error(output: &[u8]) -> VideoError {
+    let msg = String::from_utf8_lossy(output);
+    VideoError::Ffmpeg(msg.trim().lines().last().unwrap_or("no output").to_string())
+}
+
+// Spawn failures are almost always a missing tool, which the bare I/O error doesn't say
+fn spawn_error(tool: &str) -> impl FnOnce(io::Error) -> VideoError + '_ {
+    move |err| {
+        if err.kind() == io::ErrorKind::NotFound {
+            VideoError::Ffmpeg(format!("`{}` not found on the PATH", tool))
+        } else {
+            VideoError::Io(err)
+        }
+    }
+}
+
+// Waits for `child`, turning a nonzero exit into an error with the last line of its stderr
+fn wait(child: &mut Child) -> Result<(), VideoError> {
+    let mut stderr = Vec::new();
+    if let Some(mut pipe) = child.stderr.take() {
+        pipe.read_to_end(&mut stderr)?;
+    }
+    if child.wait()?.success() {
+        Ok(())
+    } else {
+        Err(ffmpeg_error(&stderr))
+    }
+}
+
+// Width, height and frame rate of the first video stream
+fn probe(path: &Path) -> Result<(usize, usize, FrameRate), VideoError> {
+    let output = Command::new(FFPROBE)
+        .args(["-v", "error", "-select_streams", "v:0"])
+        .args(["-show_entries", "stream=width,height,r_frame_rate"])
+        .args(["-of", "csv=p=0"])
+        .arg(path)
+        .output()
+        .map_err(spawn_error(FFPROBE))?;
+    if !output.status.success() {
+        return Err(ffmpeg_error(&output.stderr));
+    }
+
+    let text = String::from_utf8_lossy(&output.stdout);
+    let fields: Vec<&str> = text.trim().split(',').collect();
+    let unexpected = || VideoError::Ffmpeg(format!("unexpected ffprobe output `{}`", text.trim()));
+    let [width, height, rate] = fields[..] else {
+        return Err(unexpected());
+    };
+    let (num, den) = rate.split_once('/').unwrap_or((rate, "1"));
+    let number = |s: &str| s.trim().parse().map_err(|_| unexpected());
+
+    Ok((
+        number(width)?,
+        number(height)?,
+        FrameRate::new(number(num)? as u32, number(den)? as u32),
+    ))
+}
+
+// Decodes a video file frame by frame; frames are read from `ffmpeg` as they are requested, so
+// only one is held at a time. The process is killed if the reader is dropped early.
+pub struct VideoReader {
+    child: Child,
+    stdout: BufReader<ChildStdout>,
+    width: usize,
+    height: usize,
+    frame_rate: FrameRate,
+    done: bool,
+}
+
+impl VideoReader {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, VideoError> {
+        let path = path.as_ref();
+        let (width, height, frame_rate) = probe(path)?;
+        let mut child = Command::new(FFMPEG)
+            .args(["-v", "error", "-nostdin", "-i"])
+            .arg(path)
+            .args(["-map", "0:v:0", "-f", "rawvideo", "-pix_fmt", "rgb24", "-"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(spawn_error(FFMPEG))?;
+        let stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+
+        Ok(VideoReader {
+            child,
+            stdout,
+            width,
+            height,
+            frame_rate,
+            done: false,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn frame_rate(&self) -> FrameRate {
+        self.frame_rate
+    }
+
+    fn next_frame(&mut self) -> Result<Option<RgbImageWrapper>, VideoError> {
+        let mut buf = vec![0u8; self.width * self.height * 3];
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.stdout.read(&mut buf[filled..])? {
+                0 => break,
+                read => filled += read,
+            }
+        }
+
+        if filled < buf.len() {
+            // A partial frame means the stream was cut short, which `wait` reports
+            self.done = true;
+            wait(&mut self.child)?;
+            return if filled == 0 {
+                Ok(None)
+            } else {
+                Err(VideoError::Ffmpeg(format!(
+                    "stream ended {} bytes into a frame",
+                    filled
+                )))
+            };
+        }
+
+        let img = RgbImage::from_raw(self.width as u32, self.height as u32, buf)
+            .expect("buffer holds exactly one frame");
+        Ok(Some(RgbImageWrapper::from_image(&img)))
+    }
+}
+
+impl Iterator for VideoReader {
+    type Item = Result<RgbImageWrapper, VideoError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let frame = self.next_frame();
+        if frame.is_err() {
+            self.done = true;
+        }
+        frame.transpose()
+    }
+}
+
+impl Drop for VideoReader {
+    fn drop(&mut self) {
+        if !self.done {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+// Encodes frames into a video file, with the codec `ffmpeg` picks for the extension of `path`
+// (H.264 for .mp4 where available). Call `finish` to flush the file and see whether encoding
+// succeeded; dropping the writer without it closes the pipe and waits without reporting.
+pub struct VideoWriter {
+    child: Child,
+    stdin: Option<BufWriter<ChildStdin>>,
+    width: usize,
+    height: usize,
+}
+
+impl VideoWriter {
+    pub fn create(
+        path: impl AsRef<Path>,
+        width: usize,
+        height: usize,
+        frame_rate: FrameRate,
+    ) -> Result<Self, VideoError> {
+        let mut child = Command::new(FFMPEG)
+            .args(["-v", "error", "-y", "-f", "rawvideo", "-pix_fmt", "rgb24"])
+            .args(["-s", &format!("{}x{}", width, height)])
+            .args(["-r", &frame_rate.to_string(), "-i", "-"])
+            // Most players only handle 4:2:0, which needs even dimensions for H.264
+            .args(["-pix_fmt", "yuv420p"])
+            .arg(path.as_ref())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(spawn_error(FFMPEG))?;
+        let stdin = BufWriter::new(child.stdin.take().expect("stdin is piped"));
+
+        Ok(VideoWriter {
+            child,
+            stdin: Some(stdin),
+            width,
+            height,
+        })
+    }
+
+    pub fn write_frame(&mut self, frame: &RgbImageWrapper) -> Result<(), VideoError> {
+        if (frame.width, frame.height) != (self.width, self.height) {
+            return Err(VideoError::FrameSize(
+                (self.width, self.height),
+                (frame.width, frame.height),
+            ));
+        }
+        // Closed once `ffmpeg` has exited early, which the previous call reported
+        let Some(stdin) = self.stdin.as_mut() else {
+            return Err(VideoError::Ffmpeg(
+                "the encoder has already exited".to_string(),
+            ));
+        };
+        match stdin.write_all(frame.to_image().as_raw()) {
+            // `ffmpeg` exited early, and its stderr says why
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => {
+                self.stdin = None;
+                wait(&mut self.child)?;
+                Err(err.into())
+            }
+            result => Ok(result?),
+        }
+    }
+
+    pub fn finish(mut self) -> Result<(), VideoError> {
+        if let Some(mut stdin) = self.stdin.take() {
+            stdin.flush()?;
+        }
+        wait(&mut self.child)
+    }
+}
+
+impl Drop for VideoWriter {
+    fn drop(&mut self) {
+        if self.stdin.take().is_some() {
+            let _ = self.child.wait();
+        }
+    }
+}
+
+// Compresses every frame of `input` with `options` and encodes the results into `output` at
+// the same frame rate, returning the number of frames
+pub fn compress_video(
+    input: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    options: &CompressOptions,
+) -> Result<usize, VideoError> {
+    let reader = VideoReader::open(input)?;
+    let mut writer =
+        VideoWriter::create(output, reader.width(), reader.height(), reader.frame_rate())?;
+
+    let mut frames = 0;
+    for frame in reader {
+        writer.write_frame(&frame?.compress_with(options)?)?;
+        frames += 1;
+    }
+    writer.finish()?;
+    Ok(frames)
+}